@@ -0,0 +1,4587 @@
+use eframe::egui::{
+    self,
+    output::OpenUrl,
+    text::{CCursor, CCursorRange},
+    text_edit::TextEditState,
+    Event, FontDefinitions, Key, Modifiers, RichText, ScrollArea, Sense, Visuals,
+};
+use gmi::{
+    gemtext::{self, GemtextNode},
+    url::Url,
+};
+use serde_json::Value;
+
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{Receiver, TryRecvError},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    audio::{self, Player},
+    bookmark_sync::{self, Direction, Outcome},
+    bookmarks::{Bookmarks, TOOLBAR_FOLDER},
+    cache::{Cache, CacheSettings, CachingFetcher},
+    config::{Config, MimeAction, Theme},
+    dns::{self, IpVersion},
+    downloads::{self, Downloads},
+    favicon::Favicons,
+    feeds::{self, Subscriptions},
+    fetch::{Fetcher, GeminiFetcher, LoadError, Loaded, Page},
+    file::{self, FileFetcher},
+    fonts::{self, FontSettings},
+    gestures::{EdgeSwipe, Swipe},
+    history::History,
+    i18n::{self, tr, trf},
+    input::{self, InputPrompt, Inputs},
+    ipc::{self, Command},
+    markdown,
+    monitor::{self, Monitor},
+    nav,
+    plugin::{PluginFetcher, Plugins},
+    prefetch::{Prefetcher, Preview},
+    request_log::{LoggingFetcher, RequestLog},
+    screenshot::Screenshot,
+    session::{Session, Sessions},
+    settings,
+    shortcuts::{self, Action},
+    tab::{ClosedTab, Tab},
+    toolbar::ToolbarItem,
+    transport::{self, Timing, Trust},
+    tray::{self, Tray},
+    view::{GemtextTheme, GemtextView},
+    watch::{self, Watcher},
+    welcome,
+    window::Geometry,
+};
+
+const MIN_ZOOM: f32 = 0.5;
+const MAX_ZOOM: f32 = 3.0;
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRIES: u32 = 5;
+const MAX_CLOSED_TABS: usize = 20;
+const MAX_SUGGESTIONS: usize = 8;
+const MIN_UI_SCALE: f32 = 0.5;
+const MAX_UI_SCALE: f32 = 4.0;
+/// How far the arrow keys scroll.
+const SCROLL_STEP: f32 = 40.0;
+/// How often the open tabs are saved in case gbrowse crashes.
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+/// How many of the latest requests are shown on `about:debug`.
+const DEBUG_LOG_LINES: usize = 20;
+/// The choices in the auto refresh menu, in minutes.
+const REFRESH_INTERVALS: &[u64] = &[1, 5, 15, 30, 60];
+/// How wide the page is in reading mode, unless the style says otherwise.
+const READING_WIDTH: f32 = 700.0;
+const OUTLINE_ID: &str = "outline";
+/// Opening more links than this at once is probably a mistake.
+const MAX_OPEN_ALL: usize = 50;
+/// How many pages are listed in the history panel.
+const MAX_HISTORY_PANEL: usize = 200;
+/// How often watched pages are checked for changes, in minutes.
+const DEFAULT_WATCH_INTERVAL: u64 = 60;
+/// How many matches are listed for each tab when searching every tab.
+const MAX_TAB_MATCHES: usize = 10;
+/// Where gemtext piped into `gbrowse -` is shown.
+pub const STDIN_URL: &str = "about:stdin";
+
+pub struct Gbrowse {
+    fetcher: Arc<dyn Fetcher>,
+    request_log: RequestLog,
+    sessions: Sessions,
+    /// The window's size and position as of the last frame, saved on exit.
+    window: Geometry,
+    /// Fullscreen with only the page showing, toggled with F11.
+    reading_mode: bool,
+    /// Whether the window's been made fullscreen for reading mode.
+    fullscreen: bool,
+    show_sessions: bool,
+    /// The keyboard shortcut cheat sheet.
+    show_shortcuts: bool,
+    show_tab_search: bool,
+    show_watched: bool,
+    show_history: bool,
+    show_bookmarks_panel: bool,
+    show_links: bool,
+    show_outline: bool,
+    /// The node picked in the outline with the arrow keys.
+    outline_selected: Option<usize>,
+    /// Where each heading of the page was drawn last frame, as its node's
+    /// index and how far down the page it is.
+    headings: Vec<(usize, f32)>,
+    link_filter: String,
+    /// The url of the bookmark being dragged in the bookmarks sidebar.
+    dragging_bookmark: Option<String>,
+    history_filter: String,
+    monitor: Monitor,
+    subscriptions: Subscriptions,
+    tab_search: String,
+    /// The tab id and node of the match picked in the tab search, ringed
+    /// until the page is scrolled or clicked.
+    found: Option<(u64, usize)>,
+    /// What about:config is filtered by.
+    config_filter: String,
+    /// Settings being typed into on about:config, until they lose focus.
+    config_edits: HashMap<String, String>,
+    /// Why the last change on about:config didn't work.
+    config_error: Option<String>,
+    /// What's typed into the name field of the sessions window.
+    session_name: String,
+    /// Tabs left behind by a crash, until they're restored or dismissed.
+    crashed_session: Option<Session>,
+    /// What was last autosaved, and when to check again.
+    autosaved: Session,
+    autosave_at: Instant,
+    plugins: Plugins,
+    cache: Cache,
+    prefetcher: Prefetcher,
+    tabs: Vec<Tab>,
+    /// Index of the tab being shown.
+    current: usize,
+    /// Recently closed tabs, most recent last.
+    closed: Vec<ClosedTab>,
+    /// Tab ids, most recently used first.
+    recent: Vec<u64>,
+    /// Where the selection is in `recent` while the tab switcher is open.
+    switcher: Option<usize>,
+    favicons: Favicons,
+    bookmarks: Bookmarks,
+    history: History,
+    downloads: Downloads,
+    /// Keeps playing while other pages are browsed.
+    player: Option<Player>,
+    /// What's typed into the download folder setting, saved when it loses focus.
+    download_dir: String,
+    show_bookmarks: bool,
+    /// The bookmark sync that's running.
+    syncing: Option<Receiver<Result<Outcome, String>>>,
+    /// How the last bookmark sync went.
+    sync_status: Option<String>,
+    /// Both sides changed, so the next sync has to pick one.
+    sync_conflict: bool,
+    bookmark_filter: String,
+    /// Select everything in the url bar until the mouse is let go, so the
+    /// click that focused it doesn't place the cursor.
+    select_url: bool,
+    swipe: EdgeSwipe,
+    /// Set while a pinch or ctrl+scroll is changing the zoom.
+    zooming: bool,
+    /// Where the page was drawn last frame, to zoom around a point on it.
+    page_rect: Option<egui::Rect>,
+    /// How tall the page was last frame.
+    page_height: f32,
+    /// What's been typed to find a link, after pressing '.
+    link_find: Option<String>,
+    /// What the native window title was last set to.
+    window_title: String,
+    /// Multiplied with the system's pixels per point.
+    ui_scale: f32,
+    fonts: FontDefinitions,
+    /// The settings `fonts` was loaded from, to notice when they change.
+    font_settings: FontSettings,
+    /// The language setting the interface was last translated with.
+    language: Option<String>,
+    /// The resolver setting hosts are being looked up with.
+    resolver: Option<String>,
+    /// The IP version setting connections are being made with.
+    ip_version: IpVersion,
+    /// The identity each host is being connected to with.
+    identities: HashMap<String, String>,
+    /// The theme the interface has been set to, once it has been.
+    ui_theme: Option<Theme>,
+    theme: GemtextTheme,
+    config: Config,
+    show_page_info: bool,
+    /// Hosts that answered 44 SLOW DOWN, and when we may retry them.
+    slow_down: HashMap<String, Instant>,
+    inputs: Inputs,
+    /// The directory `gbrowse serve` is serving.
+    served: Option<Watcher>,
+    /// The directory of the `file://` page being looked at.
+    file_watcher: Option<Watcher>,
+    /// Where scripts send commands to.
+    remote: Option<ipc::Server>,
+    /// The icon in the system tray, if it's turned on.
+    tray: Option<Tray>,
+    /// Gemtext piped in, for `about:stdin`.
+    piped: Option<String>,
+}
+
+impl Gbrowse {
+    pub fn new(plugins: Plugins, start_page: String) -> Self {
+        Self::with_fetcher(Arc::new(GeminiFetcher), plugins, start_page)
+    }
+
+    pub fn with_fetcher(fetcher: Arc<dyn Fetcher>, plugins: Plugins, start_page: String) -> Self {
+        let first_run = Config::first_run();
+        let request_log = RequestLog::default();
+        let fetcher = Arc::new(LoggingFetcher::new(fetcher, request_log.clone()));
+
+        let mut app = Self {
+            fetcher: Arc::new(PluginFetcher::new(fetcher, plugins.clone())),
+            request_log,
+            sessions: Sessions::load(),
+            window: Geometry::load(),
+            reading_mode: false,
+            fullscreen: false,
+            show_sessions: false,
+            show_shortcuts: false,
+            show_tab_search: false,
+            show_watched: false,
+            show_history: false,
+            show_bookmarks_panel: false,
+            show_links: false,
+            show_outline: false,
+            outline_selected: None,
+            headings: vec![],
+            link_filter: String::new(),
+            dragging_bookmark: None,
+            history_filter: String::new(),
+            monitor: Monitor::load(),
+            subscriptions: Subscriptions::load(),
+            tab_search: String::new(),
+            found: None,
+            config_filter: String::new(),
+            config_edits: HashMap::new(),
+            config_error: None,
+            session_name: String::new(),
+            crashed_session: Session::load_autosaved(),
+            autosaved: Session::default(),
+            autosave_at: Instant::now() + AUTOSAVE_INTERVAL,
+            plugins,
+            cache: Cache::new(&CacheSettings::default()),
+            prefetcher: Prefetcher::new(),
+            tabs: vec![],
+            current: 0,
+            closed: vec![],
+            recent: vec![],
+            switcher: None,
+            favicons: Favicons::new(),
+            bookmarks: Bookmarks::load(),
+            history: History::load(),
+            downloads: Downloads::new(),
+            player: None,
+            download_dir: String::new(),
+            show_bookmarks: false,
+            syncing: None,
+            sync_status: None,
+            sync_conflict: false,
+            bookmark_filter: String::new(),
+            select_url: false,
+            swipe: EdgeSwipe::default(),
+            zooming: false,
+            page_rect: None,
+            page_height: 0.0,
+            link_find: None,
+            window_title: String::new(),
+            ui_scale: 1.0,
+            fonts: FontDefinitions::default(),
+            font_settings: FontSettings::default(),
+            language: None,
+            resolver: None,
+            ip_version: IpVersion::Any,
+            identities: HashMap::new(),
+            ui_theme: None,
+            theme: GemtextTheme::default(),
+            config: Config::load(),
+            show_page_info: false,
+            slow_down: HashMap::new(),
+            inputs: Inputs::load(),
+            served: None,
+            file_watcher: None,
+            remote: None,
+            tray: None,
+            piped: None,
+        };
+
+        app.cache.apply(&app.config.cache);
+        app.language = app.config.language.clone();
+        i18n::set_language(app.language.as_deref());
+
+        app.resolver = app.config.resolver.clone();
+        dns::set_resolver(app.resolver.as_deref());
+
+        app.ip_version = app.config.ip_version;
+        dns::set_ip_version(app.ip_version);
+
+        app.identities = app.config.identities();
+        transport::set_identities(&app.identities);
+
+        if let Some(scale) = app.config.ui_scale {
+            app.set_ui_scale(scale);
+        }
+
+        for url in app.config.pinned_tabs.clone() {
+            app.new_tab(&url);
+            app.tab_mut().pinned = true;
+        }
+
+        app.new_tab(&start_page);
+
+        if first_run {
+            app.new_tab(welcome::URL);
+            app.config.create();
+        }
+
+        app
+    }
+
+    /// Reload pages from a directory being served at `url` when they change.
+    pub fn watch(&mut self, dir: &Path, url: &str) {
+        self.served = Some(Watcher::new(dir, url));
+    }
+
+    /// Start taking commands from scripts, see [`ipc`].
+    pub fn listen(&mut self, ctx: &egui::Context) {
+        match ipc::Server::start(ctx.clone()) {
+            Ok(server) => self.remote = Some(server),
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+
+    /// Put an icon in the system tray, see [`tray`].
+    pub fn show_tray(&mut self, ctx: &egui::Context) {
+        match Tray::start(ctx.clone()) {
+            Ok(tray) => self.tray = Some(tray),
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+
+    /// Show gemtext piped into gbrowse on `about:stdin`.
+    pub fn set_piped(&mut self, text: String) {
+        for tab in &mut self.tabs {
+            if tab.url == STDIN_URL {
+                tab.page = Some(piped_page(&text));
+            }
+        }
+
+        self.piped = Some(text);
+    }
+
+    pub fn set_ui_scale(&mut self, scale: f32) {
+        self.ui_scale = scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE);
+    }
+
+    /// Load the fonts from the config.
+    pub fn load_fonts(&mut self, ctx: &egui::Context) {
+        self.font_settings = self.config.fonts.clone();
+        self.fonts = fonts::definitions(&self.font_settings);
+        ctx.set_fonts(self.fonts.clone());
+    }
+
+    fn tab(&self) -> &Tab {
+        &self.tabs[self.current]
+    }
+
+    fn tab_mut(&mut self) -> &mut Tab {
+        &mut self.tabs[self.current]
+    }
+
+    /// Open a new tab after the current one and switch to it.
+    pub fn new_tab(&mut self, url: &str) {
+        let index = (self.current + 1)
+            .max(self.pinned_count())
+            .min(self.tabs.len());
+
+        self.tabs.insert(index, Tab::new(url.to_string()));
+        self.current = index;
+        self.change_site(url, false);
+    }
+
+    /// Close a tab, keeping at least one open. Pinned tabs have to be
+    /// unpinned first.
+    pub fn close_tab(&mut self, index: usize) {
+        if self.tabs.len() <= 1 || self.tabs[index].pinned {
+            return;
+        }
+
+        let tab = self.tabs.remove(index);
+
+        if self.closed.len() == MAX_CLOSED_TABS {
+            self.closed.remove(0);
+        }
+
+        self.closed.push(ClosedTab {
+            url: tab.sites.last().cloned().unwrap_or(tab.url),
+            scroll: tab.scroll,
+        });
+
+        if self.current > index || self.current == self.tabs.len() {
+            self.current -= 1;
+        }
+    }
+
+    /// Open the most recently closed tab again, where it was scrolled to.
+    pub fn reopen_closed_tab(&mut self) {
+        if let Some(closed) = self.closed.pop() {
+            self.new_tab(&closed.url);
+            self.tab_mut().scroll_to = Some(closed.scroll);
+        }
+    }
+
+    /// Save the open tabs every so often. Nothing's saved until a crashed
+    /// session has been restored or dismissed, so it isn't overwritten.
+    fn autosave(&mut self, ctx: &egui::Context) {
+        if self.crashed_session.is_some() {
+            return;
+        }
+
+        let now = Instant::now();
+
+        if now >= self.autosave_at {
+            let session = Session::from_tabs(&self.tabs, self.current);
+
+            if session != self.autosaved {
+                session.autosave();
+                self.autosaved = session;
+            }
+
+            self.autosave_at = now + AUTOSAVE_INTERVAL;
+        }
+
+        ctx.request_repaint_after(self.autosave_at - now);
+    }
+
+    /// Open the tabs from the session that crashed.
+    fn restore_session(&mut self, session: Session) {
+        let first = self.tabs.len();
+
+        for saved in &session.tabs {
+            let Some(url) = saved.sites.last() else {
+                continue;
+            };
+
+            self.tabs.push(Tab::new(url.clone()));
+            self.current = self.tabs.len() - 1;
+            self.change_site(url, false);
+
+            let tab = self.tab_mut();
+            tab.sites = saved.sites.clone();
+            tab.forward = saved.forward.clone();
+            tab.scroll_to = Some(saved.scroll);
+        }
+
+        if first + session.current < self.tabs.len() {
+            self.current = first + session.current;
+        }
+    }
+
+    /// Open a saved session, either in place of the open tabs (except the
+    /// pinned ones) or next to them.
+    fn open_session(&mut self, session: Session, replace: bool) {
+        let old: Vec<u64> = if replace {
+            self.tabs
+                .iter()
+                .filter(|tab| !tab.pinned)
+                .map(|tab| tab.id)
+                .collect()
+        } else {
+            vec![]
+        };
+
+        self.restore_session(session);
+
+        let current = self.tab().id;
+
+        for id in old {
+            if let Some(index) = self.tabs.iter().position(|tab| tab.id == id) {
+                self.close_tab(index);
+            }
+        }
+
+        if let Some(index) = self.tabs.iter().position(|tab| tab.id == current) {
+            self.current = index;
+        }
+    }
+
+    /// Every keyboard shortcut, by category, shown with F1 or `?`.
+    fn shortcuts_window(&mut self, ctx: &egui::Context) {
+        egui::Window::new(tr("Keyboard shortcuts"))
+            .open(&mut self.show_shortcuts)
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                for &category in shortcuts::Category::ALL {
+                    ui.strong(category.name());
+
+                    egui::Grid::new(("shortcuts", category))
+                        .num_columns(2)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for action in shortcuts::actions(category) {
+                                ui.monospace(shortcuts::bindings(action));
+                                ui.label(action.describe());
+                                ui.end_row();
+                            }
+                        });
+
+                    ui.add_space(8.0);
+                }
+            });
+    }
+
+    /// Shown in the url bar when the page is a gemlog index or links to an
+    /// atom feed.
+    fn subscribe_button(&mut self, ui: &mut egui::Ui) {
+        let Some(feed) = self.tab().page.as_ref().and_then(feeds::detect) else {
+            return;
+        };
+
+        let subscribed = self.subscriptions.is_subscribed(&feed);
+        let hover = match subscribed {
+            true => tr("Unsubscribe"),
+            false => trf("Subscribe to {url}", &[("url", &feed)]),
+        };
+
+        if ui
+            .selectable_label(subscribed, "📰")
+            .on_hover_text(hover)
+            .clicked()
+        {
+            if subscribed {
+                self.subscriptions.unsubscribe(&feed);
+            } else {
+                let title = self.tab().title();
+                self.subscriptions.subscribe(&feed, &title);
+            }
+        }
+    }
+
+    /// The pages visited most recently, down the side of the window.
+    fn history_panel(&mut self, ctx: &egui::Context) {
+        if !self.show_history {
+            return;
+        }
+
+        let current = self.tab().sites.last().cloned();
+        let mut go = None;
+
+        egui::SidePanel::left("history panel")
+            .resizable(true)
+            .default_width(250.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.heading(tr("History"));
+
+                    if ui.small_button("✖").on_hover_text(tr("Close")).clicked() {
+                        self.show_history = false;
+                    }
+                });
+
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.history_filter)
+                        .hint_text(tr("Filter"))
+                        .desired_width(f32::INFINITY),
+                );
+
+                ui.separator();
+
+                ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .show(ui, |ui| {
+                        let entries = self.history.recent(&self.history_filter, MAX_HISTORY_PANEL);
+
+                        if entries.is_empty() {
+                            ui.weak(tr("Nothing in the history matches."));
+                        }
+
+                        for entry in entries {
+                            let title = match entry.title.is_empty() {
+                                true => &entry.url,
+                                false => &entry.title,
+                            };
+
+                            if ui
+                                .selectable_label(current.as_ref() == Some(&entry.url), title)
+                                .on_hover_text(&entry.url)
+                                .clicked()
+                            {
+                                go = Some(entry.url.clone());
+                            }
+                        }
+                    });
+            });
+
+        if let Some(url) = go {
+            self.change_site(&url, false);
+        }
+    }
+
+    /// The bookmarks down the side of the window, to keep them at hand.
+    fn bookmarks_panel(&mut self, ctx: &egui::Context) {
+        if !self.show_bookmarks_panel {
+            return;
+        }
+
+        let mut opened = None;
+
+        egui::SidePanel::left("bookmarks panel")
+            .resizable(true)
+            .default_width(250.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.heading(tr("Bookmarks"));
+
+                    if ui.small_button("✖").on_hover_text(tr("Close")).clicked() {
+                        self.show_bookmarks_panel = false;
+                    }
+                });
+
+                ui.weak(tr("Double click to open, drag to move."));
+                ui.separator();
+
+                ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .show(ui, |ui| {
+                        if self.bookmarks.all().is_empty() {
+                            ui.weak(tr("No bookmarks yet."));
+                        }
+
+                        opened = self.bookmarks.sidebar_ui(ui, &mut self.dragging_bookmark);
+                    });
+            });
+
+        if let Some(url) = opened {
+            self.change_site(&url, false);
+        }
+    }
+
+    /// Every link on the page and where it goes, down the right side of the
+    /// window.
+    fn links_panel(&mut self, ctx: &egui::Context) {
+        if !self.show_links {
+            return;
+        }
+
+        let filter = self.link_filter.trim().to_lowercase();
+        let links: Vec<(String, String)> = self
+            .tab()
+            .page
+            .iter()
+            .flat_map(|page| {
+                page.nodes.iter().filter_map(|node| match node {
+                    GemtextNode::Link(link, label) => {
+                        let url = nav::resolve(&page.url, link)?;
+                        let label = label.clone().unwrap_or_else(|| link.clone());
+                        Some((label, url))
+                    }
+                    _ => None,
+                })
+            })
+            .filter(|(label, url)| {
+                label.to_lowercase().contains(&filter) || url.to_lowercase().contains(&filter)
+            })
+            .collect();
+
+        let mut go = None;
+        let mut open_all = false;
+
+        egui::SidePanel::right("links panel")
+            .resizable(true)
+            .default_width(300.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.heading(tr("Links"));
+
+                    if ui.small_button("✖").on_hover_text(tr("Close")).clicked() {
+                        self.show_links = false;
+                    }
+                });
+
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.link_filter)
+                        .hint_text(tr("Filter"))
+                        .desired_width(f32::INFINITY),
+                );
+
+                let count = links.len().to_string();
+
+                open_all = ui
+                    .add_enabled(
+                        (1..=MAX_OPEN_ALL).contains(&links.len()),
+                        egui::Button::new(trf("Open all {count} in tabs", &[("count", &count)])),
+                    )
+                    .on_disabled_hover_text(trf(
+                        "Filter it down to {max} links or fewer first",
+                        &[("max", &MAX_OPEN_ALL.to_string())],
+                    ))
+                    .clicked();
+
+                ui.separator();
+
+                ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .show(ui, |ui| {
+                        for (label, url) in &links {
+                            if ui.link(label).on_hover_text(url).clicked() {
+                                go = Some(url.clone());
+                            }
+
+                            ui.weak(url);
+                        }
+                    });
+            });
+
+        if open_all {
+            // open them next to this tab, in order, and stay here
+            let current = self.current;
+
+            for (_, url) in links.iter().rev() {
+                self.new_tab(url);
+                self.current = current;
+            }
+        } else if let Some(url) = go {
+            self.change_site(&url, false);
+        }
+    }
+
+    /// The headings of the page, to jump between sections. Click on it or
+    /// press ctrl+shift+o to move through it with the arrow keys, then
+    /// enter to jump to a heading.
+    fn outline_panel(&mut self, ctx: &egui::Context) {
+        if !self.show_outline {
+            return;
+        }
+
+        let id = egui::Id::new(OUTLINE_ID);
+        let headings: Vec<(usize, usize, String)> = self
+            .tab()
+            .page
+            .iter()
+            .flat_map(|page| page.nodes.iter().enumerate())
+            .filter_map(|(index, node)| match node {
+                GemtextNode::Heading(text) => Some((index, 0, text.clone())),
+                GemtextNode::SubHeading(text) => Some((index, 1, text.clone())),
+                GemtextNode::SubSubHeading(text) => Some((index, 2, text.clone())),
+                _ => None,
+            })
+            .collect();
+
+        let mut jump = None;
+
+        egui::SidePanel::right("outline panel")
+            .resizable(true)
+            .default_width(220.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.heading(tr("Outline"));
+
+                    if ui.small_button("✖").on_hover_text(tr("Close")).clicked() {
+                        self.show_outline = false;
+                    }
+                });
+
+                ui.separator();
+
+                // behind the headings, so clicking anywhere in the panel
+                // puts the arrow keys on it
+                let area = ui.interact(ui.available_rect_before_wrap(), id, Sense::click());
+                let focused = area.has_focus();
+
+                if area.clicked() {
+                    area.request_focus();
+                }
+
+                let selected = self
+                    .outline_selected
+                    .and_then(|node| headings.iter().position(|(index, ..)| *index == node));
+
+                if focused {
+                    let last = headings.len().saturating_sub(1);
+
+                    let (moved, enter, escape) = {
+                        let mut input = ui.input_mut();
+
+                        let moved = if input.consume_key(Modifiers::NONE, Key::ArrowDown) {
+                            Some(selected.map_or(0, |at| (at + 1).min(last)))
+                        } else if input.consume_key(Modifiers::NONE, Key::ArrowUp) {
+                            Some(selected.map_or(0, |at| at.saturating_sub(1)))
+                        } else {
+                            None
+                        };
+
+                        (
+                            moved,
+                            input.consume_key(Modifiers::NONE, Key::Enter),
+                            input.consume_key(Modifiers::NONE, Key::Escape),
+                        )
+                    };
+
+                    if let Some((index, ..)) = moved.and_then(|at| headings.get(at)) {
+                        self.outline_selected = Some(*index);
+                    }
+
+                    if enter {
+                        jump = self.outline_selected;
+                    }
+
+                    // back to scrolling the page with the arrow keys
+                    if escape {
+                        area.surrender_focus();
+                    }
+                }
+
+                if headings.is_empty() {
+                    ui.weak(tr("This page has no headings."));
+                }
+
+                ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .show(ui, |ui| {
+                        for (index, level, text) in &headings {
+                            let selected = self.outline_selected == Some(*index);
+
+                            ui.horizontal(|ui| {
+                                ui.add_space(*level as f32 * 12.0);
+
+                                let label = ui.selectable_label(selected && focused, text);
+
+                                if selected && focused {
+                                    label.scroll_to_me(None);
+                                }
+
+                                if label.clicked() {
+                                    self.outline_selected = Some(*index);
+                                    jump = Some(*index);
+                                    area.request_focus();
+                                }
+                            });
+                        }
+                    });
+            });
+
+        if let Some(node) = jump {
+            let top = self
+                .headings
+                .iter()
+                .find(|(index, _)| *index == node)
+                .map(|(_, top)| *top);
+
+            if let Some(top) = top {
+                let max = self.max_scroll(self.page_height);
+                self.tab_mut().scroll_to = Some(top.clamp(0.0, max));
+            }
+        }
+    }
+
+    /// Fetch subscriptions when they're due, to find new posts.
+    fn check_subscriptions(&mut self, ctx: &egui::Context) {
+        self.subscriptions.poll();
+
+        if self.subscriptions.all().is_empty() {
+            return;
+        }
+
+        self.subscriptions
+            .fetch_due(self.page_fetcher(true), &self.config.sites());
+
+        ctx.request_repaint_after(match self.subscriptions.is_fetching() {
+            true => Duration::from_millis(500),
+            false => Duration::from_secs(60),
+        });
+    }
+
+    /// Do what was picked from the tray icon, and keep its unread posts
+    /// up to date.
+    fn tray_actions(&mut self, frame: &mut eframe::Frame) {
+        let actions = match &self.tray {
+            Some(tray) => {
+                tray.set_posts(
+                    self.subscriptions
+                        .unread()
+                        .map(|entry| (entry.title.clone(), entry.url.clone()))
+                        .collect(),
+                );
+                tray.actions()
+            }
+            None => return,
+        };
+
+        for action in actions {
+            match action {
+                tray::Action::Open => {}
+                tray::Action::NewTab => {
+                    let home = self.config.home_page().to_string();
+                    self.new_tab(&home);
+                }
+                tray::Action::Read(url) => {
+                    self.subscriptions.mark_read(&url);
+                    self.new_tab(&url);
+                }
+                tray::Action::Quit => {
+                    frame.close();
+                    return;
+                }
+            }
+
+            frame.set_visible(true);
+        }
+    }
+
+    /// Go to the oldest post from the subscriptions that hasn't been read.
+    fn next_unread(&mut self) {
+        if let Some(url) = self.subscriptions.next_unread() {
+            self.change_site(&url, false);
+        }
+    }
+
+    /// The subscriptions, for unsubscribing from ones that aren't open.
+    fn subscriptions_menu(&mut self, ui: &mut egui::Ui) {
+        let mut unsubscribe = None;
+
+        if self.subscriptions.is_fetching() {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label(tr("Checking for new posts"));
+            });
+        }
+
+        if ui.button(tr("Mark all as read")).clicked() {
+            self.subscriptions.mark_all_read();
+            ui.close_menu();
+        }
+
+        ui.separator();
+
+        if self.subscriptions.all().is_empty() {
+            ui.weak(tr(
+                "Click 📰 in the url bar on a gemlog to subscribe to it.",
+            ));
+        }
+
+        for sub in self.subscriptions.all() {
+            ui.horizontal(|ui| {
+                if ui
+                    .small_button("✖")
+                    .on_hover_text(tr("Unsubscribe"))
+                    .clicked()
+                {
+                    unsubscribe = Some(sub.url.clone());
+                }
+
+                ui.label(&sub.title).on_hover_text(&sub.url);
+            });
+        }
+
+        if let Some(url) = unsubscribe {
+            self.subscriptions.unsubscribe(&url);
+        }
+    }
+
+    /// Check watched pages for changes when they're due.
+    fn check_watched(&mut self, ctx: &egui::Context) {
+        self.monitor.poll();
+
+        if self.monitor.pages().is_empty() {
+            return;
+        }
+
+        let minutes = self.config.watch_interval.unwrap_or(DEFAULT_WATCH_INTERVAL);
+
+        self.monitor.check_due(
+            self.page_fetcher(true),
+            &self.config.sites(),
+            Duration::from_secs(minutes * 60),
+        );
+
+        ctx.request_repaint_after(match self.monitor.is_checking() {
+            true => Duration::from_millis(500),
+            false => Duration::from_secs(60),
+        });
+    }
+
+    /// The pages being watched for changes, with the changed ones flagged.
+    fn watched_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_watched;
+        let mut go = None;
+        let mut unwatch = None;
+
+        let current = self.tab().sites.last().cloned().unwrap_or_default();
+        let title = self.tab().title();
+
+        egui::Window::new(tr("Watched pages"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if self.monitor.is_watched(&current) {
+                        if ui.button(tr("Stop watching this page")).clicked() {
+                            unwatch = Some(current.clone());
+                        }
+                    } else if ui
+                        .add_enabled(
+                            !current.starts_with("about:"),
+                            egui::Button::new(tr("👁 Watch this page")),
+                        )
+                        .clicked()
+                    {
+                        self.monitor.watch(&current, &title);
+                    }
+
+                    if ui.button(tr("Check now")).clicked() {
+                        self.monitor.check_all();
+                    }
+
+                    if self.monitor.is_checking() {
+                        ui.spinner();
+                    }
+                });
+
+                ui.separator();
+
+                if self.monitor.pages().is_empty() {
+                    ui.weak(tr("gbrowse checks watched pages every so often and flags the ones that changed."));
+                }
+
+                egui::Grid::new("watched").show(ui, |ui| {
+                    for page in self.monitor.pages() {
+                        if page.changed {
+                            ui.colored_label(ui.visuals().warn_fg_color, "●")
+                                .on_hover_text(tr("Changed since you last looked"));
+                        } else {
+                            ui.label("");
+                        }
+
+                        let title = match page.title.is_empty() {
+                            true => &page.url,
+                            false => &page.title,
+                        };
+
+                        if ui.link(title).on_hover_text(&page.url).clicked() {
+                            go = Some(page.url.clone());
+                        }
+
+                        if page.previous.is_some()
+                            && ui.small_button(tr("diff")).clicked()
+                        {
+                            go = Some(monitor::diff_url(&page.url));
+                        }
+
+                        if ui.small_button("✖").on_hover_text(tr("Stop watching")).clicked() {
+                            unwatch = Some(page.url.clone());
+                        }
+
+                        ui.end_row();
+                    }
+                });
+            });
+
+        self.show_watched = open;
+
+        if let Some(url) = unwatch {
+            self.monitor.unwatch(&url);
+        }
+
+        if let Some(url) = go {
+            if let Some(watched) = url
+                .strip_prefix(monitor::DIFF_URL)
+                .and_then(|query| query.strip_prefix('?'))
+            {
+                self.monitor.seen(&input::decode(watched));
+            }
+
+            self.change_site(&url, false);
+        }
+    }
+
+    /// Search the text of every open tab, jumping to a match when it's
+    /// clicked.
+    fn tab_search_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_tab_search;
+        let mut jump = None;
+
+        egui::Window::new(tr("Search tabs"))
+            .open(&mut open)
+            .default_width(400.0)
+            .show(ctx, |ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.tab_search)
+                        .id(egui::Id::new("tab search"))
+                        .hint_text(tr("Text to find in every tab")),
+                );
+
+                let query = self.tab_search.trim().to_lowercase();
+
+                if query.is_empty() {
+                    return;
+                }
+
+                ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                    let mut found = false;
+
+                    for (index, tab) in self.tabs.iter().enumerate() {
+                        let Some(page) = &tab.page else {
+                            continue;
+                        };
+
+                        let matches: Vec<(usize, &str)> = page
+                            .nodes
+                            .iter()
+                            .enumerate()
+                            .filter_map(|(node, block)| Some((node, node_text(block)?)))
+                            .filter(|(_, text)| text.to_lowercase().contains(&query))
+                            .take(MAX_TAB_MATCHES)
+                            .collect();
+
+                        if matches.is_empty() {
+                            continue;
+                        }
+
+                        found = true;
+                        ui.strong(tab.title());
+
+                        for (node, text) in matches {
+                            if ui.selectable_label(false, snippet(text, &query)).clicked() {
+                                jump = Some((index, tab.id, node));
+                            }
+                        }
+
+                        ui.add_space(4.0);
+                    }
+
+                    if !found {
+                        ui.weak(tr("Nothing found."));
+                    }
+                });
+            });
+
+        self.show_tab_search = open;
+
+        if let Some((index, id, node)) = jump {
+            self.current = index;
+            self.found = Some((id, node));
+        }
+    }
+
+    fn sessions_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_sessions;
+        let mut save = false;
+        let mut action = None;
+
+        egui::Window::new(tr("Sessions"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.session_name)
+                            .hint_text(tr("Name, like \"research\"")),
+                    );
+
+                    save = ui
+                        .add_enabled(
+                            !self.session_name.trim().is_empty(),
+                            egui::Button::new(tr("💾 Save open tabs")),
+                        )
+                        .clicked();
+                });
+
+                ui.separator();
+
+                egui::Grid::new("sessions").show(ui, |ui| {
+                    for (name, session) in self.sessions.iter() {
+                        ui.label(name);
+                        ui.label(trf("{tabs} tabs", &[("tabs", &session.tabs.len())]));
+
+                        if ui
+                            .button(tr("Open"))
+                            .on_hover_text(tr("Close the open tabs and open these instead"))
+                            .clicked()
+                        {
+                            action = Some((name.clone(), Some(true)));
+                        }
+
+                        if ui
+                            .button(tr("Add"))
+                            .on_hover_text(tr("Open these next to the open tabs"))
+                            .clicked()
+                        {
+                            action = Some((name.clone(), Some(false)));
+                        }
+
+                        if ui.button("🗑").on_hover_text(tr("Delete")).clicked() {
+                            action = Some((name.clone(), None));
+                        }
+
+                        ui.end_row();
+                    }
+                });
+            });
+
+        self.show_sessions = open;
+
+        if save {
+            let session = Session::from_tabs(&self.tabs, self.current);
+
+            self.sessions.insert(&self.session_name, session);
+            self.session_name.clear();
+        }
+
+        match action {
+            Some((name, Some(replace))) => {
+                if let Some(session) = self.sessions.get(&name).cloned() {
+                    self.open_session(session, replace);
+                }
+            }
+            Some((name, None)) => self.sessions.remove(&name),
+            None => {}
+        }
+    }
+
+    /// Offer to reopen the tabs from when gbrowse didn't close normally.
+    fn restore_bar(&mut self, ctx: &egui::Context) {
+        let Some(session) = &self.crashed_session else {
+            return;
+        };
+
+        let tabs = session.tabs.len();
+        let mut restore = false;
+        let mut dismiss = false;
+
+        egui::TopBottomPanel::top("restore session").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(trf(
+                    "gbrowse didn't close properly. Reopen the {tabs} tabs that were open?",
+                    &[("tabs", &tabs)],
+                ));
+
+                restore = ui.button(tr("Restore")).clicked();
+                dismiss = ui.button(tr("Dismiss")).clicked();
+            });
+        });
+
+        if restore {
+            if let Some(session) = self.crashed_session.take() {
+                self.restore_session(session);
+            }
+        } else if dismiss {
+            self.crashed_session = None;
+        }
+    }
+
+    /// Offer the rest of a page that was cut short for being too big.
+    fn cut_bar(&mut self, ctx: &egui::Context) {
+        let cut = self
+            .tab()
+            .page
+            .as_ref()
+            .is_some_and(|page| page.cut.is_some());
+
+        if !cut {
+            return;
+        }
+
+        let mut load = false;
+
+        egui::TopBottomPanel::top("cut short").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(tr(
+                    "This page is too big to show all of it without freezing gbrowse, so it was cut short.",
+                ));
+
+                load = ui
+                    .button(tr("Load anyway"))
+                    .on_hover_text(tr("gbrowse might stop responding for a while."))
+                    .clicked();
+            });
+        });
+
+        if load {
+            if let Some(page) = &mut self.tab_mut().page {
+                if let Some(nodes) = page.cut.take() {
+                    page.nodes = nodes;
+                }
+            }
+        }
+    }
+
+    /// Warn that the capsule's certificate isn't the one that was pinned,
+    /// until the new one is trusted.
+    fn certificate_bar(&mut self, ctx: &egui::Context) {
+        let Some((host, fingerprint)) = self.tab().page.as_ref().and_then(|page| {
+            let certificate = page.certificate.as_ref()?;
+            let host = url::Url::parse(&page.url).ok()?.host_str()?.to_string();
+
+            (certificate.trust == Trust::Changed).then(|| (host, certificate.fingerprint.clone()))
+        }) else {
+            return;
+        };
+
+        let mut trusted = false;
+
+        egui::TopBottomPanel::top("certificate changed").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    trf(
+                        "⚠ The certificate of {host} changed since your last visit. It might have been renewed, or someone might be listening in.",
+                        &[("host", &host)],
+                    ),
+                );
+
+                trusted = ui
+                    .button(tr("Trust the new certificate"))
+                    .on_hover_text(trf(
+                        "SHA-256 fingerprint: {fingerprint}",
+                        &[("fingerprint", &fingerprint)],
+                    ))
+                    .clicked();
+            });
+        });
+
+        if trusted {
+            transport::trust(&host, &fingerprint);
+
+            if let Some(certificate) = self
+                .tab_mut()
+                .page
+                .as_mut()
+                .and_then(|page| page.certificate.as_mut())
+            {
+                certificate.trust = Trust::Known;
+            }
+        }
+    }
+
+    /// Move a tab to another position, keeping the same tab selected.
+    pub fn move_tab(&mut self, from: usize, to: usize) {
+        let id = self.tab().id;
+        let tab = self.tabs.remove(from);
+
+        self.tabs.insert(to, tab);
+        self.current = self.tabs.iter().position(|tab| tab.id == id).unwrap_or(to);
+    }
+
+    /// The emoji favicon of the capsule open in a tab.
+    fn favicon(&mut self, index: usize) -> Option<String> {
+        let host = self.tabs[index]
+            .current_host()
+            .filter(|host| !self.config.is_blocked(host))?;
+
+        self.favicons.get(&self.fetcher, &host).map(str::to_string)
+    }
+
+    fn pinned_count(&self) -> usize {
+        self.tabs.iter().filter(|tab| tab.pinned).count()
+    }
+
+    /// Pin or unpin a tab, keeping pinned tabs in front of the others.
+    fn set_pinned(&mut self, index: usize, pinned: bool) {
+        let to = if pinned {
+            self.pinned_count()
+        } else {
+            self.pinned_count() - 1
+        };
+
+        self.tabs[index].pinned = pinned;
+        self.move_tab(index, to);
+        self.save_pinned();
+    }
+
+    /// Remember which pages are pinned so they're opened at startup.
+    fn save_pinned(&mut self) {
+        let pinned: Vec<String> = self
+            .tabs
+            .iter()
+            .filter(|tab| tab.pinned)
+            .filter_map(|tab| tab.sites.last().cloned())
+            .collect();
+
+        if pinned != self.config.pinned_tabs {
+            self.config.pinned_tabs = pinned;
+            self.config.save();
+        }
+    }
+
+    pub fn change_site(&mut self, url: &str, moving_back: bool) {
+        self.navigate(url, moving_back, false);
+    }
+
+    /// Pages built into gbrowse that are gemtext, rather than drawn with egui.
+    fn gemtext_page(&self, url: &str) -> Option<Page> {
+        if url == welcome::URL {
+            Some(welcome::page())
+        } else if url == STDIN_URL {
+            self.piped.as_deref().map(piped_page)
+        } else {
+            self.monitor.diff_page(url)
+        }
+    }
+
+    /// Go to a page. Pages gone back or forward to `from_history` are shown
+    /// as they were left, if they're still around.
+    fn navigate(&mut self, url: &str, moving_back: bool, from_history: bool) {
+        if let Some(action) = welcome::Action::parse(url) {
+            self.welcome_action(action);
+            return;
+        }
+
+        // the answer to a sensitive prompt, whose query must stay out of
+        // sight and out of the cache
+        let sensitive = self.tab().input.as_ref().is_some_and(|input| {
+            input.sensitive && input::without_query(url) == input::without_query(&input.url)
+        });
+
+        let built_in = self.gemtext_page(url);
+        self.found = None;
+
+        if !sensitive {
+            self.monitor.seen(url);
+            self.subscriptions.mark_read(url);
+        }
+
+        let tab = self.tab_mut();
+
+        tab.leave();
+        tab.error = None;
+        tab.book = None;
+        tab.chapter = None;
+        tab.input = None;
+        tab.hide_query = sensitive;
+        tab.auto_refresh = None;
+        tab.refresh_at = None;
+        tab.forward.clear();
+
+        tab.url = if sensitive {
+            input::without_query(url)
+        } else {
+            url
+        }
+        .to_string();
+
+        // pages built into gbrowse
+        if url.starts_with("about:") {
+            if !moving_back {
+                tab.sites.push(url.to_string());
+            }
+
+            tab.blocked = None;
+            tab.retry_at = None;
+            tab.stop_loading();
+
+            tab.page = built_in;
+
+            return;
+        }
+
+        let url_structured = match Url::try_from(file::with_host(url).as_str()) {
+            Ok(url_structured) => url_structured,
+            Err(err) => {
+                tab.error = Some(LoadError::Other(format!(
+                    "{}: {err}",
+                    tr("Incorrectly formatted url")
+                )));
+                tab.stop_loading();
+
+                return;
+            }
+        };
+
+        if !moving_back {
+            tab.sites.push(tab.url.clone());
+        }
+
+        if !from_history {
+            tab.forget_snapshots();
+        }
+
+        tab.blocked = None;
+        tab.retry_attempt = 0;
+        tab.retry_at = None;
+
+        if self.config.is_blocked(&url_structured.authority.host) {
+            let tab = self.tab_mut();
+            tab.blocked = Some(url_structured.authority.host);
+            tab.stop_loading();
+            return;
+        }
+
+        let tab = self.tab_mut();
+
+        if let Some(snapshot) = from_history.then(|| tab.snapshots.remove(url)).flatten() {
+            tab.page = Some(snapshot.page);
+            tab.scroll_to = Some(snapshot.scroll);
+            tab.stop_loading();
+
+            return;
+        }
+
+        self.request(self.current, url_structured, false);
+    }
+
+    /// Go to whatever was typed into the url bar, expanding bookmark
+    /// keywords.
+    fn go_to(&mut self, text: &str) {
+        if let Some(url) = self.bookmarks.expand(text) {
+            self.change_site(&url, false);
+        } else if text.trim().starts_with('!') {
+            let url = self.config.search_url(text);
+            self.change_site(&url, false);
+        } else {
+            self.change_site(text.trim(), false);
+        }
+    }
+
+    /// Select the whole url when the url bar is clicked into or focused with
+    /// ctrl+l.
+    fn select_url(&mut self, ui: &egui::Ui, field: &egui::Response, focus_url: bool) {
+        if field.gained_focus() || focus_url {
+            self.select_url = true;
+        }
+
+        if !self.select_url {
+            return;
+        }
+
+        if let Some(mut state) = TextEditState::load(ui.ctx(), field.id) {
+            let len = self.tab().url.chars().count();
+
+            state.set_ccursor_range(Some(CCursorRange::two(CCursor::new(0), CCursor::new(len))));
+            state.store(ui.ctx(), field.id);
+        }
+
+        if !ui.input().pointer.any_down() {
+            self.select_url = false;
+        }
+    }
+
+    /// Suggest pages from the history while typing into the url bar.
+    fn suggestions(&mut self, ui: &mut egui::Ui, field: &egui::Response) {
+        let popup = ui.make_persistent_id("suggestions");
+        let text = self.tab().url.clone();
+
+        let editing = field.has_focus() && self.tab().sites.last() != Some(&text);
+        let suggestions = self.history.suggest(&text, MAX_SUGGESTIONS);
+
+        if editing && !suggestions.is_empty() {
+            ui.memory().open_popup(popup);
+        } else if ui.input().key_pressed(Key::Enter) && ui.memory().is_popup_open(popup) {
+            ui.memory().close_popup();
+        }
+
+        let mut clicked = None;
+
+        egui::popup_below_widget(ui, popup, field, |ui| {
+            for entry in suggestions {
+                if ui
+                    .selectable_label(false, &entry.title)
+                    .on_hover_text(&entry.url)
+                    .clicked()
+                {
+                    clicked = Some(entry.url.clone());
+                }
+            }
+        });
+
+        if let Some(url) = clicked {
+            self.change_site(&url, false);
+        }
+    }
+
+    /// The fetcher for pages, which answers from the cache unless the page
+    /// is being refreshed.
+    fn page_fetcher(&self, refresh: bool) -> Arc<dyn Fetcher> {
+        // local files are never cached, so changes to them show up
+        Arc::new(FileFetcher::new(Arc::new(CachingFetcher::new(
+            self.fetcher.clone(),
+            self.cache.clone(),
+            refresh,
+        ))))
+    }
+
+    /// Load the first few links of the page in a tab into the cache.
+    fn prefetch_links(&mut self, index: usize) {
+        let Some(page) = &self.tabs[index].page else {
+            return;
+        };
+
+        let urls: Vec<String> = page
+            .nodes
+            .iter()
+            .filter_map(|node| match node {
+                GemtextNode::Link(url, _) => nav::resolve(&page.url, url),
+                _ => None,
+            })
+            .filter(|url| {
+                url::Url::parse(url)
+                    .ok()
+                    .filter(|url| url.scheme() == "gemini")
+                    .and_then(|url| url.host_str().map(|host| !self.config.is_blocked(host)))
+                    .unwrap_or(false)
+            })
+            .take(self.config.prefetch_links)
+            .collect();
+
+        for url in urls {
+            self.prefetcher
+                .start(self.page_fetcher(false), &url, self.config.cache.max_age());
+        }
+    }
+
+    /// Start loading a page in a tab. Pages with a hidden query skip the
+    /// cache, so what was typed isn't kept around.
+    fn request(&mut self, index: usize, url: Url, refresh: bool) {
+        let fetcher: Arc<dyn Fetcher> = if self.tabs[index].hide_query {
+            Arc::new(FileFetcher::new(self.fetcher.clone()))
+        } else {
+            self.page_fetcher(refresh)
+        };
+
+        self.tabs[index].request(
+            fetcher,
+            self.config.sites(),
+            self.downloads.downloader(&self.config),
+            url,
+        );
+    }
+
+    /// Pick up the pages that finished loading in every tab.
+    fn poll_tabs(&mut self) {
+        for index in 0..self.tabs.len() {
+            if let Some(result) = self.tabs[index].try_recv() {
+                self.finish_loading(index, result);
+            }
+        }
+    }
+
+    fn finish_loading(&mut self, index: usize, result: Result<Loaded, LoadError>) {
+        let tab = &mut self.tabs[index];
+
+        match result {
+            Ok(Loaded::Page(mut page)) => {
+                tab.retry_attempt = 0;
+
+                if tab.hide_query {
+                    for redirect in &mut page.redirects {
+                        redirect.url = input::without_query(&redirect.url).to_string();
+                    }
+                }
+
+                page.url = tab.show_final_url(&page.url);
+
+                let url = page.url.clone();
+                tab.page = Some(page);
+
+                if !tab.hide_query {
+                    self.history.visit(&url, &tab.title());
+                }
+
+                let tab = &self.tabs[index];
+
+                if tab.pinned {
+                    self.save_pinned();
+                }
+
+                self.prefetch_links(index);
+            }
+            Ok(Loaded::Download { url, path }) => {
+                let url = tab.show_final_url(&url);
+                tab.page = Some(Page {
+                    nodes: gemtext::parse_gemtext(&format!(
+                        "# {}\n\n{}\n=> about:downloads {}",
+                        tr("Downloading"),
+                        trf(
+                            "Saving {url} to {path}",
+                            &[("url", &url), ("path", &path.display())]
+                        ),
+                        tr("Downloads"),
+                    )),
+                    url,
+                    redirects: vec![],
+                    ..Default::default()
+                });
+            }
+            Ok(Loaded::Book { url, book }) => {
+                tab.show_final_url(&url);
+                tab.book = Some(book);
+                tab.chapter = None;
+            }
+            Ok(Loaded::Blocked { url, host }) => {
+                tab.show_final_url(&url);
+                tab.blocked = Some(host);
+            }
+            Ok(Loaded::Audio { url, data }) => {
+                let url = tab.show_final_url(&url);
+
+                let name = input::without_query(&url)
+                    .rsplit('/')
+                    .find(|segment| !segment.is_empty())
+                    .unwrap_or(&url)
+                    .to_string();
+
+                match Player::new(name.clone(), data) {
+                    Ok(player) => {
+                        tab.page = Some(Page {
+                            nodes: gemtext::parse_gemtext(&format!(
+                                "# 🎵 {name}\n\n{}\n{}",
+                                trf("Playing {url}", &[("url", &url)]),
+                                tr("It keeps playing in the bar at the bottom while you browse."),
+                            )),
+                            url,
+                            redirects: vec![],
+                            ..Default::default()
+                        });
+
+                        self.player = Some(player);
+                    }
+                    Err(err) => tab.error = Some(LoadError::Other(err)),
+                }
+            }
+            Ok(Loaded::Input(request)) => {
+                let url = tab.show_final_url(&request.url);
+
+                let text = if request.sensitive {
+                    String::new()
+                } else {
+                    self.inputs.get(&url).cloned().unwrap_or_default()
+                };
+
+                tab.input = Some(InputPrompt {
+                    url,
+                    prompt: request.prompt,
+                    text,
+                    sensitive: request.sensitive,
+                });
+            }
+            Err(err) => {
+                if let (Some(wait), Some(host)) = (err.slow_down(), tab.current_host()) {
+                    self.slow_down.insert(host, Instant::now() + wait);
+                }
+
+                let temporary = err.is_temporary();
+                self.tabs[index].error = Some(err);
+
+                if temporary && self.config.auto_retry {
+                    self.schedule_retry(index);
+                }
+            }
+        }
+    }
+
+    /// The theme for the current page, with the user's style for its
+    /// capsule applied.
+    fn page_theme(&self) -> GemtextTheme {
+        let mut theme = self.theme.clone();
+
+        self.config.style.apply(&mut theme);
+        self.config
+            .site(self.tab().current_host().as_deref())
+            .style
+            .apply(&mut theme);
+
+        theme
+    }
+
+    /// Change the zoom for the current capsule and remember it.
+    fn set_zoom(&mut self, zoom: f32) {
+        if self.change_zoom(zoom) {
+            self.config.save();
+        }
+    }
+
+    /// Change the zoom for the current capsule without saving it yet.
+    fn change_zoom(&mut self, zoom: f32) -> bool {
+        let Some(host) = self.tab().current_host() else {
+            return false;
+        };
+
+        let zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        let settings = self.config.sites.entry(host).or_default();
+
+        settings.style.zoom = if zoom == 1.0 { None } else { Some(zoom) };
+        true
+    }
+
+    /// Zoom bit by bit while pinching or ctrl+scrolling, keeping the part of
+    /// the page under `anchor` where it is. It's saved once it stops changing.
+    fn zoom_by(&mut self, delta: f32, anchor: Option<egui::Pos2>) {
+        let before = self.page_theme().zoom;
+
+        if !self.change_zoom(before * delta) {
+            return;
+        }
+
+        let ratio = self.page_theme().zoom / before;
+        let anchor = match (self.page_rect, anchor) {
+            (Some(rect), Some(anchor)) => (anchor.y - rect.top()).clamp(0.0, rect.height()),
+            _ => 0.0,
+        };
+
+        let max = self.max_scroll(self.page_height * ratio);
+        let tab = self.tab_mut();
+        tab.scroll_to = Some(((tab.scroll + anchor) * ratio - anchor).clamp(0.0, max));
+        self.zooming = true;
+    }
+
+    /// How far a page this tall can be scrolled. The scroll area doesn't
+    /// stop pages that fit in the window from being scrolled away.
+    fn max_scroll(&self, height: f32) -> f32 {
+        self.page_rect
+            .map_or(0.0, |rect| (height - rect.height()).max(0.0))
+    }
+
+    /// Reload the page in a tab.
+    fn retry(&mut self, index: usize) {
+        let tab = &mut self.tabs[index];
+
+        tab.error = None;
+        tab.retry_at = None;
+
+        if let Some(url) = tab
+            .sites
+            .last()
+            .and_then(|url| Url::try_from(file::with_host(url).as_str()).ok())
+        {
+            self.request(index, url, true);
+        }
+    }
+
+    /// Reload the current page when a file it's made from changes, so a
+    /// capsule can be written with its pages open. Other changed pages are
+    /// dropped from the cache, to be fetched fresh when they're visited.
+    fn watch_files(&mut self, ctx: &egui::Context) {
+        let url = self
+            .tab()
+            .sites
+            .last()
+            .map(|url| file::with_host(url))
+            .unwrap_or_default();
+
+        // follow `file://` pages around, watching whichever directory they're in
+        let dir_url = &url[..url.rfind('/').map_or(0, |end| end + 1)];
+
+        match dir_url.strip_prefix("file://localhost") {
+            Some(dir) => {
+                let dir = PathBuf::from(input::decode(dir));
+
+                if self.file_watcher.as_ref().map(Watcher::dir) != Some(dir.as_path()) {
+                    self.file_watcher = Some(Watcher::new(&dir, dir_url));
+                }
+            }
+            None => self.file_watcher = None,
+        }
+
+        let changed: Vec<String> = self
+            .served
+            .iter()
+            .chain(&self.file_watcher)
+            .flat_map(Watcher::changed)
+            .collect();
+
+        if self.served.is_some() || self.file_watcher.is_some() {
+            ctx.request_repaint_after(watch::INTERVAL);
+        }
+
+        for changed in &changed {
+            self.cache.remove(changed);
+
+            if let Some(dir) = changed.strip_suffix("index.gmi") {
+                self.cache.remove(dir);
+            }
+        }
+
+        if !self.tab().loading && changed.iter().any(|changed| watch::affects(&url, changed)) {
+            self.retry(self.current);
+        }
+    }
+
+    /// Carry out the commands scripts have sent.
+    fn remote_control(&mut self) {
+        let requests = self
+            .remote
+            .as_ref()
+            .map(ipc::Server::requests)
+            .unwrap_or_default();
+
+        for request in requests {
+            let reply = match &request.command {
+                Command::Open(url) => {
+                    self.new_tab(url);
+                    Ok(String::new())
+                }
+                Command::Reload => {
+                    self.retry(self.current);
+                    Ok(String::new())
+                }
+                Command::Tabs => Ok(self
+                    .tabs
+                    .iter()
+                    .enumerate()
+                    .map(|(index, tab)| {
+                        let marker = if index == self.current { '*' } else { ' ' };
+                        let url = tab.sites.last().unwrap_or(&tab.url);
+
+                        format!("{marker} {url}\n")
+                    })
+                    .collect()),
+                Command::Dump => match &self.tab().page {
+                    Some(page) => Ok(page.nodes.iter().map(|node| format!("{node}\n")).collect()),
+                    None => Err("nothing is loaded in the current tab".to_string()),
+                },
+            };
+
+            request.reply(reply);
+        }
+    }
+
+    /// When the capsule in a tab will let us make requests again after
+    /// telling us to slow down.
+    fn slow_down_until(&self, index: usize) -> Option<Instant> {
+        self.tabs[index]
+            .current_host()
+            .and_then(|host| self.slow_down.get(&host).copied())
+            .filter(|until| *until > Instant::now())
+    }
+
+    /// Wait twice as long as last time before retrying, giving up after a few tries.
+    fn schedule_retry(&mut self, index: usize) {
+        let slow_down_until = self.slow_down_until(index);
+        let tab = &mut self.tabs[index];
+
+        if tab.retry_attempt < MAX_RETRIES {
+            let at = Instant::now() + RETRY_DELAY * 2u32.pow(tab.retry_attempt);
+
+            tab.retry_at = Some(slow_down_until.map_or(at, |until| until.max(at)));
+            tab.retry_attempt += 1;
+        } else {
+            tab.retry_at = None;
+        }
+    }
+
+    /// Retry any tabs whose backoff has run out.
+    fn run_retries(&mut self, ctx: &egui::Context) {
+        for index in 0..self.tabs.len() {
+            if let Some(at) = self.tabs[index].retry_at {
+                match at.checked_duration_since(Instant::now()) {
+                    Some(wait) => ctx.request_repaint_after(wait.min(Duration::from_secs(1))),
+                    None => self.retry(index),
+                }
+            }
+        }
+    }
+
+    /// Reload the tabs with auto refresh turned on once their timer runs
+    /// out. The timer starts over when the page is back.
+    fn run_refreshes(&mut self, ctx: &egui::Context) {
+        for index in 0..self.tabs.len() {
+            let tab = &mut self.tabs[index];
+
+            let Some(interval) = tab.auto_refresh else {
+                continue;
+            };
+
+            if tab.loading {
+                continue;
+            }
+
+            let at = *tab
+                .refresh_at
+                .get_or_insert_with(|| Instant::now() + interval);
+
+            match at.checked_duration_since(Instant::now()) {
+                Some(wait) => ctx.request_repaint_after(wait.min(Duration::from_secs(1))),
+                None => {
+                    tab.refresh_at = None;
+                    self.retry(index);
+                }
+            }
+        }
+    }
+
+    /// Pick how often the current page reloads itself, with a countdown
+    /// to the next reload.
+    fn auto_refresh_menu(&mut self, ui: &mut egui::Ui) {
+        let label = match self.tab().refresh_at {
+            Some(at) => {
+                let wait = at.saturating_duration_since(Instant::now()).as_secs();
+                format!("⏱ {}:{:02}", wait / 60, wait % 60)
+            }
+            None => "⏱".to_string(),
+        };
+
+        ui.menu_button(label, |ui| {
+            let current = self.tab().auto_refresh;
+
+            if ui.selectable_label(current.is_none(), tr("Off")).clicked() {
+                let tab = self.tab_mut();
+                tab.auto_refresh = None;
+                tab.refresh_at = None;
+                ui.close_menu();
+            }
+
+            for &minutes in REFRESH_INTERVALS {
+                let interval = Duration::from_secs(minutes * 60);
+                let text = match minutes {
+                    1 => tr("Every minute"),
+                    60 => tr("Every hour"),
+                    _ => trf("Every {minutes} minutes", &[("minutes", &minutes)]),
+                };
+
+                if ui
+                    .selectable_label(current == Some(interval), text)
+                    .clicked()
+                {
+                    let tab = self.tab_mut();
+                    tab.auto_refresh = Some(interval);
+                    tab.refresh_at = None;
+                    ui.close_menu();
+                }
+            }
+        })
+        .response
+        .on_hover_text(tr("Auto refresh"));
+    }
+
+    /// Scroll the page with the keyboard, as long as nothing else has
+    /// focus. Space and enter would type or follow a link otherwise.
+    fn keyboard_scroll(&mut self, ctx: &egui::Context) {
+        if ctx.memory().focus().is_some() || self.tab().page.is_none() || self.link_find.is_some() {
+            return;
+        }
+
+        // leave a line of the last page showing, to keep your place
+        let page = self
+            .page_rect
+            .map_or(0.0, |rect| (rect.height() - SCROLL_STEP).max(SCROLL_STEP));
+
+        let scroll = self.tabs[self.current].scroll;
+
+        let target = if shortcuts::pressed(ctx, Action::Top) {
+            0.0
+        } else if shortcuts::pressed(ctx, Action::Bottom) {
+            f32::MAX
+        } else if shortcuts::pressed(ctx, Action::PageUp) {
+            scroll - page
+        } else if shortcuts::pressed(ctx, Action::PageDown) {
+            scroll + page
+        } else if shortcuts::pressed(ctx, Action::LineUp) {
+            scroll - SCROLL_STEP
+        } else if shortcuts::pressed(ctx, Action::LineDown) {
+            scroll + SCROLL_STEP
+        } else if shortcuts::pressed(ctx, Action::NextHeading) {
+            self.headings
+                .iter()
+                .map(|(_, top)| *top)
+                .find(|top| *top > scroll + 1.0)
+                .unwrap_or(scroll)
+        } else if shortcuts::pressed(ctx, Action::PreviousHeading) {
+            self.headings
+                .iter()
+                .map(|(_, top)| *top)
+                .rfind(|top| *top < scroll - 1.0)
+                .unwrap_or(0.0)
+        } else {
+            return;
+        };
+
+        let max = self.max_scroll(self.page_height);
+        self.tab_mut().scroll_to = Some(target.clamp(0.0, max));
+    }
+
+    /// Press ' and type part of a link's label to find it, then enter to
+    /// follow it or escape to stop.
+    fn link_find(&mut self, ctx: &egui::Context) {
+        if self.tab().page.is_none() || ctx.memory().focus().is_some() {
+            self.link_find = None;
+            return;
+        }
+
+        let Some(query) = &mut self.link_find else {
+            if shortcuts::pressed(ctx, Action::FindLink) {
+                self.link_find = Some(String::new());
+            }
+
+            return;
+        };
+
+        let mut follow = false;
+        let mut stop = false;
+
+        // everything typed goes into the query, shortcuts still work
+        ctx.input_mut().events.retain(|event| match event {
+            Event::Text(text) => {
+                query.push_str(text);
+                false
+            }
+            Event::Key {
+                key,
+                pressed: true,
+                modifiers,
+            } if modifiers.is_none() => match key {
+                Key::Backspace => {
+                    query.pop();
+                    false
+                }
+                Key::Enter => {
+                    follow = true;
+                    false
+                }
+                Key::Escape => {
+                    stop = true;
+                    false
+                }
+                _ => true,
+            },
+            _ => true,
+        });
+
+        if follow {
+            let url = self.tabs[self.current].page.as_ref().and_then(|page| {
+                match page.nodes.get(find_link(&page.nodes, query)?) {
+                    Some(GemtextNode::Link(url, _)) => Some(url.clone()),
+                    _ => None,
+                }
+            });
+
+            self.link_find = None;
+
+            if let Some(url) = url {
+                self.follow_link(ctx, &url);
+            }
+        } else if stop {
+            self.link_find = None;
+        }
+    }
+
+    /// Shows what's being typed while finding a link.
+    fn link_find_bar(&self, ctx: &egui::Context) {
+        let (Some(query), Some(page)) = (&self.link_find, &self.tab().page) else {
+            return;
+        };
+
+        let found = find_link(&page.nodes, query).is_some();
+
+        egui::TopBottomPanel::bottom("link find").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(tr("Find link:"));
+                ui.monospace(query);
+
+                if !found {
+                    ui.colored_label(ui.visuals().warn_fg_color, tr("no matching links"));
+                }
+            });
+        });
+    }
+
+    /// How long the page took to load, at the bottom of the window.
+    fn timing_bar(&self, ctx: &egui::Context) {
+        let Some(timing) = self
+            .tab()
+            .page
+            .as_ref()
+            .and_then(|page| page.timing.as_ref())
+        else {
+            return;
+        };
+
+        if !self.config.show_timing {
+            return;
+        }
+
+        let summary = timing_steps(timing)
+            .into_iter()
+            .map(|(step, time)| format!("{step} {}", format_duration(time)))
+            .collect::<Vec<_>>()
+            .join(" · ");
+
+        egui::TopBottomPanel::bottom("timing").show(ctx, |ui| {
+            ui.small(summary);
+        });
+    }
+
+    /// Do what a link on the welcome page asks. Home pages and files to
+    /// import are asked for with an input prompt, which comes back here
+    /// with what was typed.
+    fn welcome_action(&mut self, action: welcome::Action) {
+        let prompt = |url: &str, prompt: String, text: String| InputPrompt {
+            url: format!("{}/{url}", welcome::URL),
+            prompt,
+            text,
+            sensitive: false,
+        };
+
+        match action {
+            welcome::Action::Theme(theme) => {
+                self.config.theme = theme;
+                self.config.save();
+            }
+            welcome::Action::Home(None) => {
+                let input = prompt(
+                    "home",
+                    tr("Which page should gbrowse start on?"),
+                    self.config.home_page().to_string(),
+                );
+
+                let tab = self.tab_mut();
+                tab.leave();
+                tab.input = Some(input);
+            }
+            welcome::Action::Home(Some(url)) => {
+                let url = url.trim();
+
+                self.config.home_page = match url.contains("://") || url.starts_with("about:") {
+                    true => Some(url.to_string()),
+                    false => Some(format!("gemini://{url}")),
+                };
+                self.config.save();
+                self.navigate(welcome::URL, true, false);
+            }
+            welcome::Action::Import(None) => {
+                let input = prompt(
+                    "import",
+                    tr("Which file should the bookmarks be imported from?"),
+                    String::new(),
+                );
+
+                let tab = self.tab_mut();
+                tab.leave();
+                tab.input = Some(input);
+            }
+            welcome::Action::Import(Some(path)) => {
+                self.navigate(welcome::URL, true, false);
+
+                match self.bookmarks.import(Path::new(path.trim())) {
+                    Ok(_) => self.show_bookmarks = true,
+                    Err(err) => self.tab_mut().error = Some(LoadError::Other(err)),
+                }
+            }
+        }
+    }
+
+    /// Go up a directory, or to the top of the capsule.
+    fn go_up(&mut self, to_root: bool) {
+        let current = self.tab().sites.last().cloned().unwrap_or_default();
+
+        let target = if to_root {
+            nav::root(&current)
+        } else {
+            nav::parent(&current)
+        };
+
+        if let Some(url) = target {
+            self.change_site(&url, false);
+        }
+    }
+
+    fn go_back(&mut self) {
+        let tab = self.tab_mut();
+
+        if tab.sites.len() < 2 {
+            return;
+        }
+
+        tab.leave();
+
+        let mut forward = std::mem::take(&mut tab.forward);
+        forward.extend(tab.sites.pop());
+
+        if let Some(before) = tab.sites.clone().last() {
+            self.navigate(before, true, true);
+        }
+
+        self.tab_mut().forward = forward;
+    }
+
+    fn go_forward(&mut self) {
+        let mut forward = std::mem::take(&mut self.tab_mut().forward);
+
+        if let Some(url) = forward.pop() {
+            self.navigate(&url, false, true);
+        }
+
+        self.tab_mut().forward = forward;
+    }
+
+    fn tab_bar(&mut self, ui: &mut egui::Ui) {
+        let mut select = None;
+        let mut close = None;
+        let mut pin = None;
+        let mut bookmark_all = false;
+        let mut dragged = None;
+        let mut rects = Vec::with_capacity(self.tabs.len());
+        let icons: Vec<_> = (0..self.tabs.len()).map(|i| self.favicon(i)).collect();
+
+        ui.horizontal_wrapped(|ui| {
+            for (index, tab) in self.tabs.iter().enumerate() {
+                // give each tab a stable id so it can be dragged past the others
+                let response = ui
+                    .push_id(tab.id, |ui| {
+                        ui.selectable_label(
+                            index == self.current,
+                            tab.label(icons[index].as_deref()),
+                        )
+                    })
+                    .inner
+                    .interact(Sense::drag())
+                    .on_hover_text(&tab.url)
+                    .context_menu(|ui| {
+                        let text = tr(if tab.pinned { "Unpin tab" } else { "Pin tab" });
+
+                        if ui.button(text).clicked() {
+                            pin = Some((index, !tab.pinned));
+                            ui.close_menu();
+                        }
+
+                        if ui.button(tr("Bookmark all tabs")).clicked() {
+                            bookmark_all = true;
+                            ui.close_menu();
+                        }
+                    });
+
+                if response.clicked() {
+                    select = Some(index);
+                }
+
+                if response.dragged() {
+                    dragged = Some(index);
+                }
+
+                rects.push(response.rect);
+
+                if self.tabs.len() > 1 && !tab.pinned && ui.small_button("✖").clicked() {
+                    close = Some(index);
+                }
+            }
+
+            if ui.button("➕").on_hover_text(tr("New tab")).clicked() {
+                let home = self.config.home_page().to_string();
+                self.new_tab(&home);
+            }
+        });
+
+        if let Some(index) = select {
+            self.current = index;
+        }
+
+        if let (Some(from), Some(pointer)) = (dragged, ui.ctx().pointer_interact_pos()) {
+            if let Some(to) = rects.iter().position(|rect| rect.contains(pointer)) {
+                // pinned tabs stay in front of the others
+                if to != from && self.tabs[to].pinned == self.tabs[from].pinned {
+                    self.move_tab(from, to);
+
+                    if self.tabs[to].pinned {
+                        self.save_pinned();
+                    }
+                }
+            }
+        }
+
+        if bookmark_all {
+            self.bookmark_all_tabs();
+        }
+
+        if let Some((index, pinned)) = pin {
+            self.set_pinned(index, pinned);
+        }
+
+        if let Some(index) = close {
+            self.close_tab(index);
+        }
+    }
+
+    /// Show the error for the current page as a gemtext page of its own.
+    fn error_page(&mut self, ui: &mut egui::Ui) {
+        let tab = self.tab();
+
+        let Some(err) = &tab.error else {
+            return;
+        };
+
+        let temporary = err.is_temporary();
+        let (title, explanation) = err.describe();
+        let icon = if temporary { "⏳" } else { "⚠" };
+
+        let mut text = format!(
+            "# {icon} {}\n\n{err}\n> {}\n\n{}\n",
+            tr(title),
+            tab.url,
+            tr(explanation),
+        );
+
+        if let Some(until) = self.slow_down_until(self.current) {
+            let wait = until.saturating_duration_since(Instant::now());
+
+            text.push_str(&trf(
+                "The server asked us to slow down, automatic retries are paused for {seconds}s.",
+                &[("seconds", &wait.as_secs_f32().ceil())],
+            ));
+            text.push('\n');
+            ui.ctx().request_repaint_after(Duration::from_secs(1));
+        }
+
+        let copied = format!("{err}\n{}", tab.url);
+        let can_go_back = tab.sites.len() > 1;
+
+        ui.add(GemtextView::new(&gemtext::parse_gemtext(&text)).theme(self.page_theme()));
+        ui.add_space(10.0);
+
+        let mut retry = false;
+        let mut back = false;
+
+        ui.horizontal(|ui| {
+            retry = ui.button(tr("🔄 Retry")).clicked();
+
+            if can_go_back {
+                back = ui.button(tr("⏪ Go back")).clicked();
+            }
+
+            if ui.button(tr("📋 Copy error")).clicked() {
+                ui.output().copied_text = copied;
+            }
+
+            if !temporary {
+                return;
+            }
+
+            if ui
+                .checkbox(&mut self.config.auto_retry, tr("Retry automatically"))
+                .changed()
+            {
+                self.config.save();
+
+                if self.config.auto_retry {
+                    self.schedule_retry(self.current);
+                } else {
+                    self.tab_mut().retry_at = None;
+                }
+            }
+
+            if let Some(at) = self.tab().retry_at {
+                let wait = at.saturating_duration_since(Instant::now());
+                ui.label(trf(
+                    "retrying in {seconds}s",
+                    &[("seconds", &wait.as_secs_f32().ceil())],
+                ));
+            }
+        });
+
+        if retry {
+            self.retry(self.current);
+        } else if back {
+            self.go_back();
+        }
+    }
+
+    fn input_page(&mut self, ui: &mut egui::Ui) {
+        let tab = &mut self.tabs[self.current];
+
+        let Some(input) = &mut tab.input else {
+            return;
+        };
+
+        let host = url::Url::parse(&input.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string));
+
+        ui.heading(&input.prompt);
+
+        let mut submit = false;
+
+        ui.horizontal(|ui| {
+            let field =
+                ui.add(egui::TextEdit::singleline(&mut input.text).password(input.sensitive));
+
+            if ui.memory().focus().is_none() {
+                field.request_focus();
+            }
+
+            submit = field.lost_focus() && ui.input().key_pressed(Key::Enter);
+            submit |= ui.button(tr("Send")).clicked();
+        });
+
+        if !input.sensitive
+            && ui
+                .checkbox(
+                    &mut self.config.remember_inputs,
+                    tr("Remember what I type into prompts"),
+                )
+                .changed()
+        {
+            self.config.save();
+        }
+
+        if let Some(host) = host.as_ref().filter(|_| !input.sensitive) {
+            if self.config.remember_inputs {
+                let settings = self.config.sites.entry(host.clone()).or_default();
+
+                if ui
+                    .checkbox(
+                        &mut settings.forget_inputs,
+                        trf("...except on {host}", &[("host", host)]),
+                    )
+                    .changed()
+                {
+                    self.config.save();
+                }
+            }
+        }
+
+        if submit && input.sensitive {
+            // keep the input out of the history and the url bar
+            let url = input::with_query(&input.url, &input.text);
+            let shown = input::without_query(&input.url).to_string();
+
+            self.change_site(&url, true);
+            self.tab_mut().url = shown;
+        } else if submit {
+            if self.config.remember_inputs(host.as_deref()) {
+                self.inputs.remember(&input.url, &input.text);
+            } else {
+                self.inputs.forget(&input.url);
+            }
+
+            let url = input::with_query(&input.url, &input.text);
+            self.change_site(&url, false);
+        }
+    }
+
+    fn blocked_page(&mut self, ui: &mut egui::Ui) {
+        let Some(host) = self.tab().blocked.clone() else {
+            return;
+        };
+
+        ui.heading(tr("🚫 Blocked"));
+        ui.label(trf(
+            "{host} is on your blocklist, so gbrowse didn't connect to it.",
+            &[("host", &host)],
+        ));
+
+        ui.horizontal(|ui| {
+            let visit = ui.button(tr("Visit anyway")).clicked();
+            let unblock = ui
+                .button(trf("Unblock {host}", &[("host", &host)]))
+                .clicked();
+
+            if unblock {
+                self.config.unblock(&host);
+                self.config.save();
+            }
+
+            if visit || unblock {
+                self.tab_mut().blocked = None;
+
+                if let Ok(url) = Url::try_from(self.tab().url.as_str()) {
+                    self.request(self.current, url, false);
+                }
+            }
+        });
+    }
+
+    /// Keep the current tab at the front of the most recently used list.
+    fn update_recent(&mut self) {
+        let id = self.tab().id;
+        let tabs = &self.tabs;
+
+        self.recent
+            .retain(|recent| *recent != id && tabs.iter().any(|tab| tab.id == *recent));
+        self.recent.insert(0, id);
+    }
+
+    /// Ctrl+tab through the tabs in the order they were last used, like
+    /// alt-tab. The tab is switched to when ctrl is let go.
+    fn tab_switcher(&mut self, ctx: &egui::Context) {
+        let len = self.recent.len();
+
+        if shortcuts::pressed(ctx, Action::PreviousTab) {
+            let selected = self.switcher.unwrap_or(0);
+            self.switcher = Some((selected + len - 1) % len);
+        }
+
+        if shortcuts::pressed(ctx, Action::NextTab) {
+            let selected = self.switcher.map_or(1, |selected| selected + 1);
+            self.switcher = Some(selected % len);
+        }
+
+        let Some(selected) = self.switcher else {
+            return;
+        };
+
+        if ctx.input_mut().consume_key(Modifiers::NONE, Key::Escape) {
+            self.switcher = None;
+            return;
+        }
+
+        let mut chosen = None;
+
+        if !ctx.input().modifiers.command {
+            chosen = Some(selected);
+        }
+
+        egui::Area::new("tab switcher")
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    for (index, id) in self.recent.iter().enumerate() {
+                        let Some(tab) = self.tabs.iter().find(|tab| tab.id == *id) else {
+                            continue;
+                        };
+
+                        if ui
+                            .selectable_label(index == selected, tab.title())
+                            .clicked()
+                        {
+                            chosen = Some(index);
+                        }
+                    }
+                });
+            });
+
+        if let Some(index) = chosen {
+            let id = self.recent[index];
+
+            self.switcher = None;
+            self.current = self
+                .tabs
+                .iter()
+                .position(|tab| tab.id == id)
+                .unwrap_or(self.current);
+        }
+    }
+
+    /// Add or remove a bookmark for the current page.
+    fn toggle_bookmark(&mut self) {
+        let Some(url) = self.tab().sites.last().cloned() else {
+            return;
+        };
+
+        if self.bookmarks.get(&url).is_some() {
+            self.bookmarks.remove(&url);
+        } else {
+            let title = self.tab().title();
+            self.bookmarks.add(&url, &title);
+        }
+    }
+
+    /// Save every open tab into a new bookmarks folder.
+    fn bookmark_all_tabs(&mut self) {
+        let pages = self
+            .tabs
+            .iter()
+            .filter_map(|tab| Some((tab.sites.last()?.clone(), tab.title())))
+            .collect();
+
+        let folder = self.bookmarks.add_folder(&tr("Open tabs"), pages);
+
+        self.bookmark_filter = folder;
+        self.show_bookmarks = true;
+    }
+
+    /// The sync button, and which side to keep when both changed.
+    fn sync_ui(&self, ui: &mut egui::Ui) -> Option<Direction> {
+        let mut sync = None;
+
+        ui.horizontal(|ui| {
+            let enabled = self.config.bookmark_sync.is_some() && self.syncing.is_none();
+
+            if ui
+                .add_enabled(enabled, egui::Button::new(tr("🔄 Sync")))
+                .on_hover_text(tr("Sync the bookmarks with your capsule"))
+                .on_disabled_hover_text(tr(
+                    "Set bookmark_sync to a titan:// url on about:config to sync",
+                ))
+                .clicked()
+            {
+                sync = Some(Direction::Both);
+            }
+
+            if self.sync_conflict && self.syncing.is_none() {
+                if ui.button(tr("Keep these")).clicked() {
+                    sync = Some(Direction::Push);
+                }
+
+                if ui.button(tr("Keep the synced ones")).clicked() {
+                    sync = Some(Direction::Pull);
+                }
+            }
+        });
+
+        if let Some(status) = &self.sync_status {
+            ui.weak(status);
+        }
+
+        sync
+    }
+
+    /// Take in the result of a bookmark sync once it's done.
+    fn poll_sync(&mut self, ctx: &egui::Context) {
+        let Some(rx) = &self.syncing else {
+            return;
+        };
+
+        let result = match rx.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => {
+                ctx.request_repaint_after(Duration::from_millis(100));
+                return;
+            }
+            Err(TryRecvError::Disconnected) => Err(tr("The sync stopped unexpectedly.")),
+        };
+
+        self.syncing = None;
+
+        let (status, synced) = match result {
+            Ok(Outcome::UpToDate(bookmarks)) => (tr("Bookmarks are up to date."), Some(bookmarks)),
+            Ok(Outcome::Pushed(bookmarks)) => (tr("Bookmarks uploaded."), Some(bookmarks)),
+            Ok(Outcome::Pulled(bookmarks)) => {
+                self.bookmarks.replace(bookmarks.clone());
+                (tr("Bookmarks downloaded."), Some(bookmarks))
+            }
+            Ok(Outcome::Conflict) => {
+                self.sync_conflict = true;
+                (
+                    tr("The bookmarks changed here and on the capsule since the last sync."),
+                    None,
+                )
+            }
+            Err(err) => (err, None),
+        };
+
+        if let Some(bookmarks) = synced {
+            bookmark_sync::remember(&bookmarks);
+        }
+
+        self.sync_status = Some(status);
+    }
+
+    fn bookmarks_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_bookmarks;
+        let mut clicked = None;
+        let mut bookmark_all = false;
+        let mut sync = None;
+        let current = self.tab().sites.last().cloned().unwrap_or_default();
+
+        egui::Window::new(tr("Bookmarks"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                if self.bookmarks.get(&current).is_some() {
+                    egui::CollapsingHeader::new(tr("This page")).show(ui, |ui| {
+                        self.bookmarks.edit_ui(ui, &current);
+                    });
+                    ui.separator();
+                }
+
+                if ui
+                    .checkbox(
+                        &mut self.config.bookmarks_toolbar,
+                        tr("Show bookmarks toolbar"),
+                    )
+                    .on_hover_text(trf(
+                        "Bookmarks in the \"{folder}\" folder",
+                        &[("folder", &TOOLBAR_FOLDER)],
+                    ))
+                    .changed()
+                {
+                    self.config.save();
+                }
+
+                if ui.button(tr("Bookmark all tabs")).clicked() {
+                    bookmark_all = true;
+                }
+
+                sync = self.sync_ui(ui);
+
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.bookmark_filter)
+                        .hint_text(tr("Filter, or #tag")),
+                );
+
+                ScrollArea::vertical().show(ui, |ui| {
+                    clicked = self.bookmarks.ui(ui, &self.bookmark_filter);
+                });
+            });
+
+        self.show_bookmarks = open;
+
+        if bookmark_all {
+            self.bookmark_all_tabs();
+        }
+
+        if let (Some(direction), Some(url)) = (sync, self.config.bookmark_sync.clone()) {
+            self.syncing = Some(bookmark_sync::start(
+                direction,
+                url,
+                self.config.bookmark_sync_token.clone(),
+                self.bookmarks.all().to_vec(),
+            ));
+            self.sync_status = Some(tr("Syncing…"));
+            self.sync_conflict = false;
+        }
+
+        if let Some(url) = clicked {
+            self.change_site(&url, false);
+        }
+    }
+
+    fn about_page(&mut self, ui: &mut egui::Ui) {
+        let Some(page) = self
+            .tab()
+            .sites
+            .last()
+            .filter(|url| url.starts_with("about:"))
+        else {
+            return;
+        };
+
+        match page.as_str() {
+            "about:downloads" => self.downloads_page(ui),
+            "about:cache" => self.cache_page(ui),
+            "about:requests" => self.requests_page(ui),
+            "about:debug" => self.debug_page(ui),
+            "about:config" => self.config_page(ui),
+            // shown like any other page
+            welcome::URL => {}
+            page => {
+                ui.heading(tr("⚠ Unknown page"));
+                ui.label(trf(
+                    "gbrowse doesn't have a page called {page}.",
+                    &[("page", &page)],
+                ));
+            }
+        }
+    }
+
+    fn downloads_page(&mut self, ui: &mut egui::Ui) {
+        ui.heading(tr("Downloads"));
+
+        ui.horizontal(|ui| {
+            ui.label(tr("Save downloads to"));
+
+            if self.download_dir.is_empty() {
+                self.download_dir = self.config.download_dir().display().to_string();
+            }
+
+            if ui.text_edit_singleline(&mut self.download_dir).lost_focus() {
+                let dir = PathBuf::from(self.download_dir.trim());
+
+                self.config.download_dir = Some(dir).filter(|dir| *dir != downloads::default_dir());
+                self.config.save();
+            }
+        });
+
+        ui.separator();
+        self.downloads.ui(ui);
+    }
+
+    /// Every setting, for the ones that don't have a place elsewhere.
+    fn config_page(&mut self, ui: &mut egui::Ui) {
+        ui.heading(tr("Advanced settings"));
+        ui.label(tr(
+            "Everything in config.ron. Changes are saved right away. Lists and maps are written as JSON.",
+        ));
+
+        ui.add(egui::TextEdit::singleline(&mut self.config_filter).hint_text(tr("🔍 Search")));
+
+        if let Some(err) = &self.config_error {
+            ui.colored_label(ui.visuals().error_fg_color, err);
+        }
+
+        ui.separator();
+
+        let filter = self.config_filter.to_lowercase();
+        let mut changes = vec![];
+        let mut save = false;
+
+        ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("config")
+                .num_columns(3)
+                .striped(true)
+                .show(ui, |ui| {
+                    for setting in settings::settings(&self.config) {
+                        if !setting.key.to_lowercase().contains(&filter) {
+                            continue;
+                        }
+
+                        if setting.is_default() {
+                            ui.monospace(&setting.key);
+                        } else {
+                            ui.label(RichText::new(&setting.key).monospace().strong())
+                                .on_hover_text(tr("Changed from the default"));
+                        }
+
+                        match &setting.value {
+                            Value::Bool(value) => {
+                                let mut value = *value;
+
+                                if ui.checkbox(&mut value, "").changed() {
+                                    changes.push((setting.key.clone(), vec![Value::Bool(value)]));
+                                    save = true;
+                                }
+                            }
+                            Value::Number(number) => {
+                                // whole numbers stay whole, or they won't fit an integer setting
+                                let (response, value) = match number.as_u64() {
+                                    Some(mut value) => (
+                                        ui.add(egui::DragValue::new(&mut value)),
+                                        Value::from(value),
+                                    ),
+                                    None => {
+                                        let mut value = number.as_f64().unwrap_or_default();
+                                        let response = ui.add(
+                                            egui::DragValue::new(&mut value)
+                                                .speed(0.01)
+                                                .max_decimals(3),
+                                        );
+
+                                        (response, Value::from(value))
+                                    }
+                                };
+
+                                if response.changed() {
+                                    changes.push((setting.key.clone(), vec![value]));
+                                }
+
+                                save |= response.drag_released() || response.lost_focus();
+                            }
+                            value => {
+                                let mut text = self
+                                    .config_edits
+                                    .get(&setting.key)
+                                    .cloned()
+                                    .unwrap_or_else(|| settings::to_text(value));
+
+                                let response = ui.text_edit_singleline(&mut text);
+
+                                if response.lost_focus() {
+                                    self.config_edits.remove(&setting.key);
+                                    changes.push((setting.key.clone(), settings::from_text(&text)));
+                                    save = true;
+                                } else if response.has_focus() {
+                                    self.config_edits.insert(setting.key.clone(), text);
+                                }
+                            }
+                        }
+
+                        if ui
+                            .add_enabled(!setting.is_default(), egui::Button::new("↺"))
+                            .on_hover_text(tr("Reset to the default"))
+                            .clicked()
+                        {
+                            changes.push((setting.key.clone(), vec![setting.default.clone()]));
+                            save = true;
+                        }
+
+                        ui.end_row();
+                    }
+                });
+        });
+
+        for (key, values) in changes {
+            let mut result = Err(String::new());
+
+            for value in values {
+                result = settings::set(&self.config, &key, value);
+
+                if result.is_ok() {
+                    break;
+                }
+            }
+
+            match result {
+                Ok(config) => {
+                    self.config = config;
+                    self.config_error = None;
+                }
+                Err(err) => self.config_error = Some(err),
+            }
+        }
+
+        if save {
+            self.cache.apply(&self.config.cache);
+
+            if let Some(scale) = self.config.ui_scale {
+                self.set_ui_scale(scale);
+            }
+
+            self.config.save();
+        }
+    }
+
+    /// How full the cache is, and how big it may get.
+    fn cache_page(&mut self, ui: &mut egui::Ui) {
+        ui.heading(tr("Cache"));
+
+        let usage = |(pages, size): (usize, u64)| {
+            trf(
+                "{pages} pages, {size}",
+                &[("pages", &pages), ("size", &downloads::format_bytes(size))],
+            )
+        };
+
+        let memory = usage(self.cache.memory_usage());
+        let disk = usage(self.cache.disk_usage());
+        let settings = &mut self.config.cache;
+        let mut responses = vec![];
+
+        egui::Grid::new("cache").num_columns(3).show(ui, |ui| {
+            ui.label(tr("In memory"));
+            ui.label(memory);
+            responses.push(
+                ui.add(
+                    egui::DragValue::new(&mut settings.memory_size)
+                        .clamp_range(0..=4096)
+                        .prefix(tr("up to "))
+                        .suffix(" MiB"),
+                ),
+            );
+            ui.end_row();
+
+            ui.label(tr("On disk"));
+            ui.label(disk);
+            responses.push(
+                ui.add(
+                    egui::DragValue::new(&mut settings.disk_size)
+                        .clamp_range(0..=65536)
+                        .prefix(tr("up to "))
+                        .suffix(" MiB"),
+                ),
+            );
+            ui.end_row();
+
+            ui.label(tr("Fetch pages again after"));
+            ui.label("");
+            responses.push(
+                ui.add(
+                    egui::DragValue::new(&mut settings.max_age)
+                        .clamp_range(0..=60 * 24 * 30)
+                        .suffix(tr(" minutes")),
+                ),
+            );
+            ui.end_row();
+        });
+
+        if responses.iter().any(|response| response.changed()) {
+            self.cache.apply(&self.config.cache);
+        }
+
+        if responses
+            .iter()
+            .any(|response| response.drag_released() || response.lost_focus())
+        {
+            self.config.save();
+        }
+
+        if ui.button(tr("🗑 Clear cache")).clicked() {
+            self.cache.clear();
+            self.prefetcher.clear();
+        }
+    }
+
+    /// Every request made this session, newest first.
+    fn requests_page(&mut self, ui: &mut egui::Ui) {
+        ui.heading(tr("Requests"));
+
+        ui.horizontal(|ui| {
+            if ui.button(tr("💾 Export as JSON")).clicked() {
+                self.export_request_log();
+            }
+
+            if ui.button(tr("🗑 Clear")).clicked() {
+                self.request_log.clear();
+            }
+        });
+
+        ui.separator();
+
+        egui::Grid::new("requests").striped(true).show(ui, |ui| {
+            for entry in self.request_log.entries().iter().rev() {
+                ui.label(
+                    entry
+                        .status
+                        .map(|status| status.to_string())
+                        .unwrap_or_default(),
+                );
+                ui.label(&entry.url);
+
+                match &entry.error {
+                    Some(err) => ui.colored_label(ui.visuals().error_fg_color, err),
+                    None => ui.label(&entry.meta),
+                };
+
+                ui.label(
+                    entry
+                        .size
+                        .map(|size| downloads::format_bytes(size as u64))
+                        .unwrap_or_default(),
+                );
+                ui.label(
+                    entry
+                        .timing
+                        .as_ref()
+                        .and_then(|timing| timing.total)
+                        .map(|total| format!("{total} ms"))
+                        .unwrap_or_default(),
+                );
+                ui.end_row();
+            }
+        });
+    }
+
+    /// What's going on inside gbrowse right now, for bug reports.
+    fn debug_page(&mut self, ui: &mut egui::Ui) {
+        ui.heading(tr("Debug"));
+
+        ui.label(RichText::new(tr("Tabs")).strong());
+
+        egui::Grid::new("debug tabs").striped(true).show(ui, |ui| {
+            for (index, tab) in self.tabs.iter().enumerate() {
+                let state = if tab.loading {
+                    tr("loading")
+                } else if tab.error.is_some() {
+                    tr("error")
+                } else if tab.blocked.is_some() {
+                    tr("blocked")
+                } else if tab.input.is_some() {
+                    tr("waiting for input")
+                } else if tab.book.is_some() {
+                    tr("book")
+                } else if let Some(page) = &tab.page {
+                    trf("{nodes} lines", &[("nodes", &page.nodes.len())])
+                } else {
+                    String::new()
+                };
+
+                ui.label(if index == self.current {
+                    format!("▶ {}", tab.id)
+                } else {
+                    tab.id.to_string()
+                });
+                ui.label(tab.sites.last().unwrap_or(&tab.url));
+                ui.label(state);
+                ui.label(trf(
+                    "{back} back, {forward} forward, {snapshots} kept",
+                    &[
+                        ("back", &tab.sites.len().saturating_sub(1)),
+                        ("forward", &tab.forward.len()),
+                        ("snapshots", &tab.snapshots.len()),
+                    ],
+                ));
+                ui.label(if tab.pinned {
+                    tr("pinned")
+                } else {
+                    String::new()
+                });
+                ui.end_row();
+            }
+        });
+
+        ui.separator();
+        ui.label(RichText::new(tr("Background work")).strong());
+
+        egui::Grid::new("debug work").show(ui, |ui| {
+            let loading = self.tabs.iter().filter(|tab| tab.loading).count();
+
+            for (name, count) in [
+                (tr("Pages loading"), loading),
+                (tr("Downloads"), self.downloads.active()),
+                (tr("Links prefetching"), self.prefetcher.active()),
+            ] {
+                ui.label(name);
+                ui.label(count.to_string());
+                ui.end_row();
+            }
+        });
+
+        ui.separator();
+        ui.label(RichText::new(tr("Cache")).strong());
+
+        let pages = self.cache.memory_entries();
+        let (disk_pages, disk_size) = self.cache.disk_usage();
+
+        ui.label(trf(
+            "{pages} pages in memory, {disk_pages} on disk ({size})",
+            &[
+                ("pages", &pages.len()),
+                ("disk_pages", &disk_pages),
+                ("size", &downloads::format_bytes(disk_size)),
+            ],
+        ));
+
+        egui::CollapsingHeader::new(tr("Pages in memory")).show(ui, |ui| {
+            egui::Grid::new("debug cache").show(ui, |ui| {
+                for (url, size) in &pages {
+                    ui.label(url);
+                    ui.label(downloads::format_bytes(*size));
+                    ui.end_row();
+                }
+            });
+        });
+
+        ui.separator();
+        ui.label(RichText::new(tr("Certificates")).strong());
+        ui.label(trf(
+            "{count} capsules have a pinned certificate",
+            &[("count", &transport::pinned_count())],
+        ));
+
+        ui.separator();
+        ui.label(RichText::new(tr("Recent requests")).strong());
+
+        for entry in self
+            .request_log
+            .entries()
+            .iter()
+            .rev()
+            .take(DEBUG_LOG_LINES)
+        {
+            let line = match (&entry.error, entry.status) {
+                (Some(err), _) => format!("{}: {err}", entry.url),
+                (None, Some(status)) => format!("{}: {status} {}", entry.url, entry.meta),
+                (None, None) => entry.url.clone(),
+            };
+
+            ui.monospace(line);
+        }
+    }
+
+    /// Save the request log as JSON in the downloads folder.
+    fn export_request_log(&mut self) {
+        let result = self.request_log.to_json().and_then(|json| {
+            self.downloads.downloader(&self.config).start(
+                "about:requests",
+                "gbrowse-requests.json",
+                Box::new(Cursor::new(json.into_bytes())),
+                MimeAction::Download,
+            )
+        });
+
+        if let Err(err) = result {
+            eprintln!("{err}");
+        }
+    }
+
+    /// Read a gempub book a chapter at a time.
+    fn book_page(&mut self, ui: &mut egui::Ui) {
+        let theme = self.page_theme();
+        let tab = &mut self.tabs[self.current];
+
+        let Some(book) = &tab.book else {
+            return;
+        };
+
+        let mut chapter = tab.chapter;
+        let mut clicked = None;
+
+        ui.horizontal(|ui| {
+            ui.heading(&book.title);
+
+            if let Some(author) = &book.author {
+                ui.label(trf("by {author}", &[("author", author)]));
+            }
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button(tr("📖 Contents")).clicked() {
+                chapter = None;
+            }
+
+            let previous = match chapter {
+                Some(0) => Some(None),
+                Some(n) => Some(Some(n - 1)),
+                None => None,
+            };
+
+            if ui
+                .add_enabled(previous.is_some(), egui::Button::new(tr("◀ Previous")))
+                .clicked()
+            {
+                chapter = previous.flatten();
+            }
+
+            let current = chapter.map_or(tr("Contents"), |n| book.chapters[n].title.clone());
+
+            egui::ComboBox::from_id_source("chapter")
+                .selected_text(current)
+                .show_ui(ui, |ui| {
+                    for (n, item) in book.chapters.iter().enumerate() {
+                        ui.selectable_value(&mut chapter, Some(n), &item.title);
+                    }
+                });
+
+            let next = chapter.map_or(0, |n| n + 1);
+
+            if ui
+                .add_enabled(next < book.chapters.len(), egui::Button::new(tr("Next ▶")))
+                .clicked()
+            {
+                chapter = Some(next);
+            }
+        });
+
+        ui.separator();
+
+        let nodes = match tab.chapter {
+            Some(n) => &book.chapters[n].nodes,
+            None => &book.index,
+        };
+
+        ScrollArea::vertical()
+            .id_source(("book", tab.id, tab.chapter))
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                ui.add(
+                    GemtextView::new(nodes)
+                        .theme(theme)
+                        .plugins(&self.plugins)
+                        .on_link(|url| clicked = Some(url.to_string())),
+                );
+            });
+
+        // links between chapters stay in the book
+        if let Some(link) = clicked {
+            match book.chapter_for(tab.chapter, &link) {
+                Some(n) => chapter = Some(n),
+                None => self.follow_link(ui.ctx(), &link),
+            }
+        }
+
+        self.tab_mut().chapter = chapter;
+    }
+
+    /// Controls for whatever audio is playing, shown under every tab.
+    fn player_bar(&mut self, ctx: &egui::Context) {
+        let Some(player) = &mut self.player else {
+            return;
+        };
+
+        let mut stop = false;
+
+        egui::TopBottomPanel::bottom("player").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let paused = player.is_paused() || player.is_finished();
+
+                if ui.button(if paused { "▶" } else { "⏸" }).clicked() {
+                    player.toggle();
+                }
+
+                ui.label(&player.name);
+
+                let position = player.position();
+                let text = match player.duration() {
+                    Some(duration) => {
+                        format!(
+                            "{} / {}",
+                            audio::format_time(position),
+                            audio::format_time(duration)
+                        )
+                    }
+                    None => audio::format_time(position),
+                };
+
+                let progress = player.duration().map_or(0.0, |duration| {
+                    position.as_secs_f32() / duration.as_secs_f32().max(1.0)
+                });
+
+                ui.add(
+                    egui::ProgressBar::new(progress)
+                        .text(text)
+                        .desired_width(200.0),
+                );
+
+                stop = ui.button("✖").on_hover_text(tr("Stop")).clicked();
+            });
+        });
+
+        if stop {
+            self.player = None;
+        } else if !player.is_paused() && !player.is_finished() {
+            ctx.request_repaint_after(Duration::from_millis(500));
+        }
+    }
+
+    fn page_info(&mut self, ctx: &egui::Context) {
+        let Some(page) = &self.tabs[self.current].page else {
+            return;
+        };
+
+        egui::Window::new(tr("Page info"))
+            .open(&mut self.show_page_info)
+            .show(ctx, |ui| {
+                ui.label(RichText::new(&page.url).strong());
+
+                if !page.redirects.is_empty() {
+                    ui.separator();
+                    ui.label(tr("Redirected from:"));
+
+                    egui::Grid::new("redirects").show(ui, |ui| {
+                        for redirect in &page.redirects {
+                            ui.label(redirect.status.to_string());
+                            ui.label(&redirect.url);
+                            ui.end_row();
+                        }
+                    });
+                }
+
+                ui.separator();
+
+                egui::Grid::new("response").show(ui, |ui| {
+                    if let Some(mime) = &page.mime {
+                        let (kind, charset) = mime_and_charset(mime);
+
+                        ui.label(tr("Type"));
+                        ui.label(kind);
+                        ui.end_row();
+
+                        ui.label(tr("Charset"));
+                        ui.label(charset);
+                        ui.end_row();
+
+                        ui.label(tr("Size"));
+                        ui.label(downloads::format_bytes(page.size as u64));
+                        ui.end_row();
+                    }
+
+                    ui.label(tr("Certificate"));
+                    match &page.certificate {
+                        Some(certificate) => {
+                            let status = match certificate.trust {
+                                Trust::New => RichText::new(tr("First visit, trusted from now on")),
+                                Trust::Known => RichText::new(tr("Same as on earlier visits")),
+                                Trust::Local => {
+                                    RichText::new(tr("Not pinned for capsules on this computer"))
+                                }
+                                Trust::Changed => {
+                                    RichText::new(tr("⚠ Changed since earlier visits"))
+                                        .color(ui.visuals().warn_fg_color)
+                                }
+                            };
+
+                            ui.label(status).on_hover_text(trf(
+                                "SHA-256 fingerprint: {fingerprint}",
+                                &[("fingerprint", &certificate.fingerprint)],
+                            ));
+                        }
+                        None if page.url.starts_with("mercury://") => {
+                            ui.label(tr("None, mercury doesn't use TLS"));
+                        }
+                        None if page.timing.is_none() && page.mime.is_some() => {
+                            ui.label(tr("Not known for pages from the cache"));
+                        }
+                        None => {
+                            ui.label(tr("None"));
+                        }
+                    }
+                    ui.end_row();
+
+                    ui.label(tr("Identity"));
+                    match &page.identity {
+                        Some(identity) => ui.label(identity),
+                        None => ui.label(tr("None")),
+                    };
+                    ui.end_row();
+                });
+
+                if let Some(certificate) = &page.certificate {
+                    if ui.small_button(tr("📋 Copy fingerprint")).clicked() {
+                        ui.output().copied_text = certificate.fingerprint.clone();
+                    }
+                }
+
+                ui.separator();
+
+                let Some(timing) = &page.timing else {
+                    match page.mime {
+                        Some(_) => ui.label(tr("Loaded from the cache.")),
+                        None => ui.label(tr("Built into gbrowse.")),
+                    };
+                    return;
+                };
+
+                egui::Grid::new("timing").show(ui, |ui| {
+                    for (step, time) in timing_steps(timing) {
+                        ui.label(step);
+                        ui.label(format_duration(time));
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+
+    /// The tab bar, url bar, breadcrumbs and bookmarks, all hidden in
+    /// reading mode.
+    fn toolbars(&mut self, ui: &mut egui::Ui) {
+        self.tab_bar(ui);
+
+        // search bar
+        ScrollArea::horizontal()
+            .id_source("horizontal scroll")
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    if self.tab().sites.len() > 1 && ui.button("⏪").clicked() {
+                        self.go_back();
+                    }
+
+                    if !self.tab().forward.is_empty() && ui.button("⏩").clicked() {
+                        self.go_forward();
+                    }
+
+                    for item in self.config.toolbar.items() {
+                        self.toolbar_item(ui, item);
+                    }
+
+                    let zoom = self.page_theme().zoom;
+
+                    if zoom != 1.0
+                        && ui
+                            .button(format!("{:.0}%", zoom * 100.0))
+                            .on_hover_text(tr("Reset zoom"))
+                            .clicked()
+                    {
+                        self.set_zoom(1.0);
+                    }
+
+                    if self.tab().loading {
+                        ui.label(tr("loading..."));
+                    }
+                });
+            });
+
+        // breadcrumbs for moving up the capsule
+        let crumbs = nav::breadcrumbs(self.tab().sites.last().map_or("", String::as_str));
+
+        if !crumbs.is_empty() {
+            let mut clicked = None;
+
+            ui.horizontal_wrapped(|ui| {
+                ui.spacing_mut().item_spacing.x = 2.0;
+
+                for (i, (name, url)) in crumbs.iter().enumerate() {
+                    if i > 0 {
+                        ui.weak("/");
+                    }
+
+                    if ui.small_button(name).on_hover_text(url).clicked() {
+                        clicked = Some(url.clone());
+                    }
+                }
+            });
+
+            if let Some(url) = clicked {
+                self.change_site(&url, false);
+            }
+        }
+
+        // bookmarks toolbar
+        if self.config.bookmarks_toolbar {
+            let mut clicked = None;
+
+            ui.horizontal_wrapped(|ui| {
+                for bookmark in self.bookmarks.in_folder(TOOLBAR_FOLDER) {
+                    if ui
+                        .button(&bookmark.title)
+                        .on_hover_text(&bookmark.url)
+                        .clicked()
+                    {
+                        clicked = Some(bookmark.url.clone());
+                    }
+                }
+            });
+
+            if let Some(url) = clicked {
+                self.change_site(&url, false);
+            }
+        }
+
+        ui.separator();
+    }
+
+    fn toolbar_item(&mut self, ui: &mut egui::Ui, item: ToolbarItem) {
+        match item {
+            ToolbarItem::Home => {
+                if ui.button("🏠").on_hover_text(tr("Home")).clicked() {
+                    let home = self.config.home_page().to_string();
+                    self.change_site(&home, false);
+                }
+            }
+            ToolbarItem::Reload => {
+                if !self.tab().sites.is_empty()
+                    && !self.tab().loading
+                    && ui.button("🔄").on_hover_text(tr("Reload")).clicked()
+                {
+                    self.retry(self.current);
+                }
+            }
+            ToolbarItem::Up => {
+                let current = self.tab().sites.last().cloned().unwrap_or_default();
+
+                if nav::parent(&current).is_some() {
+                    if ui.button("⬆").on_hover_text(tr("Up a directory")).clicked() {
+                        self.go_up(false);
+                    }
+
+                    if ui.button("⏫").on_hover_text(tr("Capsule root")).clicked() {
+                        self.go_up(true);
+                    }
+                }
+            }
+            ToolbarItem::Url => {
+                if let Some(icon) = self.favicon(self.current) {
+                    ui.label(icon);
+                }
+
+                let focus_url = shortcuts::pressed(ui.ctx(), Action::FocusUrl);
+                let field = ui.text_edit_singleline(&mut self.tab_mut().url);
+                let enter = field.lost_focus() && ui.input().key_pressed(Key::Enter);
+
+                if focus_url {
+                    field.request_focus();
+                }
+
+                self.select_url(ui, &field, focus_url);
+
+                // escape throws away what was typed
+                if field.lost_focus() && ui.input().key_pressed(Key::Escape) {
+                    if let Some(url) = self.tab().sites.last().cloned() {
+                        self.tab_mut().url = url;
+                    }
+                }
+
+                self.subscribe_button(ui);
+
+                if ui.button("🚀").clicked() || enter {
+                    self.go_to(&self.tab().url.clone());
+                }
+
+                self.suggestions(ui, &field);
+            }
+            ToolbarItem::PageInfo => {
+                ui.toggle_value(&mut self.show_page_info, "ℹ")
+                    .on_hover_text(tr("Page info"));
+            }
+            ToolbarItem::Bookmark => {
+                let bookmarked = self
+                    .tab()
+                    .sites
+                    .last()
+                    .is_some_and(|url| self.bookmarks.get(url).is_some());
+
+                if ui
+                    .selectable_label(bookmarked, if bookmarked { "★" } else { "☆" })
+                    .on_hover_text(tr("Bookmark this page"))
+                    .clicked()
+                {
+                    self.toggle_bookmark();
+                }
+            }
+            ToolbarItem::Share => {
+                ui.menu_button("🔗", |ui| self.share_menu(ui))
+                    .response
+                    .on_hover_text(tr("Share"));
+            }
+            ToolbarItem::AutoRefresh => self.auto_refresh_menu(ui),
+            ToolbarItem::Bookmarks => {
+                ui.toggle_value(&mut self.show_bookmarks, "📚")
+                    .on_hover_text(tr("Bookmarks"));
+            }
+            ToolbarItem::Sessions => {
+                ui.toggle_value(&mut self.show_sessions, "🗂")
+                    .on_hover_text(tr("Sessions"));
+            }
+            ToolbarItem::Window => {
+                ui.menu_button("🗖", |ui| self.window_menu(ui))
+                    .response
+                    .on_hover_text(tr("Window"));
+            }
+            ToolbarItem::Downloads => {
+                if ui.button("📥").on_hover_text(tr("Downloads")).clicked() {
+                    self.change_site("about:downloads", false);
+                }
+            }
+            ToolbarItem::Subscriptions => {
+                let label = match self.subscriptions.unread_count() {
+                    0 => "📰".to_string(),
+                    unread => format!("📰 {unread}"),
+                };
+
+                let hover = match self.subscriptions.oldest_unread() {
+                    Some(entry) => trf(
+                        "Next unread post: {title}. Right click for your subscriptions.",
+                        &[("title", &format!("{} {}", entry.date, entry.title))],
+                    ),
+                    None => tr("No unread posts. Right click for your subscriptions."),
+                };
+
+                let button = ui.button(label).on_hover_text(hover);
+
+                if button.clicked() {
+                    self.next_unread();
+                }
+
+                button.context_menu(|ui| self.subscriptions_menu(ui));
+            }
+            ToolbarItem::History => {
+                ui.toggle_value(&mut self.show_history, "🕘")
+                    .on_hover_text(tr("History"));
+            }
+            ToolbarItem::Watched => {
+                let label = match self.monitor.changed() {
+                    0 => "👁".to_string(),
+                    changed => format!("👁 {changed}"),
+                };
+
+                ui.toggle_value(&mut self.show_watched, label)
+                    .on_hover_text(tr("Watched pages"));
+            }
+        }
+    }
+
+    fn window_menu(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .checkbox(
+                &mut self.config.always_on_top,
+                tr("Keep above other windows"),
+            )
+            .on_hover_text(tr("Takes effect the next time gbrowse is started."))
+            .changed()
+        {
+            self.config.save();
+        }
+
+        ui.checkbox(&mut self.show_history, tr("🕘 History (Ctrl+H)"));
+        ui.checkbox(
+            &mut self.show_bookmarks_panel,
+            tr("📚 Bookmarks sidebar (Ctrl+B)"),
+        );
+        ui.checkbox(&mut self.show_links, tr("🔗 Links on this page"));
+        ui.checkbox(&mut self.show_outline, tr("☰ Outline (Ctrl+Shift+O)"));
+
+        if ui.button(tr("👁 Watched pages")).clicked() {
+            self.show_watched = true;
+            ui.close_menu();
+        }
+
+        if ui.button(tr("🔎 Search tabs")).clicked() {
+            self.show_tab_search = true;
+            ui.close_menu();
+        }
+
+        if ui.button(tr("📖 Reading mode (F11)")).clicked() {
+            self.reading_mode = true;
+            ui.close_menu();
+        }
+
+        ui.menu_button(tr("🔧 Toolbar"), |ui| self.toolbar_menu(ui));
+    }
+
+    /// Pick the toolbar's buttons and move them around.
+    fn toolbar_menu(&mut self, ui: &mut egui::Ui) {
+        let mut changed = false;
+
+        // the ones in the toolbar in their order, then the rest
+        let shown = self.config.toolbar.items();
+        let hidden = ToolbarItem::ALL.iter().filter(|item| !shown.contains(item));
+
+        for item in shown.iter().chain(hidden).copied() {
+            ui.horizontal(|ui| {
+                let mut shown = self.config.toolbar.contains(item);
+
+                if ui
+                    .add_enabled(
+                        item.removable(),
+                        egui::Checkbox::new(&mut shown, item.name()),
+                    )
+                    .changed()
+                {
+                    self.config.toolbar.toggle(item);
+                    changed = true;
+                }
+
+                if shown {
+                    if ui
+                        .small_button("⏴")
+                        .on_hover_text(tr("Move left"))
+                        .clicked()
+                    {
+                        self.config.toolbar.shift(item, -1);
+                        changed = true;
+                    }
+
+                    if ui
+                        .small_button("⏵")
+                        .on_hover_text(tr("Move right"))
+                        .clicked()
+                    {
+                        self.config.toolbar.shift(item, 1);
+                        changed = true;
+                    }
+                }
+            });
+        }
+
+        if ui.button(tr("Reset")).clicked() {
+            self.config.toolbar = Default::default();
+            changed = true;
+        }
+
+        if changed {
+            self.config.save();
+        }
+    }
+
+    /// Copy the current page's title and url for pasting elsewhere.
+    fn share_menu(&mut self, ui: &mut egui::Ui) {
+        let Some(url) = self.tab().sites.last() else {
+            ui.close_menu();
+            return;
+        };
+
+        let title = self.tab().title();
+
+        if ui.button(tr("📋 Copy title and url")).clicked() {
+            ui.output().copied_text = format!("{title} — {url}");
+            ui.close_menu();
+        }
+
+        if ui.button(tr("📋 Copy as gemtext link")).clicked() {
+            ui.output().copied_text = format!("=> {url} {title}");
+            ui.close_menu();
+        }
+
+        if self.tab().page.is_some() && ui.button(tr("📷 Save screenshot")).clicked() {
+            self.save_screenshot(ui.ctx());
+            ui.close_menu();
+        }
+    }
+
+    /// Save the whole page as a png in the downloads folder.
+    fn save_screenshot(&mut self, ctx: &egui::Context) {
+        let Some(page) = &self.tab().page else {
+            return;
+        };
+
+        let screenshot = Screenshot::render(
+            &page.nodes,
+            &self.page_theme(),
+            &self.plugins,
+            self.fonts.clone(),
+            ctx.style(),
+            ctx.available_rect().width(),
+            ctx.pixels_per_point(),
+        );
+
+        let name = format!("{}.png", self.tab().title().replace(['/', '\\'], "-"));
+
+        let result = screenshot.to_png().and_then(|png| {
+            self.downloads.downloader(&self.config).start(
+                &page.url,
+                &name,
+                Box::new(Cursor::new(png)),
+                MimeAction::Download,
+            )
+        });
+
+        if let Err(err) = result {
+            eprintln!("{err}");
+        }
+    }
+
+    /// Open a link from the current page, which may be relative to it.
+    pub fn follow_link(&mut self, ctx: &egui::Context, url: &str) {
+        // if full url
+        if let Ok(parsed_url) = url::Url::parse(url) {
+            if self.plugins.serves(parsed_url.scheme()) {
+                self.change_site(url, false);
+            } else {
+                ctx.output().open_url = Some(OpenUrl::new_tab(url));
+            }
+        } else {
+            // relative to the page that's loaded, like the link's tooltip,
+            // not whatever has been typed into the url bar
+            let base = match &self.tab().page {
+                Some(page) => page.url.clone(),
+                None => self.tab().sites.last().cloned().unwrap_or_default(),
+            };
+
+            if let Some(url) = nav::resolve(&base, url) {
+                self.change_site(&url, false);
+            }
+        }
+    }
+}
+
+impl eframe::App for Gbrowse {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        Session::remove_autosaved();
+
+        if self.window != Geometry::default() {
+            self.window.save();
+        }
+    }
+
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let info = frame.info();
+
+        // a minimized window has no size worth remembering, and reading mode
+        // shouldn't be what the window opens in next time
+        if let (Some(native), true, false) = (
+            info.native_pixels_per_point,
+            info.window_info.size.min_elem() > 1.0,
+            self.reading_mode,
+        ) {
+            self.window
+                .update(&info.window_info, ctx.pixels_per_point(), native);
+        }
+
+        let pixels_per_point = info.native_pixels_per_point.unwrap_or(1.0) * self.ui_scale;
+
+        if ctx.pixels_per_point() != pixels_per_point {
+            ctx.set_pixels_per_point(pixels_per_point);
+        }
+
+        if self.font_settings != self.config.fonts {
+            self.load_fonts(ctx);
+        }
+
+        if self.language != self.config.language {
+            self.language = self.config.language.clone();
+            i18n::set_language(self.language.as_deref());
+        }
+
+        if self.resolver != self.config.resolver {
+            self.resolver = self.config.resolver.clone();
+            dns::set_resolver(self.resolver.as_deref());
+        }
+
+        if self.ui_theme != Some(self.config.theme) {
+            self.ui_theme = Some(self.config.theme);
+
+            let dark = match self.config.theme {
+                Theme::System => info.system_theme != Some(eframe::Theme::Light),
+                Theme::Dark => true,
+                Theme::Light => false,
+            };
+
+            ctx.set_visuals(if dark {
+                Visuals::dark()
+            } else {
+                Visuals::light()
+            });
+        }
+
+        if self.ip_version != self.config.ip_version {
+            self.ip_version = self.config.ip_version;
+            dns::set_ip_version(self.ip_version);
+        }
+
+        if self.identities != self.config.identities() {
+            self.identities = self.config.identities();
+            transport::set_identities(&self.identities);
+        }
+
+        ctx.options().screen_reader = self.config.screen_reader;
+
+        // ctrl+scroll or pinch to zoom, ctrl+0 to reset
+        let zoom_delta = ctx.input().zoom_delta();
+
+        if zoom_delta != 1.0 {
+            let anchor = ctx
+                .input()
+                .multi_touch()
+                .map(|touch| touch.start_pos)
+                .or_else(|| ctx.input().pointer.hover_pos());
+
+            self.zoom_by(zoom_delta, anchor);
+        } else if self.zooming {
+            self.zooming = false;
+            self.config.save();
+        }
+
+        if shortcuts::pressed(ctx, Action::ResetZoom) {
+            self.set_zoom(1.0);
+        }
+
+        if shortcuts::pressed(ctx, Action::ReopenTab) {
+            self.reopen_closed_tab();
+        }
+
+        if shortcuts::pressed(ctx, Action::NewTab) {
+            let home = self.config.home_page().to_string();
+            self.new_tab(&home);
+        }
+
+        if shortcuts::pressed(ctx, Action::CloseTab) {
+            self.close_tab(self.current);
+        }
+
+        // reloading skips the cache
+        if shortcuts::pressed(ctx, Action::Reload) {
+            self.retry(self.current);
+        }
+
+        if shortcuts::pressed(ctx, Action::Root) {
+            self.go_up(true);
+        }
+
+        if shortcuts::pressed(ctx, Action::Up) {
+            self.go_up(false);
+        }
+
+        if shortcuts::pressed(ctx, Action::Help) {
+            self.show_shortcuts = !self.show_shortcuts;
+        }
+
+        if shortcuts::pressed(ctx, Action::HistoryPanel) {
+            self.show_history = !self.show_history;
+        }
+
+        if shortcuts::pressed(ctx, Action::BookmarksPanel) {
+            self.show_bookmarks_panel = !self.show_bookmarks_panel;
+        }
+
+        // opening the outline puts the arrow keys on it
+        if shortcuts::pressed(ctx, Action::Outline) {
+            self.show_outline = !self.show_outline;
+
+            if self.show_outline {
+                ctx.memory().request_focus(egui::Id::new(OUTLINE_ID));
+            }
+        }
+
+        if shortcuts::pressed(ctx, Action::NextUnread) {
+            self.next_unread();
+        }
+
+        if shortcuts::pressed(ctx, Action::SearchTabs) {
+            self.show_tab_search = true;
+            ctx.memory().request_focus(egui::Id::new("tab search"));
+        }
+
+        // a match from the tab search stays ringed until you move on
+        let moved_on = {
+            let input = ctx.input();
+            input.scroll_delta.y != 0.0
+                || input.pointer.any_pressed()
+                || !input.keys_down.is_empty()
+        };
+
+        if moved_on {
+            self.found = None;
+        }
+
+        self.link_find(ctx);
+        self.keyboard_scroll(ctx);
+        self.update_recent();
+        self.tab_switcher(ctx);
+
+        if shortcuts::pressed(ctx, Action::ReadingMode) {
+            self.reading_mode = !self.reading_mode;
+        }
+
+        if self.reading_mode
+            && ctx.memory().focus().is_none()
+            && shortcuts::pressed(ctx, Action::LeaveReadingMode)
+        {
+            self.reading_mode = false;
+        }
+
+        if self.fullscreen != self.reading_mode {
+            self.fullscreen = self.reading_mode;
+            frame.set_fullscreen(self.fullscreen);
+        }
+
+        match self.swipe.update(ctx) {
+            Some(Swipe::Back) => self.go_back(),
+            Some(Swipe::Forward) => self.go_forward(),
+            None => {}
+        }
+
+        // get content back from other threads
+        self.poll_tabs();
+        self.downloads.poll();
+        self.prefetcher.poll();
+        self.run_retries(ctx);
+        self.run_refreshes(ctx);
+        self.watch_files(ctx);
+        self.poll_sync(ctx);
+        self.check_watched(ctx);
+        self.check_subscriptions(ctx);
+        self.remote_control();
+        self.tray_actions(frame);
+
+        self.player_bar(ctx);
+        self.link_find_bar(ctx);
+        self.timing_bar(ctx);
+        self.restore_bar(ctx);
+        self.cut_bar(ctx);
+        self.certificate_bar(ctx);
+        self.autosave(ctx);
+
+        if !self.reading_mode {
+            self.history_panel(ctx);
+            self.bookmarks_panel(ctx);
+            self.links_panel(ctx);
+            self.outline_panel(ctx);
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if !self.reading_mode {
+                self.toolbars(ui);
+            }
+
+            // display error
+            self.error_page(ui);
+
+            // display input prompt
+            self.input_page(ui);
+
+            // display blocked host interstitial
+            self.blocked_page(ui);
+
+            // display pages built into gbrowse
+            self.about_page(ui);
+
+            // display gempub books
+            self.book_page(ui);
+
+            // display text
+            let mut clicked = None;
+            let mut search = None;
+            let mut scroll = None;
+            let mut hovered = None;
+
+            let scroll_to = match self.tab().page {
+                Some(_) => self.tab_mut().scroll_to.take(),
+                None => None,
+            };
+
+            let mut headings = Vec::new();
+
+            let found = self
+                .found
+                .filter(|(id, _)| *id == self.tab().id)
+                .map(|(_, node)| node);
+
+            if let Some(page) = &self.tab().page {
+                let theme = self.page_theme();
+                let margin = match self.reading_mode {
+                    true => {
+                        let width = theme.content_width.unwrap_or(READING_WIDTH);
+                        ((ui.available_width() - width) / 2.0).max(0.0)
+                    }
+                    false => 0.0,
+                };
+
+                let mut area = ScrollArea::vertical()
+                    .id_source(("vertical scroll", self.tab().id))
+                    .auto_shrink([false, false]);
+
+                if let Some(offset) = scroll_to {
+                    area = area.vertical_scroll_offset(offset);
+                }
+
+                let output = area.show(ui, |ui| {
+                    let mut view = GemtextView::new(&page.nodes)
+                        .theme(theme)
+                        .plugins(&self.plugins)
+                        .base_url(&page.url)
+                        .headings(&mut headings)
+                        .highlight(
+                            self.link_find
+                                .as_deref()
+                                .and_then(|query| find_link(&page.nodes, query))
+                                .or(found),
+                        )
+                        .on_link(|url| clicked = Some(url.to_string()))
+                        .context_menu(|ui, node| page_menu(ui, node, page, &mut search));
+
+                    if self.config.link_previews {
+                        let prefetcher = &self.prefetcher;
+
+                        view = view.link_tooltip(|ui, url| {
+                            hovered = Some(url.to_string());
+                            link_tooltip(ui, url, prefetcher.get(url), prefetcher.is_loading(url))
+                        });
+                    }
+
+                    ui.horizontal_top(|ui| {
+                        ui.add_space(margin);
+                        ui.add(view)
+                    })
+                    .inner
+                });
+
+                scroll = Some(output.state.offset.y);
+                self.page_rect = Some(output.inner_rect);
+                self.page_height = output.inner.rect.height();
+            }
+
+            if let Some(scroll) = scroll {
+                self.tab_mut().scroll = scroll;
+            }
+
+            self.headings = headings;
+
+            if let Some(url) = clicked {
+                self.follow_link(ui.ctx(), &url);
+            }
+
+            if let Some(url) = hovered {
+                let blocked = url::Url::parse(&url)
+                    .ok()
+                    .and_then(|url| url.host_str().map(|host| self.config.is_blocked(host)));
+
+                if blocked == Some(false) {
+                    self.prefetcher.start(
+                        self.page_fetcher(false),
+                        &url,
+                        self.config.cache.max_age(),
+                    );
+                }
+            }
+
+            if let Some(text) = search {
+                let url = self.config.search_url(&text);
+                self.new_tab(&url);
+            }
+        });
+
+        self.page_info(ctx);
+        self.bookmarks_window(ctx);
+        self.sessions_window(ctx);
+        self.shortcuts_window(ctx);
+        self.tab_search_window(ctx);
+        self.watched_window(ctx);
+
+        // name the window after the page, so it's recognizable in the taskbar
+        let title = format!("{} - gbrowse", self.tab().title());
+
+        if title != self.window_title {
+            frame.set_window_title(&title);
+            self.window_title = title;
+        }
+
+        if self.tabs.iter().any(|tab| tab.loading)
+            || self.downloads.in_progress()
+            || self.prefetcher.in_progress()
+        {
+            ctx.request_repaint();
+        }
+    }
+}
+
+/// The right click menu for a line of the page.
+fn page_menu(ui: &mut egui::Ui, node: &GemtextNode, page: &Page, search: &mut Option<String>) {
+    if let GemtextNode::Link(url, label) = node {
+        if ui.button(tr("📋 Copy link address")).clicked() {
+            ui.output().copied_text = nav::resolve(&page.url, url).unwrap_or_else(|| url.clone());
+            ui.close_menu();
+        }
+
+        if let Some(label) = label {
+            if ui.button(tr("📋 Copy link text")).clicked() {
+                ui.output().copied_text = label.clone();
+                ui.close_menu();
+            }
+        }
+
+        ui.separator();
+    }
+
+    let Some(text) = node_text(node) else {
+        ui.close_menu();
+        return;
+    };
+
+    if ui
+        .button(trf(
+            "🔍 Search geminispace for “{text}”",
+            &[("text", &truncate(text, 24))],
+        ))
+        .clicked()
+    {
+        *search = Some(text.to_string());
+        ui.close_menu();
+    }
+
+    ui.separator();
+
+    if ui.button(tr("📝 Copy page as Markdown")).clicked() {
+        ui.output().copied_text = markdown::from_gemtext(&page.nodes, &page.url);
+        ui.close_menu();
+    }
+}
+
+/// Where a link goes, and a preview of the page once it's loaded.
+fn link_tooltip(ui: &mut egui::Ui, url: &str, preview: Option<&Preview>, loading: bool) {
+    ui.set_max_width(400.0);
+
+    match preview {
+        Some(preview) => {
+            if let Some(title) = &preview.title {
+                ui.strong(title);
+            }
+
+            if let Some(snippet) = &preview.snippet {
+                ui.label(snippet);
+            }
+        }
+        None if loading => {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.weak(tr("Loading preview…"));
+            });
+        }
+        None => {}
+    }
+
+    ui.weak(url);
+}
+
+/// The first link whose label has `query` in it, ignoring case.
+fn find_link(nodes: &[GemtextNode], query: &str) -> Option<usize> {
+    let query = query.to_lowercase();
+
+    nodes.iter().position(|node| match node {
+        GemtextNode::Link(url, label) => label
+            .as_deref()
+            .unwrap_or(url)
+            .to_lowercase()
+            .contains(&query),
+        _ => false,
+    })
+}
+
+/// The readable text of a node, for searching.
+fn node_text(node: &GemtextNode) -> Option<&str> {
+    match node {
+        GemtextNode::Text(text)
+        | GemtextNode::Heading(text)
+        | GemtextNode::SubHeading(text)
+        | GemtextNode::SubSubHeading(text)
+        | GemtextNode::ListItem(text)
+        | GemtextNode::Blockquote(text)
+        | GemtextNode::Preformatted(text, _) => Some(text.trim()).filter(|text| !text.is_empty()),
+        GemtextNode::Link(url, label) => Some(label.as_deref().unwrap_or(url)),
+        GemtextNode::EmptyLine => None,
+    }
+}
+
+/// The part of `text` around where `query` is, for listing matches.
+fn snippet(text: &str, query: &str) -> String {
+    let lower = text.to_lowercase();
+    let at = lower
+        .find(query)
+        .map_or(0, |at| lower[..at].chars().count());
+    let skip = at.saturating_sub(30);
+
+    let rest: String = text.chars().skip(skip).collect();
+    let rest = truncate(&rest, 80);
+
+    match skip {
+        0 => rest,
+        _ => format!("…{rest}"),
+    }
+}
+
+/// Each step of a request that was timed, translated.
+fn timing_steps(timing: &Timing) -> Vec<(String, Duration)> {
+    let mut steps = vec![(tr("DNS"), timing.dns), (tr("Connect"), timing.connect)];
+
+    if let Some(tls) = timing.tls {
+        steps.push((tr("TLS"), tls));
+    }
+
+    steps.push((tr("First byte"), timing.first_byte));
+
+    if let Some(total) = timing.total {
+        steps.push((tr("Total"), total));
+    }
+
+    steps
+}
+
+/// The mime type without its parameters, and its charset. Gemini says
+/// text is UTF-8 unless the server says otherwise.
+fn mime_and_charset(mime: &str) -> (String, String) {
+    let mut parts = mime.split(';').map(str::trim);
+    let kind = parts.next().unwrap_or_default().to_string();
+
+    let charset = parts
+        .find_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            name.trim()
+                .eq_ignore_ascii_case("charset")
+                .then(|| value.trim().trim_matches('"').to_string())
+        })
+        .unwrap_or_else(|| "utf-8".to_string());
+
+    (kind, charset)
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{} ms", duration.as_millis())
+}
+
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() > max {
+        text.chars().take(max - 1).chain(['…']).collect()
+    } else {
+        text.to_string()
+    }
+}
+
+fn piped_page(text: &str) -> Page {
+    Page {
+        url: STDIN_URL.to_string(),
+        redirects: vec![],
+        nodes: gemtext::parse_gemtext(text),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::MockFetcher;
+    use gmi::protocol::StatusCode;
+
+    fn browser(fetcher: MockFetcher, start_page: &str) -> Gbrowse {
+        let mut app = Gbrowse::with_fetcher(
+            Arc::new(fetcher),
+            Plugins::default(),
+            start_page.to_string(),
+        );
+        wait(&mut app);
+        app
+    }
+
+    /// Let the current tab finish loading.
+    fn wait(app: &mut Gbrowse) {
+        let started = Instant::now();
+
+        while app.tab().loading {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "page never loaded"
+            );
+
+            app.poll_tabs();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn pushes_history() {
+        let fetcher = MockFetcher::default()
+            .respond("gemini://a.org/", 20, "text/gemini", "# A")
+            .respond("gemini://a.org/b", 20, "text/gemini", "# B");
+        let mut app = browser(fetcher, "gemini://a.org/");
+
+        assert_eq!(app.tab().title(), "A");
+
+        app.change_site("gemini://a.org/b", false);
+        wait(&mut app);
+
+        assert_eq!(app.tab().sites, ["gemini://a.org/", "gemini://a.org/b"]);
+        assert_eq!(app.tab().title(), "B");
+        assert_eq!(app.history.recent("", 10).len(), 2);
+
+        app.go_back();
+        wait(&mut app);
+
+        assert_eq!(app.tab().sites, ["gemini://a.org/"]);
+        assert_eq!(app.tab().forward, ["gemini://a.org/b"]);
+        assert_eq!(app.tab().title(), "A");
+    }
+
+    #[test]
+    fn redirect_replaces_history_entry() {
+        let fetcher = MockFetcher::default()
+            .respond("gemini://a.org/", 31, "/new", "")
+            .respond("gemini://a.org/new", 20, "text/gemini", "# New");
+        let app = browser(fetcher, "gemini://a.org/");
+
+        assert_eq!(app.tab().sites, ["gemini://a.org/new"]);
+        assert_eq!(app.tab().url, "gemini://a.org/new");
+    }
+
+    #[test]
+    fn error_status_is_shown() {
+        let app = browser(MockFetcher::default(), "gemini://a.org/missing");
+
+        assert!(app.tab().page.is_none());
+        assert!(matches!(
+            app.tab().error,
+            Some(LoadError::Status {
+                status: StatusCode::PermanentFailure(1),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn follows_links_by_scheme() {
+        let fetcher = MockFetcher::default()
+            .respond("gemini://a.org/", 20, "text/gemini", "# A")
+            .respond("mercury://b.org/", 20, "text/gemini", "# B");
+        let mut app = browser(fetcher, "gemini://a.org/");
+        let ctx = egui::Context::default();
+
+        app.follow_link(&ctx, "mercury://b.org/");
+        wait(&mut app);
+
+        assert_eq!(app.tab().url, "mercury://b.org/");
+        assert_eq!(app.tab().title(), "B");
+
+        for link in ["https://c.org/", "mailto:me@c.org"] {
+            app.follow_link(&ctx, link);
+
+            assert_eq!(app.tab().url, "mercury://b.org/");
+            assert_eq!(
+                ctx.output().open_url.take().map(|open| open.url).as_deref(),
+                Some(link)
+            );
+        }
+    }
+
+    #[test]
+    fn sensitive_input_is_not_cached() {
+        let fetcher = MockFetcher::default()
+            .respond("gemini://a.org/login", 11, "Password", "")
+            .respond("gemini://a.org/login?hunter2", 20, "text/gemini", "# Hi");
+        let mut app = browser(fetcher, "gemini://a.org/login");
+
+        let input = app.tab().input.as_ref().expect("a prompt");
+        assert!(input.sensitive);
+
+        let url = input::with_query(&input.url, "hunter2");
+        app.change_site(&url, true);
+        wait(&mut app);
+
+        assert_eq!(app.tab().title(), "Hi");
+        assert!(app.tab().hide_query);
+        assert!(!app.tab().sites.iter().any(|url| url.contains("hunter2")));
+        assert!(app.cache.get(&url).is_none());
+        assert!(app.history.recent("hunter2", 10).is_empty());
+    }
+
+    #[test]
+    fn sensitive_input_is_hidden_when_asked_again() {
+        let fetcher = MockFetcher::default()
+            .respond("gemini://a.org/", 20, "text/gemini", "# A")
+            .respond("gemini://a.org/login", 11, "Password", "")
+            .respond("gemini://a.org/login?wrong", 11, "Wrong, try again", "");
+        let mut app = browser(fetcher, "gemini://a.org/");
+
+        app.change_site("gemini://a.org/login", false);
+        wait(&mut app);
+
+        let input = app.tab().input.as_ref().expect("a prompt");
+        let url = input::with_query(&input.url, "wrong");
+        app.change_site(&url, false);
+
+        assert!(!app.tab().url.contains("wrong"));
+        wait(&mut app);
+
+        let tab = app.tab();
+        let input = tab.input.as_ref().expect("another prompt");
+
+        assert_eq!(input.prompt, "Wrong, try again");
+        assert_eq!(tab.url, "gemini://a.org/login");
+        assert!(!input.url.contains("wrong"));
+        assert!(!tab.sites.iter().any(|url| url.contains("wrong")));
+    }
+}
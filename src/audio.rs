@@ -0,0 +1,125 @@
+//! Plays audio/* responses, so podcasts can be listened to while browsing.
+//! Only built with the `audio` feature.
+
+use std::time::Duration;
+
+#[cfg(feature = "audio")]
+pub use player::Player;
+
+#[cfg(not(feature = "audio"))]
+pub use stub::Player;
+
+/// Format a position in a track as `m:ss`.
+pub fn format_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+#[cfg(feature = "audio")]
+mod player {
+    use rodio::{Decoder, OutputStream, Sink, Source};
+
+    use std::{
+        io::Cursor,
+        time::{Duration, Instant},
+    };
+
+    pub struct Player {
+        pub name: String,
+        // dropping the stream stops the sound
+        _stream: OutputStream,
+        sink: Sink,
+        duration: Option<Duration>,
+        /// How much was played before the last time it was resumed.
+        played: Duration,
+        resumed: Option<Instant>,
+    }
+
+    impl Player {
+        pub fn new(name: String, data: Vec<u8>) -> Result<Self, String> {
+            let (stream, handle) =
+                OutputStream::try_default().map_err(|err| format!("Audio Error: {err}"))?;
+            let sink = Sink::try_new(&handle).map_err(|err| format!("Audio Error: {err}"))?;
+            let source =
+                Decoder::new(Cursor::new(data)).map_err(|err| format!("Audio Error: {err}"))?;
+
+            let duration = source.total_duration();
+            sink.append(source);
+
+            Ok(Self {
+                name,
+                _stream: stream,
+                sink,
+                duration,
+                played: Duration::ZERO,
+                resumed: Some(Instant::now()),
+            })
+        }
+
+        pub fn is_paused(&self) -> bool {
+            self.sink.is_paused()
+        }
+
+        pub fn is_finished(&self) -> bool {
+            self.sink.empty()
+        }
+
+        pub fn toggle(&mut self) {
+            match self.resumed.take() {
+                Some(resumed) => {
+                    self.played += resumed.elapsed();
+                    self.sink.pause();
+                }
+                None => {
+                    self.resumed = Some(Instant::now());
+                    self.sink.play();
+                }
+            }
+        }
+
+        pub fn position(&self) -> Duration {
+            let position = self.played + self.resumed.map_or(Duration::ZERO, |at| at.elapsed());
+
+            self.duration
+                .map_or(position, |duration| position.min(duration))
+        }
+
+        pub fn duration(&self) -> Option<Duration> {
+            self.duration
+        }
+    }
+}
+
+#[cfg(not(feature = "audio"))]
+mod stub {
+    use std::time::Duration;
+
+    /// Stands in for the real player when gbrowse is built without audio.
+    pub struct Player {
+        pub name: String,
+    }
+
+    impl Player {
+        pub fn new(_name: String, _data: Vec<u8>) -> Result<Self, String> {
+            Err("gbrowse was built without audio support".to_string())
+        }
+
+        pub fn is_paused(&self) -> bool {
+            true
+        }
+
+        pub fn is_finished(&self) -> bool {
+            true
+        }
+
+        pub fn toggle(&mut self) {}
+
+        pub fn position(&self) -> Duration {
+            Duration::ZERO
+        }
+
+        pub fn duration(&self) -> Option<Duration> {
+            None
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Syncing the bookmarks through a capsule that takes Titan uploads, so
+//! they follow you between computers without a third-party service. They're
+//! uploaded to the `titan://` url in the config and read back over gemini
+//! from the same place.
+//!
+//! The bookmarks as they were at the last sync are kept, so it's clear
+//! which side changed. If both did, nothing's overwritten until you pick
+//! which to keep.
+
+use std::{
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use crate::{
+    bookmarks::Bookmark,
+    config::{self, Sites},
+    fetch::{self, GeminiFetcher, LoadError},
+    transport,
+};
+
+/// The bookmarks as of the last sync.
+const SYNCED_FILE: &str = "bookmarks.synced.ron";
+
+#[derive(Clone, Copy)]
+pub enum Direction {
+    /// Push or pull, whichever side changed.
+    Both,
+    /// Overwrite the synced bookmarks with these ones.
+    Push,
+    /// Overwrite these bookmarks with the synced ones.
+    Pull,
+}
+
+pub enum Outcome {
+    UpToDate(Vec<Bookmark>),
+    Pushed(Vec<Bookmark>),
+    Pulled(Vec<Bookmark>),
+    /// Both sides changed since the last sync.
+    Conflict,
+}
+
+/// Sync in the background.
+pub fn start(
+    direction: Direction,
+    url: String,
+    token: Option<String>,
+    local: Vec<Bookmark>,
+) -> Receiver<Result<Outcome, String>> {
+    let (tx, rx) = mpsc::channel();
+    let base: Vec<Bookmark> = config::read_file(SYNCED_FILE);
+
+    thread::spawn(move || {
+        tx.send(run(direction, &url, token.as_deref(), local, base))
+            .ok();
+    });
+
+    rx
+}
+
+/// Remember what was synced, to tell what changed next time.
+pub fn remember(bookmarks: &[Bookmark]) {
+    config::write_file(SYNCED_FILE, &bookmarks);
+}
+
+fn run(
+    direction: Direction,
+    url: &str,
+    token: Option<&str>,
+    local: Vec<Bookmark>,
+    base: Vec<Bookmark>,
+) -> Result<Outcome, String> {
+    let titan = url::Url::parse(url).map_err(|err| format!("Invalid sync url {url}: {err}"))?;
+
+    let push = |local: Vec<Bookmark>| {
+        upload(&titan, token, &local)?;
+        Ok(Outcome::Pushed(local))
+    };
+
+    if let Direction::Push = direction {
+        return push(local);
+    }
+
+    let remote = download(&titan)?;
+
+    match (direction, remote) {
+        (Direction::Pull, Some(remote)) => Ok(Outcome::Pulled(remote)),
+        (Direction::Pull, None) => Err(format!("There are no bookmarks at {url} yet")),
+        (_, Some(remote)) if remote == local => Ok(Outcome::UpToDate(local)),
+        (_, None) => push(local),
+        (_, Some(remote)) if remote == base => push(local),
+        (_, Some(remote)) if local == base => Ok(Outcome::Pulled(remote)),
+        (_, Some(_)) => Ok(Outcome::Conflict),
+    }
+}
+
+/// The synced bookmarks, or `None` if none have been uploaded yet.
+fn download(titan: &url::Url) -> Result<Option<Vec<Bookmark>>, String> {
+    let mut url = titan.clone();
+    url.set_scheme("gemini")
+        .map_err(|_| format!("Can't read {titan} over gemini"))?;
+
+    let response = match fetch::fetch_followed(&GeminiFetcher, url.as_str(), &Sites::default()) {
+        Ok(response) => response,
+        Err(LoadError::Status { status, .. }) if u8::from(status) == 51 => return Ok(None),
+        Err(err) => return Err(err.to_string()),
+    };
+
+    let text = String::from_utf8_lossy(&response.data);
+
+    ron::from_str(&text)
+        .map(Some)
+        .map_err(|err| format!("{url} isn't a bookmarks file: {err}"))
+}
+
+fn upload(titan: &url::Url, token: Option<&str>, bookmarks: &[Bookmark]) -> Result<(), String> {
+    let text = ron::ser::to_string_pretty(bookmarks, ron::ser::PrettyConfig::default())
+        .map_err(|err| format!("Error serializing bookmarks: {err}"))?;
+
+    let response = transport::upload(titan, "text/plain", token, text.as_bytes())?;
+
+    match u8::from(response.status) {
+        20..=39 => Ok(()),
+        status => Err(format!("Upload failed: {status} {}", response.meta)),
+    }
+}
@@ -0,0 +1,437 @@
+use eframe::egui::{self, CollapsingHeader, CursorIcon, RichText, Sense};
+use gmi::gemtext::{self, GemtextNode};
+use serde::{Deserialize, Serialize};
+
+use std::{collections::BTreeSet, fs, path::Path};
+
+use crate::{config, i18n::tr, input};
+
+/// Bookmarks in this folder are shown in the bookmarks toolbar.
+pub const TOOLBAR_FOLDER: &str = "Toolbar";
+
+/// Saved pages, kept in `bookmarks.ron`.
+pub struct Bookmarks {
+    bookmarks: Vec<Bookmark>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Bookmark {
+    pub url: String,
+    pub title: String,
+    /// Folders separated by `/`, e.g. `"Gemini/Games"`. Empty for the top level.
+    pub folder: String,
+    pub tags: Vec<String>,
+    /// Typing this into the url bar opens the bookmark. Anything typed after
+    /// it replaces `%s` in the url, or is sent as the query if there's no
+    /// `%s`, e.g. `gs weather`.
+    pub keyword: String,
+}
+
+impl Bookmark {
+    fn folders(&self) -> Vec<&str> {
+        self.folder
+            .split('/')
+            .map(str::trim)
+            .filter(|folder| !folder.is_empty())
+            .collect()
+    }
+
+    /// Whether every word of a filter matches the title, url or folder.
+    /// Words starting with `#` match tags instead.
+    pub fn matches(&self, filter: &str) -> bool {
+        filter.split_whitespace().all(|word| {
+            let word = word.to_lowercase();
+
+            match word.strip_prefix('#') {
+                Some(tag) => self.tags.iter().any(|t| t.to_lowercase() == tag),
+                None => [&self.title, &self.url, &self.folder]
+                    .iter()
+                    .any(|field| field.to_lowercase().contains(&word)),
+            }
+        })
+    }
+}
+
+impl Bookmarks {
+    pub fn load() -> Self {
+        Self {
+            bookmarks: config::read_file("bookmarks.ron"),
+        }
+    }
+
+    pub fn save(&self) {
+        config::write_file("bookmarks.ron", &self.bookmarks);
+    }
+
+    pub fn all(&self) -> &[Bookmark] {
+        &self.bookmarks
+    }
+
+    /// Swap every bookmark for another set, like ones synced from elsewhere.
+    pub fn replace(&mut self, bookmarks: Vec<Bookmark>) {
+        self.bookmarks = bookmarks;
+        self.save();
+    }
+
+    pub fn get(&self, url: &str) -> Option<&Bookmark> {
+        self.bookmarks.iter().find(|bookmark| bookmark.url == url)
+    }
+
+    /// The bookmarks directly inside a folder.
+    pub fn in_folder<'a>(&'a self, folder: &'a str) -> impl Iterator<Item = &'a Bookmark> {
+        self.bookmarks
+            .iter()
+            .filter(move |bookmark| bookmark.folders() == folder.split('/').collect::<Vec<_>>())
+    }
+
+    /// Expand a bookmark keyword typed into the url bar.
+    pub fn expand(&self, text: &str) -> Option<String> {
+        let (keyword, rest) = text.trim().split_once(' ').unwrap_or((text.trim(), ""));
+
+        let bookmark = self
+            .bookmarks
+            .iter()
+            .find(|bookmark| !bookmark.keyword.is_empty() && bookmark.keyword == keyword)?;
+
+        let rest = rest.trim();
+
+        Some(if bookmark.url.contains("%s") {
+            bookmark.url.replace("%s", &input::encode(rest))
+        } else if !rest.is_empty() {
+            input::with_query(&bookmark.url, rest)
+        } else {
+            bookmark.url.clone()
+        })
+    }
+
+    pub fn add(&mut self, url: &str, title: &str) {
+        if self.get(url).is_none() {
+            self.bookmarks.push(Bookmark {
+                url: url.to_string(),
+                title: title.to_string(),
+                ..Default::default()
+            });
+            self.save();
+        }
+    }
+
+    /// Save a group of pages into a new folder, named so it doesn't clash
+    /// with the existing ones. Returns the folder's name.
+    pub fn add_folder(&mut self, name: &str, pages: Vec<(String, String)>) -> String {
+        let taken = |folder: &str| {
+            self.bookmarks
+                .iter()
+                .any(|b| b.folders().first() == Some(&folder))
+        };
+
+        let folder = (1..)
+            .map(|n| {
+                if n == 1 {
+                    name.to_string()
+                } else {
+                    format!("{name} {n}")
+                }
+            })
+            .find(|folder| !taken(folder))
+            .unwrap_or_default();
+
+        for (url, title) in pages {
+            self.bookmarks.push(Bookmark {
+                url,
+                title,
+                folder: folder.clone(),
+                ..Default::default()
+            });
+        }
+
+        self.save();
+        folder
+    }
+
+    /// Add the links in a gemtext file, like the one Lagrange exports, to
+    /// an "Imported" folder, skipping ones that are already bookmarked.
+    /// Returns how many were added.
+    pub fn import(&mut self, path: &Path) -> Result<usize, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Error reading {}: {err}", path.display()))?;
+
+        let mut pages: Vec<(String, String)> = vec![];
+
+        for node in gemtext::parse_gemtext(&text) {
+            if let GemtextNode::Link(url, title) = node {
+                if url.contains("://")
+                    && self.get(&url).is_none()
+                    && !pages.iter().any(|(u, _)| *u == url)
+                {
+                    pages.push((url.clone(), title.unwrap_or(url)));
+                }
+            }
+        }
+
+        let count = pages.len();
+
+        if count > 0 {
+            self.add_folder("Imported", pages);
+        }
+
+        Ok(count)
+    }
+
+    /// Add the bookmarks from another profile that aren't here yet, keeping
+    /// their folders and tags. Returns how many were added.
+    pub fn merge(&mut self, other: Vec<Bookmark>) -> usize {
+        let mut added = 0;
+
+        for bookmark in other {
+            if self.get(&bookmark.url).is_none() {
+                self.bookmarks.push(bookmark);
+                added += 1;
+            }
+        }
+
+        self.save();
+        added
+    }
+
+    pub fn remove(&mut self, url: &str) {
+        self.bookmarks.retain(|bookmark| bookmark.url != url);
+        self.save();
+    }
+
+    /// Edit the folder and tags of the bookmark for `url`.
+    pub fn edit_ui(&mut self, ui: &mut egui::Ui, url: &str) {
+        let Some(bookmark) = self.bookmarks.iter_mut().find(|b| b.url == url) else {
+            return;
+        };
+
+        let mut changed = false;
+        let mut tags = bookmark.tags.join(", ");
+
+        egui::Grid::new("edit bookmark").show(ui, |ui| {
+            ui.label(tr("Title"));
+            changed |= ui.text_edit_singleline(&mut bookmark.title).changed();
+            ui.end_row();
+
+            ui.label(tr("Folder"));
+            changed |= ui.text_edit_singleline(&mut bookmark.folder).changed();
+            ui.end_row();
+
+            ui.label(tr("Keyword"));
+            changed |= ui.text_edit_singleline(&mut bookmark.keyword).changed();
+            ui.end_row();
+
+            ui.label(tr("Tags"));
+            if ui.text_edit_singleline(&mut tags).changed() {
+                bookmark.tags = tags
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                changed = true;
+            }
+            ui.end_row();
+        });
+
+        if changed {
+            self.save();
+        }
+    }
+
+    /// Show the bookmarks matching `filter` as a folder tree. Returns the
+    /// url of the bookmark that was clicked.
+    pub fn ui(&self, ui: &mut egui::Ui, filter: &str) -> Option<String> {
+        let matching: Vec<&Bookmark> = self
+            .bookmarks
+            .iter()
+            .filter(|bookmark| bookmark.matches(filter))
+            .collect();
+
+        let mut clicked = None;
+        folder_ui(ui, &matching, 0, !filter.is_empty(), &mut clicked);
+
+        clicked
+    }
+
+    /// The folder tree for the sidebar, where bookmarks are opened with a
+    /// double click and dragged onto another bookmark to go in front of it
+    /// or onto a folder to go into it. `dragging` is the url of the
+    /// bookmark being dragged. Returns the url of the one that was opened.
+    pub fn sidebar_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        dragging: &mut Option<String>,
+    ) -> Option<String> {
+        let all: Vec<&Bookmark> = self.bookmarks.iter().collect();
+        let mut sidebar = Sidebar {
+            dragging,
+            opened: None,
+            dropped: None,
+        };
+
+        sidebar.folder(ui, &all, 0);
+
+        let Sidebar {
+            opened, dropped, ..
+        } = sidebar;
+
+        if dragging.is_some() {
+            ui.output().cursor_icon = CursorIcon::Grabbing;
+        }
+
+        if ui.input().pointer.any_released() {
+            if let (Some(url), Some(target)) = (dragging.take(), dropped) {
+                self.move_to(&url, target);
+            }
+        }
+
+        opened
+    }
+
+    fn move_to(&mut self, url: &str, target: DropTarget) {
+        let Some(from) = self.bookmarks.iter().position(|b| b.url == url) else {
+            return;
+        };
+
+        let mut bookmark = self.bookmarks.remove(from);
+
+        match target {
+            DropTarget::Before(before) => {
+                let Some(to) = self.bookmarks.iter().position(|b| b.url == before) else {
+                    self.bookmarks.insert(from, bookmark);
+                    return;
+                };
+
+                bookmark.folder = self.bookmarks[to].folder.clone();
+                self.bookmarks.insert(to, bookmark);
+            }
+            DropTarget::Into(folder) => {
+                bookmark.folder = folder;
+                self.bookmarks.push(bookmark);
+            }
+        }
+
+        self.save();
+    }
+}
+
+/// Where a dragged bookmark was let go.
+enum DropTarget {
+    /// In front of the bookmark with this url, in its folder.
+    Before(String),
+    /// At the end of this folder.
+    Into(String),
+}
+
+struct Sidebar<'a> {
+    dragging: &'a mut Option<String>,
+    opened: Option<String>,
+    dropped: Option<DropTarget>,
+}
+
+impl Sidebar<'_> {
+    fn folder(&mut self, ui: &mut egui::Ui, bookmarks: &[&Bookmark], depth: usize) {
+        let subfolders: BTreeSet<&str> = bookmarks
+            .iter()
+            .filter_map(|bookmark| bookmark.folders().get(depth).copied())
+            .collect();
+
+        for folder in subfolders {
+            let inside: Vec<&Bookmark> = bookmarks
+                .iter()
+                .filter(|bookmark| bookmark.folders().get(depth) == Some(&folder))
+                .copied()
+                .collect();
+
+            let path = inside[0].folders()[..=depth].join("/");
+
+            let header = CollapsingHeader::new(format!("📁 {folder}"))
+                .id_source(("sidebar", &path))
+                .show(ui, |ui| self.folder(ui, &inside, depth + 1))
+                .header_response;
+
+            self.drop_zone(ui, header.rect, DropTarget::Into(path));
+        }
+
+        for bookmark in bookmarks.iter().filter(|b| b.folders().len() == depth) {
+            let dragged = self.dragging.as_ref() == Some(&bookmark.url);
+            let row = ui
+                .selectable_label(dragged, &bookmark.title)
+                .on_hover_text(&bookmark.url)
+                .interact(Sense::drag());
+
+            if row.double_clicked() {
+                self.opened = Some(bookmark.url.clone());
+            }
+
+            if row.drag_started() {
+                *self.dragging = Some(bookmark.url.clone());
+            }
+
+            if !dragged {
+                self.drop_zone(ui, row.rect, DropTarget::Before(bookmark.url.clone()));
+            }
+        }
+    }
+
+    /// Mark where the bookmark would go while one is dragged over `rect`.
+    fn drop_zone(&mut self, ui: &mut egui::Ui, rect: egui::Rect, target: DropTarget) {
+        if self.dragging.is_none() || !ui.rect_contains_pointer(rect) {
+            return;
+        }
+
+        let stroke = ui.visuals().selection.stroke;
+
+        match target {
+            DropTarget::Before(_) => ui.painter().hline(rect.x_range(), rect.top(), stroke),
+            DropTarget::Into(_) => ui.painter().rect_stroke(rect, 2.0, stroke),
+        }
+
+        self.dropped = Some(target);
+    }
+}
+
+fn folder_ui(
+    ui: &mut egui::Ui,
+    bookmarks: &[&Bookmark],
+    depth: usize,
+    open: bool,
+    clicked: &mut Option<String>,
+) {
+    let subfolders: BTreeSet<&str> = bookmarks
+        .iter()
+        .filter_map(|bookmark| bookmark.folders().get(depth).copied())
+        .collect();
+
+    for folder in subfolders {
+        let inside: Vec<&Bookmark> = bookmarks
+            .iter()
+            .filter(|bookmark| bookmark.folders().get(depth) == Some(&folder))
+            .copied()
+            .collect();
+
+        let path = inside[0].folders()[..=depth].join("/");
+
+        CollapsingHeader::new(format!("📁 {folder}"))
+            .id_source(path)
+            .default_open(open)
+            .show(ui, |ui| folder_ui(ui, &inside, depth + 1, open, clicked));
+    }
+
+    for bookmark in bookmarks.iter().filter(|b| b.folders().len() == depth) {
+        ui.horizontal(|ui| {
+            if ui
+                .link(&bookmark.title)
+                .on_hover_text(&bookmark.url)
+                .clicked()
+            {
+                *clicked = Some(bookmark.url.clone());
+            }
+
+            for tag in &bookmark.tags {
+                ui.label(RichText::new(format!("#{tag}")).weak());
+            }
+        });
+    }
+}
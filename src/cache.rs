@@ -0,0 +1,413 @@
+//! Responses kept in memory and on disk, so a page that was fetched a
+//! moment ago (like a link that was previewed, or the page you were on
+//! before closing gbrowse) opens without waiting on the network.
+
+use gmi::{
+    protocol::{Response, StatusCode},
+    url::Url,
+};
+use serde::{Deserialize, Serialize};
+
+use std::{
+    cmp::Reverse,
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    config,
+    fetch::{Fetcher, Stream},
+};
+
+const MB: u64 = 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct CacheSettings {
+    /// How many megabytes of pages to keep in memory. The pages used least
+    /// recently go first.
+    pub memory_size: u64,
+    /// Same, for the pages saved to disk. `0` keeps pages off the disk.
+    pub disk_size: u64,
+    /// How many minutes a page is used for before it's fetched again.
+    pub max_age: u64,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            memory_size: 32,
+            disk_size: 64,
+            max_age: 5,
+        }
+    }
+}
+
+impl CacheSettings {
+    pub fn max_age(&self) -> Duration {
+        Duration::from_secs(self.max_age * 60)
+    }
+}
+
+struct Entry {
+    response: Response,
+    fetched: SystemTime,
+    used: Instant,
+}
+
+impl Entry {
+    fn size(&self) -> u64 {
+        (self.response.meta.len() + self.response.data.len()) as u64
+    }
+}
+
+#[derive(Default)]
+struct Entries {
+    entries: HashMap<String, Entry>,
+    settings: CacheSettings,
+}
+
+impl Entries {
+    fn size(&self) -> u64 {
+        self.entries.values().map(Entry::size).sum()
+    }
+
+    /// Drop stale pages, then the least recently used ones until the rest
+    /// fit in the budget.
+    fn evict(&mut self) {
+        let max_age = self.settings.max_age();
+
+        self.entries
+            .retain(|_, entry| is_fresh(entry.fetched, max_age));
+
+        let mut size = self.size();
+
+        while size > self.settings.memory_size * MB {
+            let Some(url) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(url, _)| url.clone())
+            else {
+                break;
+            };
+
+            if let Some(entry) = self.entries.remove(&url) {
+                size -= entry.size();
+            }
+        }
+    }
+}
+
+/// Successful responses by url. Clones share the same entries.
+#[derive(Clone)]
+pub struct Cache {
+    entries: Arc<Mutex<Entries>>,
+    /// Where pages are saved, if there's anywhere to save them.
+    dir: Option<PathBuf>,
+}
+
+impl Cache {
+    pub fn new(settings: &CacheSettings) -> Self {
+        let cache = Self {
+            entries: Arc::default(),
+            dir: config::cache_dir().map(|dir| dir.join("pages")),
+        };
+
+        cache.apply(settings);
+        cache
+    }
+
+    /// Use new settings, evicting pages if the cache got smaller.
+    pub fn apply(&self, settings: &CacheSettings) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.settings = settings.clone();
+            entries.evict();
+        }
+
+        if let Some(dir) = &self.dir {
+            evict_disk(dir, settings.disk_size * MB);
+        }
+    }
+
+    fn settings(&self) -> CacheSettings {
+        self.entries
+            .lock()
+            .map(|entries| entries.settings.clone())
+            .unwrap_or_default()
+    }
+
+    /// A fresh copy of a page, from memory or else from disk.
+    pub fn get(&self, url: &str) -> Option<Response> {
+        let settings = {
+            let mut entries = self.entries.lock().ok()?;
+            let max_age = entries.settings.max_age();
+
+            if let Some(entry) = entries
+                .entries
+                .get_mut(url)
+                .filter(|entry| is_fresh(entry.fetched, max_age))
+            {
+                entry.used = Instant::now();
+                return Some(entry.response.clone());
+            }
+
+            entries.settings.clone()
+        };
+
+        let (fetched, response) = read_disk(self.dir.as_ref()?, url)?;
+
+        if !is_fresh(fetched, settings.max_age()) {
+            return None;
+        }
+
+        self.remember(url, &response, fetched);
+        Some(response)
+    }
+
+    pub fn insert(&self, url: &str, response: &Response) {
+        let fetched = SystemTime::now();
+
+        self.remember(url, response, fetched);
+
+        let disk_size = self.settings().disk_size * MB;
+
+        // whatever was typed into the query could be private
+        if disk_size == 0 || url.contains('?') {
+            return;
+        }
+
+        if let Some(dir) = &self.dir {
+            if let Err(err) = write_disk(dir, url, response, fetched) {
+                eprintln!("Error saving {url} to the cache: {err}");
+            }
+
+            evict_disk(dir, disk_size);
+        }
+    }
+
+    /// Keep a page in memory.
+    fn remember(&self, url: &str, response: &Response, fetched: SystemTime) {
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+
+        entries.entries.insert(
+            url.to_string(),
+            Entry {
+                response: response.clone(),
+                fetched,
+                used: Instant::now(),
+            },
+        );
+        entries.evict();
+    }
+
+    /// How many pages are in memory, and how many bytes they take up.
+    pub fn memory_usage(&self) -> (usize, u64) {
+        self.entries
+            .lock()
+            .map(|entries| (entries.entries.len(), entries.size()))
+            .unwrap_or_default()
+    }
+
+    /// The urls of the pages in memory and their size, biggest first.
+    pub fn memory_entries(&self) -> Vec<(String, u64)> {
+        let mut pages: Vec<(String, u64)> = self
+            .entries
+            .lock()
+            .map(|entries| {
+                entries
+                    .entries
+                    .iter()
+                    .map(|(url, entry)| (url.clone(), entry.size()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        pages.sort_by_key(|(_, size)| Reverse(*size));
+        pages
+    }
+
+    /// How many pages are on disk, and how many bytes they take up.
+    pub fn disk_usage(&self) -> (usize, u64) {
+        let files = self.dir.as_deref().map(disk_files).unwrap_or_default();
+
+        (files.len(), files.iter().map(|(_, size, _)| size).sum())
+    }
+
+    /// Forget a page, so it's fetched again next time.
+    pub fn remove(&self, url: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.entries.remove(url);
+        }
+
+        if let Some(dir) = &self.dir {
+            fs::remove_file(disk_path(dir, url)).ok();
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.entries.clear();
+        }
+
+        if let Some(dir) = &self.dir {
+            evict_disk(dir, 0);
+        }
+    }
+}
+
+fn is_fresh(fetched: SystemTime, max_age: Duration) -> bool {
+    fetched.elapsed().is_ok_and(|elapsed| elapsed < max_age)
+}
+
+/// Pages are saved in a file named after a hash of their url. The file
+/// starts with the url and when it was fetched, then the response as it
+/// came over the wire. Its modified time is when it was last used.
+fn disk_path(dir: &Path, url: &str) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+
+    dir.join(format!("{:016x}", hasher.finish()))
+}
+
+fn read_disk(dir: &Path, url: &str) -> Option<(SystemTime, Response)> {
+    let path = disk_path(dir, url);
+    let data = fs::read(&path).ok()?;
+
+    let mut parts = data.splitn(3, |byte| *byte == b'\n');
+    let saved_url = parts.next()?;
+    let fetched = std::str::from_utf8(parts.next()?).ok()?.parse().ok()?;
+    let response = Response::try_from(parts.next()?).ok()?;
+
+    // two urls with the same hash
+    if saved_url != url.as_bytes() {
+        return None;
+    }
+
+    if let Ok(file) = File::options().write(true).open(&path) {
+        file.set_modified(SystemTime::now()).ok();
+    }
+
+    Some((UNIX_EPOCH + Duration::from_secs(fetched), response))
+}
+
+fn write_disk(
+    dir: &Path,
+    url: &str,
+    response: &Response,
+    fetched: SystemTime,
+) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let fetched = fetched
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let mut file = File::create(disk_path(dir, url))?;
+
+    write!(
+        file,
+        "{url}\n{fetched}\n{} {}\r\n",
+        u8::from(response.status),
+        response.meta
+    )?;
+    file.write_all(&response.data)
+}
+
+/// The files in the cache folder, with their size and when they were used.
+fn disk_files(dir: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let used = metadata.modified().ok()?;
+
+            metadata
+                .is_file()
+                .then(|| (entry.path(), metadata.len(), used))
+        })
+        .collect()
+}
+
+/// Delete the least recently used pages until the rest fit in `budget`.
+fn evict_disk(dir: &Path, budget: u64) {
+    let mut files = disk_files(dir);
+    let mut size: u64 = files.iter().map(|(_, size, _)| size).sum();
+
+    files.sort_by_key(|(_, _, used)| *used);
+
+    for (path, file_size, _) in files {
+        if size <= budget {
+            break;
+        }
+
+        if let Err(err) = fs::remove_file(&path) {
+            eprintln!("Error removing {} from the cache: {err}", path.display());
+        }
+
+        size -= file_size;
+    }
+}
+
+/// Answers from the cache when it can, and caches whatever `inner` fetches.
+pub struct CachingFetcher {
+    inner: Arc<dyn Fetcher>,
+    cache: Cache,
+    /// Go to the network even if the cache has the page, for reloads.
+    refresh: bool,
+}
+
+impl CachingFetcher {
+    pub fn new(inner: Arc<dyn Fetcher>, cache: Cache, refresh: bool) -> Self {
+        Self {
+            inner,
+            cache,
+            refresh,
+        }
+    }
+}
+
+impl Fetcher for CachingFetcher {
+    fn fetch(&self, url: &Url) -> Result<Response, String> {
+        self.open(url)?.into_response()
+    }
+
+    fn open(&self, url: &Url) -> Result<Stream, String> {
+        let key = url.to_string();
+
+        if !self.refresh {
+            if let Some(response) = self.cache.get(&key) {
+                return Ok(Stream::from(response));
+            }
+        }
+
+        let mut stream = self.inner.open(url)?;
+        let hook = stream.on_response.take();
+        let cache = self.cache.clone();
+
+        stream.on_response = Some(Box::new(move |response| {
+            if let Some(hook) = hook {
+                hook(response);
+            }
+
+            // downloads are streamed to disk and come through here empty
+            if matches!(response.status, StatusCode::Success(_)) && !response.data.is_empty() {
+                cache.insert(&key, response);
+            }
+        }));
+
+        Ok(stream)
+    }
+}
@@ -0,0 +1,514 @@
+use directories_next::ProjectDirs;
+use eframe::epaint::Color32;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cache::CacheSettings,
+    dns::IpVersion,
+    downloads,
+    fonts::FontSettings,
+    input,
+    plugin::ExternalPlugin,
+    settings::{self, Change},
+    toolbar::Toolbar,
+    view::GemtextTheme,
+    DEFAULT_STARTING_PAGE,
+};
+
+pub const DEFAULT_SEARCH_ENGINE: &str = "gemini://geminispace.info/search";
+/// Settings changed in gbrowse, rather than in `config.ron`.
+const STATE_FILE: &str = "state.ron";
+
+/// Bangs that work without any configuration.
+const BUILTIN_SEARCH_ENGINES: &[(&str, &str)] = &[
+    ("gs", "gemini://geminispace.info/search"),
+    ("au", "gemini://auragem.letz.dev/search/s"),
+];
+
+/// User settings, read from `config.ron` in the config directory.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// Settings for individual capsules, keyed by host.
+    pub sites: HashMap<String, SiteSettings>,
+    /// Hosts that are never connected to. Subdomains are blocked too.
+    pub blocked_hosts: Vec<String>,
+    /// Retry temporary failures (4x) with exponential backoff.
+    pub auto_retry: bool,
+    /// Pre-fill input prompts with what was typed into them last time.
+    pub remember_inputs: bool,
+    /// Urls of the pinned tabs, opened at startup.
+    pub pinned_tabs: Vec<String>,
+    /// The buttons in the top bar and their order, e.g. `[Home, Url, Bookmarks, Window]`.
+    pub toolbar: Toolbar,
+    /// Light or dark, or whichever the system uses.
+    pub theme: Theme,
+    /// The page opened at startup and in new tabs.
+    pub home_page: Option<String>,
+    /// Show the bookmarks in the toolbar folder under the url bar.
+    pub bookmarks_toolbar: bool,
+    /// Where downloads are saved. Defaults to the system downloads folder.
+    pub download_dir: Option<PathBuf>,
+    /// What to do with responses of each MIME type, e.g.
+    /// `"application/pdf": Open`. Patterns like `"image/*"` work too.
+    pub mime_types: HashMap<String, MimeAction>,
+    /// The search page used to look up text from a page. Defaults to
+    /// geminispace.info.
+    pub search_engine: Option<String>,
+    /// More search pages, picked by starting a search with `!` and their
+    /// name, e.g. `"kl": "gemini://kennedy.gemi.dev/search"` for `!kl`.
+    pub search_engines: HashMap<String, String>,
+    /// Scale the whole interface on top of the system's scale, e.g. `1.25`
+    /// when everything looks too small.
+    pub ui_scale: Option<f32>,
+    /// Fonts for the text of pages.
+    pub fonts: FontSettings,
+    /// The style for every capsule. Each capsule's own style goes on top.
+    pub style: SiteStyle,
+    /// The language of the interface, like `"de"`. Defaults to the
+    /// system's language.
+    pub language: Option<String>,
+    /// Look hosts up with this DNS server, like `"9.9.9.9"`, or this
+    /// DNS-over-HTTPS url, like `"https://dns.quad9.net/dns-query"`,
+    /// instead of the system's resolver.
+    pub resolver: Option<String>,
+    /// Whether to connect over IPv6 or IPv4 when a host has both.
+    pub ip_version: IpVersion,
+    /// Read out the focused link or button with egui's text to speech. This
+    /// isn't AccessKit, so screen readers still can't see the page, and it
+    /// only speaks when built with the `eframe/screen_reader` feature.
+    pub screen_reader: bool,
+    /// Load gemini links when they're hovered to preview the page they go
+    /// to. Off by default, since it makes requests for pages you might not
+    /// visit.
+    pub link_previews: bool,
+    /// Show how long each step of loading the page took at the bottom of
+    /// the window.
+    pub show_timing: bool,
+    /// Print every request and its status to stdout, for debugging. Takes
+    /// effect the next time gbrowse starts.
+    pub log_requests: bool,
+    /// Programs that serve their own schemes or filter responses, run on
+    /// each request they're for. Takes effect the next time gbrowse starts.
+    pub plugins: Vec<ExternalPlugin>,
+    /// Put an icon in the system tray to bring the window up, open a tab or
+    /// read unread posts from. Needs gbrowse built with the `tray` feature
+    /// and a tray on Linux with X11. Takes effect the next time gbrowse
+    /// starts.
+    pub tray_icon: bool,
+    /// Keep the window above other windows. eframe can only set this when
+    /// the window is opened.
+    pub always_on_top: bool,
+    /// Load this many of the first links on a page in the background while
+    /// it's read, so following them is instant on a slow connection.
+    /// `0` turns it off.
+    pub prefetch_links: usize,
+    /// How much the cache holds.
+    pub cache: CacheSettings,
+    /// How often watched pages are checked for changes, in minutes.
+    /// Defaults to an hour.
+    pub watch_interval: Option<u64>,
+    /// A `titan://` url to sync the bookmarks to, like
+    /// `"titan://example.org/bookmarks.ron"`. They're read back from the
+    /// same place over gemini.
+    pub bookmark_sync: Option<String>,
+    /// The token the capsule wants with uploads, if it wants one.
+    pub bookmark_sync_token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+pub enum Theme {
+    #[default]
+    System,
+    Dark,
+    Light,
+}
+
+/// What to do with a response.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub enum MimeAction {
+    /// Show it in the browser.
+    #[default]
+    Inline,
+    /// Save it to the downloads folder.
+    Download,
+    /// Save it, then open it with the system's default program.
+    Open,
+    /// Save it to a temporary file and hand it to a program, e.g.
+    /// `Command("mpv")`. `%f` is replaced with the file, otherwise it's
+    /// added to the end.
+    Command(String),
+}
+
+/// Look up what to do with a MIME type, preferring an exact match over a
+/// `type/*` pattern.
+pub fn mime_action(actions: &HashMap<String, MimeAction>, mime: &str) -> MimeAction {
+    let mime = essence(mime);
+    let wildcard = format!("{}/*", mime.split('/').next().unwrap_or_default());
+
+    actions
+        .get(mime)
+        .or_else(|| actions.get(&wildcard))
+        .cloned()
+        .unwrap_or_default()
+}
+
+impl Config {
+    /// Load the config file, falling back to the defaults if it's missing
+    /// or can't be parsed, with the settings changed in gbrowse on top.
+    pub fn load() -> Self {
+        let changes: Vec<Change> = read_file(STATE_FILE);
+
+        settings::apply(read_file("config.ron"), &changes)
+    }
+
+    /// Keep the settings changed in gbrowse in `state.ron`, leaving
+    /// `config.ron` the way it was written.
+    pub fn save(&self) {
+        let written = read_file("config.ron");
+
+        write_file(STATE_FILE, &settings::changes(written, self));
+    }
+
+    /// Write `config.ron` for the first time.
+    pub fn create(&self) {
+        write_file("config.ron", self);
+    }
+
+    /// Whether gbrowse hasn't been set up on this computer yet.
+    pub fn first_run() -> bool {
+        config_dir().is_some_and(|dir| !dir.join("config.ron").exists())
+    }
+
+    pub fn is_blocked(&self, host: &str) -> bool {
+        self.blocked_hosts
+            .iter()
+            .any(|blocked| host_matches(host, blocked))
+    }
+
+    /// Remove every blocklist entry that covers `host`.
+    pub fn unblock(&mut self, host: &str) {
+        self.blocked_hosts
+            .retain(|blocked| !host_matches(host, blocked));
+    }
+
+    pub fn home_page(&self) -> &str {
+        self.home_page.as_deref().unwrap_or(DEFAULT_STARTING_PAGE)
+    }
+
+    pub fn download_dir(&self) -> PathBuf {
+        self.download_dir
+            .clone()
+            .unwrap_or_else(downloads::default_dir)
+    }
+
+    pub fn search_engine(&self) -> &str {
+        self.search_engine
+            .as_deref()
+            .unwrap_or(DEFAULT_SEARCH_ENGINE)
+    }
+
+    /// The url for a search, using the engine named by a leading bang like
+    /// `!au` or the default one otherwise.
+    pub fn search_url(&self, query: &str) -> String {
+        let query = query.trim();
+
+        if let Some((bang, rest)) = query
+            .strip_prefix('!')
+            .map(|query| query.split_once(' ').unwrap_or((query, "")))
+        {
+            let engine = self
+                .search_engines
+                .get(bang)
+                .map(String::as_str)
+                .or_else(|| {
+                    BUILTIN_SEARCH_ENGINES
+                        .iter()
+                        .find(|(name, _)| *name == bang)
+                        .map(|(_, url)| *url)
+                });
+
+            if let Some(engine) = engine {
+                return input::with_query(engine, rest.trim());
+            }
+        }
+
+        input::with_query(self.search_engine(), query)
+    }
+
+    /// Whether what's typed into input prompts on `host` should be kept.
+    pub fn remember_inputs(&self, host: Option<&str>) -> bool {
+        self.remember_inputs && !self.site(host).forget_inputs
+    }
+
+    /// The settings for a host, or the defaults if there are none.
+    pub fn site(&self, host: Option<&str>) -> SiteSettings {
+        host.and_then(|host| self.sites.get(host))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The settings for every host and the blocklist, to take along when
+    /// loading a page on another thread, since redirects can go to other
+    /// hosts.
+    pub fn sites(&self) -> Sites {
+        Sites {
+            sites: self.sites.clone(),
+            blocked_hosts: self.blocked_hosts.clone(),
+        }
+    }
+
+    /// The identity each host is always connected to with, by host.
+    pub fn identities(&self) -> HashMap<String, String> {
+        self.sites
+            .iter()
+            .filter_map(|(host, site)| Some((host.clone(), site.identity.clone()?)))
+            .collect()
+    }
+}
+
+/// The settings of every host and the blocklist, taken from the config.
+#[derive(Default, Clone)]
+pub struct Sites {
+    sites: HashMap<String, SiteSettings>,
+    blocked_hosts: Vec<String>,
+}
+
+impl Sites {
+    /// The settings for a host, or the defaults if there are none.
+    pub fn get(&self, host: &str) -> SiteSettings {
+        self.sites.get(host).cloned().unwrap_or_default()
+    }
+
+    pub fn is_blocked(&self, host: &str) -> bool {
+        self.blocked_hosts
+            .iter()
+            .any(|blocked| host_matches(host, blocked))
+    }
+}
+
+/// Preferences for a single capsule, consulted when requesting and
+/// rendering its pages.
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct SiteSettings {
+    pub style: SiteStyle,
+    /// Refuse redirects that point to a different host.
+    pub same_host_redirects: bool,
+    /// MIME types that are saved to the downloads folder instead of shown,
+    /// e.g. `"image/*"` or `"application/pdf"`.
+    pub download: Vec<String>,
+    /// Never remember input typed into prompts on this capsule.
+    pub forget_inputs: bool,
+    /// Always connect with this client certificate, the name of a PEM file
+    /// holding it and its key in the `identities` folder of the config
+    /// directory, e.g. `"me"` for `identities/me.pem`.
+    pub identity: Option<String>,
+}
+
+impl SiteSettings {
+    pub fn should_download(&self, mime: &str) -> bool {
+        let mime = essence(mime);
+
+        self.download
+            .iter()
+            .any(|pattern| match pattern.strip_suffix("/*") {
+                Some(kind) => mime.split('/').next() == Some(kind),
+                None => pattern == mime,
+            })
+    }
+}
+
+/// Overrides for how pages from a single capsule are drawn. Anything left
+/// out keeps the default.
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct SiteStyle {
+    pub text_size: Option<f32>,
+    pub text_color: Option<[u8; 3]>,
+    pub link_color: Option<[u8; 3]>,
+    pub content_width: Option<f32>,
+    /// Extra space between the lines of a paragraph.
+    pub line_spacing: Option<f32>,
+    /// Space between paragraphs and other lines of gemtext.
+    pub paragraph_spacing: Option<f32>,
+    /// Line paragraphs up with both edges.
+    pub justify: Option<bool>,
+    /// Remembered from the last time the page was zoomed.
+    pub zoom: Option<f32>,
+}
+
+impl SiteStyle {
+    pub fn apply(&self, theme: &mut GemtextTheme) {
+        let color = |[r, g, b]: [u8; 3]| Color32::from_rgb(r, g, b);
+
+        if let Some(text_size) = self.text_size {
+            theme.text_size = Some(text_size);
+        }
+
+        if let Some(text_color) = self.text_color {
+            theme.text_color = Some(color(text_color));
+        }
+
+        if let Some(link_color) = self.link_color {
+            theme.link_color = Some(color(link_color));
+        }
+
+        if let Some(content_width) = self.content_width {
+            theme.content_width = Some(content_width);
+        }
+
+        if let Some(line_spacing) = self.line_spacing {
+            theme.line_spacing = line_spacing;
+        }
+
+        if let Some(paragraph_spacing) = self.paragraph_spacing {
+            theme.paragraph_spacing = Some(paragraph_spacing);
+        }
+
+        if let Some(justify) = self.justify {
+            theme.justify = justify;
+        }
+
+        if let Some(zoom) = self.zoom {
+            theme.zoom = zoom;
+        }
+    }
+}
+
+/// A MIME type without its parameters, e.g. `text/gemini` for
+/// `text/gemini; lang=en`.
+fn essence(mime: &str) -> &str {
+    mime.split(';').next().unwrap_or_default().trim()
+}
+
+/// Whether `host` is `domain` or one of its subdomains.
+fn host_matches(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|sub| sub.ends_with('.'))
+}
+
+/// Read a file from the config directory, falling back to the default if
+/// it's missing or can't be parsed.
+pub fn read_file<T: DeserializeOwned + Default>(name: &str) -> T {
+    let Some(path) = config_dir().map(|dir| dir.join(name)) else {
+        return T::default();
+    };
+
+    let Ok(text) = fs::read_to_string(&path) else {
+        return T::default();
+    };
+
+    match ron::from_str(&text) {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("Error reading {}: {err}", path.display());
+            T::default()
+        }
+    }
+}
+
+/// Read a file like the ones in the config directory from somewhere else,
+/// like another computer's config directory.
+pub fn read_path<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("Error reading {}: {err}", path.display()))?;
+
+    ron::from_str(&text).map_err(|err| format!("Error reading {}: {err}", path.display()))
+}
+
+/// Write a file into the config directory, creating it if needed. It's
+/// written next to the old one first and then moved over it, so a crash
+/// halfway through doesn't leave a broken file behind.
+pub fn write_file<T: Serialize>(name: &str, value: &T) {
+    let Some(dir) = config_dir() else {
+        return;
+    };
+
+    let text = match ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default()) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("Error serializing {name}: {err}");
+            return;
+        }
+    };
+
+    let temp = dir.join(format!("{name}.tmp"));
+
+    if let Err(err) = fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(&temp, text))
+        .and_then(|_| fs::rename(&temp, dir.join(name)))
+    {
+        eprintln!("Error writing {name}: {err}");
+    }
+}
+
+/// Where gbrowse keeps its files, e.g. `~/.config/gbrowse` on Linux. Tests
+/// don't get one, so they never read or write the real files.
+pub fn config_dir() -> Option<PathBuf> {
+    if cfg!(test) {
+        return None;
+    }
+
+    ProjectDirs::from("", "", "gbrowse").map(|dirs| dirs.config_dir().to_path_buf())
+}
+
+pub fn cache_dir() -> Option<PathBuf> {
+    if cfg!(test) {
+        return None;
+    }
+
+    ProjectDirs::from("", "", "gbrowse").map(|dirs| dirs.cache_dir().to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocking(hosts: &[&str]) -> Config {
+        Config {
+            blocked_hosts: hosts.iter().map(|host| host.to_string()).collect(),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn matches_host_and_subdomains() {
+        assert!(host_matches("b.org", "b.org"));
+        assert!(host_matches("a.b.org", "b.org"));
+        assert!(host_matches("x.a.b.org", "b.org"));
+    }
+
+    #[test]
+    fn doesnt_match_suffix_without_dot() {
+        assert!(!host_matches("notb.org", "b.org"));
+        assert!(!host_matches("b.org", "a.b.org"));
+        assert!(!host_matches("b.org.evil", "b.org"));
+    }
+
+    #[test]
+    fn blocks_subdomains() {
+        let config = blocking(&["b.org"]);
+
+        assert!(config.is_blocked("a.b.org"));
+        assert!(!config.is_blocked("notb.org"));
+        assert!(config.sites().is_blocked("a.b.org"));
+        assert!(!config.sites().is_blocked("c.org"));
+    }
+
+    #[test]
+    fn unblocks_every_covering_entry() {
+        let mut config = blocking(&["b.org", "a.b.org", "notb.org"]);
+        config.unblock("a.b.org");
+
+        assert_eq!(config.blocked_hosts, ["notb.org"]);
+    }
+}
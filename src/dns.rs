@@ -0,0 +1,224 @@
+//! Looking up hosts and connecting to them. Lookups are remembered for the
+//! rest of the session, and when a host has both IPv6 and IPv4 addresses
+//! they're tried side by side ("happy eyeballs"), so a capsule with a
+//! broken AAAA record doesn't hang.
+
+use serde::{Deserialize, Serialize};
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{SocketAddr, TcpStream},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex, RwLock,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::resolver::Resolver;
+
+/// How long to give an address before trying the next one as well.
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Addresses by host and port.
+type Lookups = HashMap<(String, u16), Vec<SocketAddr>>;
+
+static LOOKUPS: Mutex<Option<Lookups>> = Mutex::new(None);
+static RESOLVER: RwLock<Resolver> = RwLock::new(Resolver::System);
+/// The IP version from the config, and the one from the command line, which
+/// wins.
+static IP_VERSION: RwLock<(IpVersion, Option<IpVersion>)> = RwLock::new((IpVersion::Any, None));
+
+/// Which kind of address to connect to when a host has both.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+pub enum IpVersion {
+    /// Try both at once and take whichever answers first.
+    #[default]
+    Any,
+    /// Try IPv6 addresses first, then IPv4 ones.
+    PreferIpv6,
+    PreferIpv4,
+    /// Only ever use IPv6.
+    Ipv6,
+    Ipv4,
+}
+
+impl FromStr for IpVersion {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        match text {
+            "any" => Ok(Self::Any),
+            "prefer-ipv6" => Ok(Self::PreferIpv6),
+            "prefer-ipv4" => Ok(Self::PreferIpv4),
+            "ipv6" => Ok(Self::Ipv6),
+            "ipv4" => Ok(Self::Ipv4),
+            _ => Err(format!(
+                "{text} should be any, prefer-ipv6, prefer-ipv4, ipv6 or ipv4"
+            )),
+        }
+    }
+}
+
+/// Prefer or stick to one kind of address, as the config says.
+pub fn set_ip_version(version: IpVersion) {
+    if let Ok(mut current) = IP_VERSION.write() {
+        current.0 = version;
+    }
+}
+
+/// Use this IP version for the rest of the session, whatever the config
+/// says.
+pub fn override_ip_version(version: IpVersion) {
+    if let Ok(mut current) = IP_VERSION.write() {
+        current.1 = Some(version);
+    }
+}
+
+/// Look hosts up with a DNS server or DNS-over-HTTPS url from the config,
+/// or with the system's resolver for `None`.
+pub fn set_resolver(resolver: Option<&str>) {
+    let resolver = match resolver.map(Resolver::parse) {
+        Some(Ok(resolver)) => resolver,
+        Some(Err(err)) => {
+            eprintln!("Error reading the resolver: {err}");
+            Resolver::System
+        }
+        None => Resolver::System,
+    };
+
+    if let Ok(mut current) = RESOLVER.write() {
+        *current = resolver;
+    }
+
+    if let Ok(mut lookups) = LOOKUPS.lock() {
+        *lookups = None;
+    }
+}
+
+/// The addresses of a host, from earlier in the session if it's been
+/// looked up before.
+pub fn lookup(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    let key = (host.to_string(), port);
+
+    if let Some(addrs) = LOOKUPS
+        .lock()
+        .ok()
+        .and_then(|lookups| lookups.as_ref()?.get(&key).cloned())
+    {
+        return Ok(addrs);
+    }
+
+    let resolver = RESOLVER
+        .read()
+        .map(|resolver| resolver.clone())
+        .unwrap_or(Resolver::System);
+
+    let addrs: Vec<SocketAddr> = resolver
+        .resolve(host)?
+        .into_iter()
+        .map(|ip| SocketAddr::new(ip, port))
+        .collect();
+
+    if let Ok(mut lookups) = LOOKUPS.lock() {
+        lookups
+            .get_or_insert_with(HashMap::new)
+            .insert(key, addrs.clone());
+    }
+
+    Ok(addrs)
+}
+
+/// Forget a lookup, so the host is looked up again next time.
+fn forget(host: &str, port: u16) {
+    if let Ok(mut lookups) = LOOKUPS.lock() {
+        if let Some(lookups) = lookups.as_mut() {
+            lookups.remove(&(host.to_string(), port));
+        }
+    }
+}
+
+/// Connect to whichever address of a host answers first. Each address gets
+/// a head start of [`ATTEMPT_DELAY`] over the next one, in the order from
+/// [`order`].
+pub fn connect(host: &str, port: u16) -> io::Result<TcpStream> {
+    let version = IP_VERSION
+        .read()
+        .map(|version| version.1.unwrap_or(version.0))
+        .unwrap_or_default();
+
+    let addrs = order(lookup(host, port)?, version);
+
+    if addrs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no addresses for {host}"),
+        ));
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let connected = Arc::new(AtomicBool::new(false));
+
+    for (attempt, addr) in addrs.into_iter().enumerate() {
+        let tx = tx.clone();
+        let connected = connected.clone();
+
+        thread::spawn(move || {
+            thread::sleep(ATTEMPT_DELAY * attempt as u32);
+
+            if !connected.load(Ordering::Relaxed) {
+                tx.send(TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT))
+                    .ok();
+            }
+        });
+    }
+
+    drop(tx);
+
+    let mut last_err = None;
+
+    for result in rx {
+        match result {
+            Ok(stream) => {
+                connected.store(true, Ordering::Relaxed);
+                return Ok(stream);
+            }
+            Err(err) => last_err = Some(err),
+        }
+    }
+
+    // the addresses might have changed since they were looked up
+    forget(host, port);
+
+    Err(last_err.unwrap_or_else(|| io::Error::other(format!("couldn't connect to {host}"))))
+}
+
+/// The order to try addresses in. By default that's alternating between
+/// IPv6 and IPv4, starting with IPv6.
+fn order(addrs: Vec<SocketAddr>, version: IpVersion) -> Vec<SocketAddr> {
+    let (mut v6, mut v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(SocketAddr::is_ipv6);
+
+    match version {
+        IpVersion::PreferIpv6 => return v6.into_iter().chain(v4).collect(),
+        IpVersion::PreferIpv4 => return v4.into_iter().chain(v6).collect(),
+        IpVersion::Ipv6 => return v6,
+        IpVersion::Ipv4 => return v4,
+        IpVersion::Any => {}
+    }
+
+    let mut addrs = Vec::with_capacity(v6.len() + v4.len());
+
+    v6.reverse();
+    v4.reverse();
+
+    loop {
+        match (v6.pop(), v4.pop()) {
+            (None, None) => return addrs,
+            (a, b) => addrs.extend(a.into_iter().chain(b)),
+        }
+    }
+}
@@ -0,0 +1,400 @@
+use directories_next::UserDirs;
+use eframe::egui::{self, RichText};
+
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    config::{self, Config, MimeAction, SiteSettings},
+    i18n::tr,
+};
+
+/// How often progress is reported, in bytes.
+const PROGRESS_STEP: u64 = 64 * 1024;
+
+/// A file being saved from a response.
+pub struct Download {
+    pub url: String,
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub state: DownloadState,
+    control: Arc<Control>,
+}
+
+#[derive(PartialEq)]
+pub enum DownloadState {
+    InProgress,
+    Complete,
+    Cancelled,
+    Failed(String),
+}
+
+/// Lets the UI pause or cancel a download running on another thread.
+#[derive(Default)]
+struct Control {
+    paused: AtomicBool,
+    cancelled: AtomicBool,
+}
+
+enum Update {
+    Started {
+        id: u64,
+        url: String,
+        path: PathBuf,
+        control: Arc<Control>,
+    },
+    Progress {
+        id: u64,
+        bytes: u64,
+    },
+    Finished {
+        id: u64,
+        state: DownloadState,
+    },
+}
+
+/// Starts downloads from the threads that load pages, and reports back to
+/// [`Downloads`].
+#[derive(Clone)]
+pub struct Downloader {
+    dir: PathBuf,
+    actions: HashMap<String, MimeAction>,
+    tx: Sender<Update>,
+}
+
+impl Downloader {
+    /// What to do with a response. The capsule's own download list wins
+    /// over the global rules.
+    pub fn action(&self, mime: &str, settings: &SiteSettings) -> MimeAction {
+        if settings.should_download(mime) {
+            MimeAction::Download
+        } else {
+            config::mime_action(&self.actions, mime)
+        }
+    }
+
+    /// Save a response body on a new thread, returning where it's going.
+    pub fn start(
+        &self,
+        url: &str,
+        name: &str,
+        mut body: Box<dyn Read + Send>,
+        action: MimeAction,
+    ) -> Result<PathBuf, String> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        // files for other programs don't need to clutter the downloads folder
+        let dir = match action {
+            MimeAction::Command(_) => env::temp_dir().join("gbrowse"),
+            _ => self.dir.clone(),
+        };
+
+        let (path, mut file) = create_file(&dir, name)?;
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let control = Arc::new(Control::default());
+        let tx = self.tx.clone();
+
+        tx.send(Update::Started {
+            id,
+            url: url.to_string(),
+            path: path.clone(),
+            control: control.clone(),
+        })
+        .ok();
+
+        let partial = path.clone();
+
+        thread::spawn(move || {
+            let state = match copy(&mut body, &mut file, &control, |bytes| {
+                tx.send(Update::Progress { id, bytes }).ok();
+            }) {
+                Ok(true) => DownloadState::Complete,
+                Ok(false) => DownloadState::Cancelled,
+                Err(err) => DownloadState::Failed(err.to_string()),
+            };
+
+            drop(file);
+
+            // don't leave half a file behind
+            let state = if state != DownloadState::Complete {
+                fs::remove_file(&partial).ok();
+                state
+            } else {
+                match action {
+                    MimeAction::Open => {
+                        open_path(&partial);
+                        state
+                    }
+                    MimeAction::Command(command) => match run_command(&command, &partial) {
+                        Ok(()) => state,
+                        Err(err) => DownloadState::Failed(err),
+                    },
+                    _ => state,
+                }
+            };
+
+            tx.send(Update::Finished { id, state }).ok();
+        });
+
+        Ok(path)
+    }
+}
+
+/// Start a program configured for a MIME type with the downloaded file.
+fn run_command(command: &str, path: &Path) -> Result<(), String> {
+    let path = path.display().to_string();
+    let mut args: Vec<String> = command.split_whitespace().map(str::to_string).collect();
+
+    if args.is_empty() {
+        return Err("No command given".to_string());
+    }
+
+    if !args.iter().any(|arg| arg.contains("%f")) {
+        args.push("%f".to_string());
+    }
+
+    let args: Vec<String> = args.iter().map(|arg| arg.replace("%f", &path)).collect();
+
+    process::Command::new(&args[0])
+        .args(&args[1..])
+        .spawn()
+        .map(|_| ())
+        .map_err(|err| format!("Error running {}: {err}", args[0]))
+}
+
+/// Copy the body into the file, returning `false` if it was cancelled.
+fn copy(
+    body: &mut dyn Read,
+    file: &mut File,
+    control: &Control,
+    mut progress: impl FnMut(u64),
+) -> io::Result<bool> {
+    let mut buf = [0; 8192];
+    let mut bytes = 0;
+    let mut reported = 0;
+
+    loop {
+        while control.paused.load(Ordering::Relaxed) && !control.cancelled.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        if control.cancelled.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+
+        let read = body.read(&mut buf)?;
+
+        if read == 0 {
+            progress(bytes);
+            return Ok(true);
+        }
+
+        file.write_all(&buf[..read])?;
+        bytes += read as u64;
+
+        if bytes - reported >= PROGRESS_STEP {
+            progress(bytes);
+            reported = bytes;
+        }
+    }
+}
+
+/// Create a new file in `dir`, adding a number to the name if it's taken so
+/// nothing gets overwritten.
+fn create_file(dir: &Path, name: &str) -> Result<(PathBuf, File), String> {
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{ext}")),
+        _ => (name, String::new()),
+    };
+
+    fs::create_dir_all(dir).map_err(|err| format!("Download Error: {err}"))?;
+
+    for n in 0.. {
+        let path = match n {
+            0 => dir.join(name),
+            n => dir.join(format!("{stem} ({n}){ext}")),
+        };
+
+        match File::options().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(format!("Download Error: {err}")),
+        }
+    }
+
+    unreachable!()
+}
+
+/// The downloads made since gbrowse was started, shown on `about:downloads`.
+pub struct Downloads {
+    downloads: Vec<(u64, Download)>,
+    tx: Sender<Update>,
+    rx: Receiver<Update>,
+}
+
+impl Downloads {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        Self {
+            downloads: vec![],
+            tx,
+            rx,
+        }
+    }
+
+    pub fn downloader(&self, config: &Config) -> Downloader {
+        Downloader {
+            dir: config.download_dir(),
+            actions: config.mime_types.clone(),
+            tx: self.tx.clone(),
+        }
+    }
+
+    pub fn in_progress(&self) -> bool {
+        self.active() > 0
+    }
+
+    /// How many downloads are still going.
+    pub fn active(&self) -> usize {
+        self.downloads
+            .iter()
+            .filter(|(_, download)| download.state == DownloadState::InProgress)
+            .count()
+    }
+
+    /// Pick up progress from the download threads.
+    pub fn poll(&mut self) {
+        while let Ok(update) = self.rx.try_recv() {
+            match update {
+                Update::Started {
+                    id,
+                    url,
+                    path,
+                    control,
+                } => self.downloads.push((
+                    id,
+                    Download {
+                        url,
+                        path,
+                        bytes: 0,
+                        state: DownloadState::InProgress,
+                        control,
+                    },
+                )),
+                Update::Progress { id, bytes } => {
+                    if let Some(download) = self.get_mut(id) {
+                        download.bytes = bytes;
+                    }
+                }
+                Update::Finished { id, state } => {
+                    if let Some(download) = self.get_mut(id) {
+                        download.state = state;
+                    }
+                }
+            }
+        }
+    }
+
+    fn get_mut(&mut self, id: u64) -> Option<&mut Download> {
+        self.downloads
+            .iter_mut()
+            .find(|(download_id, _)| *download_id == id)
+            .map(|(_, download)| download)
+    }
+
+    /// List the downloads, newest first.
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        if self.downloads.is_empty() {
+            ui.label(tr("Nothing has been downloaded yet."));
+        }
+
+        egui::Grid::new("downloads").striped(true).show(ui, |ui| {
+            for (_, download) in self.downloads.iter().rev() {
+                let name = download
+                    .path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+
+                ui.label(RichText::new(name).strong())
+                    .on_hover_text(&download.url);
+
+                ui.label(format_bytes(download.bytes));
+
+                match &download.state {
+                    DownloadState::InProgress => {
+                        let control = &download.control;
+                        let paused = control.paused.load(Ordering::Relaxed);
+
+                        if ui
+                            .button(tr(if paused { "▶ Resume" } else { "⏸ Pause" }))
+                            .clicked()
+                        {
+                            control.paused.store(!paused, Ordering::Relaxed);
+                        }
+
+                        if ui.button(tr("✖ Cancel")).clicked() {
+                            control.cancelled.store(true, Ordering::Relaxed);
+                        }
+                    }
+                    DownloadState::Complete => {
+                        if ui.button(tr("Open file")).clicked() {
+                            open_path(&download.path);
+                        }
+
+                        if ui.button(tr("Show in folder")).clicked() {
+                            if let Some(dir) = download.path.parent() {
+                                open_path(dir);
+                            }
+                        }
+                    }
+                    DownloadState::Cancelled => {
+                        ui.label(tr("Cancelled"));
+                    }
+                    DownloadState::Failed(err) => {
+                        ui.label(RichText::new(tr("Failed")).color(ui.visuals().error_fg_color))
+                            .on_hover_text(err);
+                    }
+                }
+
+                ui.end_row();
+            }
+        });
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{bytes} B"),
+        1024..=1048575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1048576.0),
+    }
+}
+
+/// The user's downloads folder, or the working directory if there isn't one.
+pub fn default_dir() -> PathBuf {
+    UserDirs::new()
+        .and_then(|dirs| dirs.download_dir().map(|dir| dir.to_path_buf()))
+        .unwrap_or_default()
+}
+
+/// Open a file or folder with the system's default program.
+pub fn open_path(path: &Path) {
+    if let Err(err) = open::that(path) {
+        eprintln!("Error opening {}: {err}", path.display());
+    }
+}
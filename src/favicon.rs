@@ -0,0 +1,73 @@
+use gmi::{protocol::StatusCode, url::Url};
+
+use std::{
+    collections::HashMap,
+    str,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+};
+
+use crate::fetch::Fetcher;
+
+/// Emoji favicons from `/favicon.txt`, fetched once per host.
+pub struct Favicons {
+    /// `None` while loading, or if the host doesn't have one.
+    icons: HashMap<String, Option<String>>,
+    tx: Sender<(String, Option<String>)>,
+    rx: Receiver<(String, Option<String>)>,
+}
+
+impl Favicons {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        Self {
+            icons: HashMap::new(),
+            tx,
+            rx,
+        }
+    }
+
+    /// The favicon for a host, starting to fetch it if we haven't yet.
+    pub fn get(&mut self, fetcher: &Arc<dyn Fetcher>, host: &str) -> Option<&str> {
+        while let Ok((host, icon)) = self.rx.try_recv() {
+            self.icons.insert(host, icon);
+        }
+
+        if !self.icons.contains_key(host) {
+            self.icons.insert(host.to_string(), None);
+
+            let fetcher = fetcher.clone();
+            let tx = self.tx.clone();
+            let host = host.to_string();
+
+            thread::spawn(move || {
+                let icon = fetch_favicon(fetcher.as_ref(), &host);
+                tx.send((host, icon)).ok();
+            });
+        }
+
+        self.icons.get(host)?.as_deref()
+    }
+}
+
+fn fetch_favicon(fetcher: &dyn Fetcher, host: &str) -> Option<String> {
+    let url = Url::try_from(format!("gemini://{host}/favicon.txt").as_str()).ok()?;
+    let response = fetcher.fetch(&url).ok()?;
+
+    if !matches!(response.status, StatusCode::Success(_)) || !response.meta.starts_with("text/") {
+        return None;
+    }
+
+    // the convention is a single emoji, so don't let a whole page through
+    let icon = str::from_utf8(&response.data).ok()?.trim();
+
+    if icon.is_empty() || icon.chars().count() > 8 || icon.contains(char::is_whitespace) {
+        return None;
+    }
+
+    Some(icon.to_string())
+}
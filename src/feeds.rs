@@ -0,0 +1,340 @@
+//! Subscriptions to gemlogs, kept in `subscriptions.ron`. A gemlog index
+//! is a page linking to its posts with labels starting with the date, like
+//! `=> post.gmi 2022-10-01 Hello`, as in gemini's subscription spec. Atom
+//! feeds that pages link to can be subscribed to as well.
+//!
+//! Subscriptions are fetched in the background every so often, and the
+//! posts that have been read are kept in `subscriptions.read.ron`, so
+//! they can be read through oldest first.
+
+use gmi::gemtext::{self, GemtextNode};
+use serde::{Deserialize, Serialize};
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::{self, Sites},
+    fetch::{self, Fetcher, Page},
+    nav,
+};
+
+/// How often each subscription is fetched again.
+pub const REFRESH: Duration = Duration::from_secs(60 * 60);
+const READ_FILE: &str = "subscriptions.read.ron";
+
+/// A page needs this many dated links to look like a gemlog index, so a
+/// single dated link on a page isn't mistaken for one.
+const MIN_DATED_LINKS: usize = 2;
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct Subscription {
+    /// The gemlog index or atom feed.
+    pub url: String,
+    pub title: String,
+}
+
+/// A post in a gemlog or atom feed.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub url: String,
+    pub title: String,
+    /// `YYYY-MM-DD`, which sorts the same as the dates do.
+    pub date: String,
+}
+
+pub struct Subscriptions {
+    subscriptions: Vec<Subscription>,
+    /// The posts in each subscription, by its url, as of the last fetch.
+    entries: HashMap<String, Vec<Entry>>,
+    read: HashSet<String>,
+    fetched: HashMap<String, Instant>,
+    /// Subscriptions being fetched right now.
+    fetching: HashSet<String>,
+    tx: Sender<(String, Result<String, String>)>,
+    rx: Receiver<(String, Result<String, String>)>,
+}
+
+impl Subscriptions {
+    pub fn load() -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        Self {
+            subscriptions: config::read_file("subscriptions.ron"),
+            entries: HashMap::new(),
+            read: config::read_file(READ_FILE),
+            fetched: HashMap::new(),
+            fetching: HashSet::new(),
+            tx,
+            rx,
+        }
+    }
+
+    fn save(&self) {
+        config::write_file("subscriptions.ron", &self.subscriptions);
+    }
+
+    pub fn all(&self) -> &[Subscription] {
+        &self.subscriptions
+    }
+
+    pub fn is_subscribed(&self, url: &str) -> bool {
+        self.subscriptions.iter().any(|sub| sub.url == url)
+    }
+
+    pub fn subscribe(&mut self, url: &str, title: &str) {
+        if !self.is_subscribed(url) {
+            self.subscriptions.push(Subscription {
+                url: url.to_string(),
+                title: title.to_string(),
+            });
+            self.save();
+        }
+    }
+
+    pub fn unsubscribe(&mut self, url: &str) {
+        self.subscriptions.retain(|sub| sub.url != url);
+        self.entries.remove(url);
+        self.fetched.remove(url);
+        self.save();
+    }
+
+    pub fn is_fetching(&self) -> bool {
+        !self.fetching.is_empty()
+    }
+
+    /// The posts that haven't been read, oldest first.
+    pub fn unread(&self) -> impl Iterator<Item = &Entry> {
+        let mut unread: Vec<&Entry> = self
+            .subscriptions
+            .iter()
+            .filter_map(|sub| self.entries.get(&sub.url))
+            .flatten()
+            .filter(|entry| !self.read.contains(&entry.url))
+            .collect();
+
+        unread.sort_by(|a, b| a.date.cmp(&b.date));
+        unread.dedup_by(|a, b| a.url == b.url);
+        unread.into_iter()
+    }
+
+    pub fn unread_count(&self) -> usize {
+        self.unread().count()
+    }
+
+    pub fn oldest_unread(&self) -> Option<&Entry> {
+        self.unread().next()
+    }
+
+    /// The oldest post that hasn't been read, which counts as read now.
+    pub fn next_unread(&mut self) -> Option<String> {
+        let url = self.oldest_unread()?.url.clone();
+
+        self.mark_read(&url);
+        Some(url)
+    }
+
+    /// A post was read, whether it came from [`Subscriptions::next_unread`]
+    /// or a link.
+    pub fn mark_read(&mut self, url: &str) {
+        let is_entry = self
+            .entries
+            .values()
+            .flatten()
+            .any(|entry| entry.url == url);
+
+        if is_entry && self.read.insert(url.to_string()) {
+            config::write_file(READ_FILE, &self.read);
+        }
+    }
+
+    /// Everything there is so far counts as read, to start from a clean
+    /// slate.
+    pub fn mark_all_read(&mut self) {
+        let urls: Vec<String> = self.unread().map(|entry| entry.url.clone()).collect();
+
+        self.read.extend(urls);
+        config::write_file(READ_FILE, &self.read);
+    }
+
+    /// Fetch the subscriptions that haven't been fetched for [`REFRESH`] in
+    /// the background.
+    pub fn fetch_due(&mut self, fetcher: Arc<dyn Fetcher>, sites: &Sites) {
+        for sub in &self.subscriptions {
+            let due = self
+                .fetched
+                .get(&sub.url)
+                .is_none_or(|fetched| fetched.elapsed() >= REFRESH);
+
+            if !due || self.fetching.contains(&sub.url) {
+                continue;
+            }
+
+            let url = sub.url.clone();
+            let fetcher = fetcher.clone();
+            let sites = sites.clone();
+            let tx = self.tx.clone();
+
+            self.fetching.insert(url.clone());
+
+            thread::spawn(move || {
+                let result = fetch::fetch_followed(&*fetcher, &url, &sites)
+                    .map(|response| String::from_utf8_lossy(&response.data).into_owned())
+                    .map_err(|err| err.to_string());
+
+                tx.send((url, result)).ok();
+            });
+        }
+    }
+
+    /// Read the posts out of the subscriptions that finished fetching.
+    pub fn poll(&mut self) {
+        for (url, result) in self.rx.try_iter() {
+            self.fetching.remove(&url);
+            // try again next time instead of right away
+            self.fetched.insert(url.clone(), Instant::now());
+
+            match result {
+                Ok(text) => {
+                    self.entries.insert(url.clone(), entries(&url, &text));
+                }
+                Err(err) => eprintln!("Error fetching subscription {url}: {err}"),
+            }
+        }
+    }
+}
+
+/// The posts in a gemlog index or atom feed.
+fn entries(url: &str, text: &str) -> Vec<Entry> {
+    if text.trim_start().starts_with('<') {
+        return atom_entries(url, text);
+    }
+
+    gemtext::parse_gemtext(text)
+        .into_iter()
+        .filter_map(|node| match node {
+            GemtextNode::Link(link, Some(label)) => {
+                let date = date(&label)?.to_string();
+                let title = label.trim_start()[10..]
+                    .trim_start_matches([' ', '-', '–', '—', ':'])
+                    .to_string();
+
+                Some(Entry {
+                    url: nav::resolve(url, &link)?,
+                    title,
+                    date,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Just enough of atom to get each entry's link, title and date, rather
+/// than pulling in an xml parser.
+fn atom_entries(url: &str, xml: &str) -> Vec<Entry> {
+    xml.split("<entry")
+        .skip(1)
+        .filter_map(|entry| {
+            let entry = entry.split("</entry>").next().unwrap_or_default();
+            let link = entry
+                .split("<link")
+                .skip(1)
+                .map(|link| link.split('>').next().unwrap_or_default())
+                .find(|link| attribute(link, "rel").is_none_or(|rel| rel == "alternate"))
+                .and_then(|link| attribute(link, "href"))?;
+            let date = element(entry, "updated")
+                .or_else(|| element(entry, "published"))
+                .and_then(|updated| updated.get(..10))?;
+
+            Some(Entry {
+                url: nav::resolve(url, &unescape(link))?,
+                title: element(entry, "title").map(unescape).unwrap_or_default(),
+                date: date.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The text inside the first `<name>` element.
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{name}"))?;
+    let inside = &xml[start..];
+    let inside = &inside[inside.find('>')? + 1..];
+    let text = &inside[..inside.find(&format!("</{name}>"))?];
+
+    Some(
+        text.trim()
+            .trim_start_matches("<![CDATA[")
+            .trim_end_matches("]]>"),
+    )
+}
+
+/// The value of an attribute in the inside of a tag.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!("{name}=");
+    let (index, _) = tag
+        .match_indices(&pattern)
+        .find(|(index, _)| tag[..*index].ends_with(char::is_whitespace))?;
+    let rest = &tag[index + pattern.len()..];
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+
+    rest[1..].split(quote).next()
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// What a page can be subscribed to: the page itself if it's a gemlog
+/// index, otherwise an atom feed it links to.
+pub fn detect(page: &Page) -> Option<String> {
+    let mut links = page.nodes.iter().filter_map(|node| match node {
+        GemtextNode::Link(url, label) => Some((url, label.as_deref().unwrap_or_default())),
+        _ => None,
+    });
+
+    if links
+        .clone()
+        .filter(|(_, label)| date(label).is_some())
+        .count()
+        >= MIN_DATED_LINKS
+    {
+        return Some(page.url.clone());
+    }
+
+    links
+        .find(|(url, label)| is_atom(url, label))
+        .and_then(|(url, _)| nav::resolve(&page.url, url))
+}
+
+/// The `YYYY-MM-DD` a gemlog link's label starts with.
+pub fn date(label: &str) -> Option<&str> {
+    let date = label.trim_start().get(..10)?;
+
+    let is_date = date.bytes().enumerate().all(|(index, byte)| match index {
+        4 | 7 => byte == b'-',
+        _ => byte.is_ascii_digit(),
+    });
+
+    is_date.then_some(date)
+}
+
+fn is_atom(url: &str, label: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+
+    path.ends_with(".xml") || path.ends_with(".atom") || label.to_lowercase().contains("atom")
+}
@@ -0,0 +1,883 @@
+use gmi::{
+    gemtext::{self, GemtextNode},
+    protocol::{Response, StatusCode},
+    url::Url,
+};
+
+use std::{
+    fmt,
+    io::{Cursor, Read},
+    path::PathBuf,
+    str,
+    time::Duration,
+};
+
+use crate::{
+    config::{MimeAction, Sites},
+    downloads::Downloader,
+    file,
+    gpub::{self, Book},
+    html, markdown, nav,
+    transport::{self, ServerCertificate, Timing},
+};
+
+/// Something that can turn a url into a raw response.
+///
+/// The browser only ever talks to the network through this trait, so the
+/// UI can be driven by a mock transport and other protocols can be added
+/// by implementing it.
+pub trait Fetcher: Send + Sync {
+    fn fetch(&self, url: &Url) -> Result<Response, String>;
+
+    /// Like [`Fetcher::fetch`], but the body is left to be read, so big
+    /// files can be streamed to disk.
+    fn open(&self, url: &Url) -> Result<Stream, String> {
+        self.fetch(url).map(Stream::from)
+    }
+}
+
+/// The schemes gbrowse loads itself, on top of the ones plugins serve.
+/// Links with any other scheme are handed to another program.
+pub const SCHEMES: &[&str] = &["gemini", "mercury", "file", "about"];
+
+/// Fetches gemini (and mercury) urls.
+pub struct GeminiFetcher;
+
+impl Fetcher for GeminiFetcher {
+    fn fetch(&self, url: &Url) -> Result<Response, String> {
+        self.open(url)?.into_response()
+    }
+
+    fn open(&self, url: &Url) -> Result<Stream, String> {
+        let connection = transport::connect(url).map_err(|err| format!("Request Error: {err}"))?;
+
+        Ok(Stream {
+            status: connection.status,
+            meta: connection.meta,
+            body: connection.body,
+            on_response: None,
+            timing: Some(connection.timing),
+            certificate: connection.certificate,
+            identity: connection.identity,
+        })
+    }
+}
+
+type ResponseHook = Box<dyn FnOnce(&mut Response) + Send>;
+
+/// A response whose body hasn't been read yet.
+pub struct Stream {
+    pub status: StatusCode,
+    pub meta: String,
+    body: Box<dyn Read + Send>,
+    /// Called with the response once it's read, or with just the header if
+    /// the body is streamed somewhere else.
+    pub on_response: Option<ResponseHook>,
+    /// How long the request took so far, or `None` if it didn't go over
+    /// the network.
+    pub timing: Option<Timing>,
+    /// The server's certificate, if it came over gemini.
+    pub certificate: Option<ServerCertificate>,
+    /// The client certificate that was sent, if any.
+    pub identity: Option<String>,
+}
+
+impl Stream {
+    /// A response that didn't come over the network.
+    pub fn new(status: StatusCode, meta: String, body: Box<dyn Read + Send>) -> Self {
+        Self {
+            status,
+            meta,
+            body,
+            on_response: None,
+            timing: None,
+            certificate: None,
+            identity: None,
+        }
+    }
+
+    /// Read the whole body.
+    pub fn into_response(mut self) -> Result<Response, String> {
+        let mut data = Vec::new();
+
+        self.body
+            .read_to_end(&mut data)
+            .map_err(|err| format!("Request Error: IO error: {err}"))?;
+
+        let mut response = Response {
+            status: self.status,
+            meta: self.meta,
+            data,
+        };
+
+        if let Some(hook) = self.on_response {
+            hook(&mut response);
+        }
+
+        Ok(response)
+    }
+
+    /// Take the body to read it elsewhere.
+    pub fn into_body(self) -> Box<dyn Read + Send> {
+        if let Some(hook) = self.on_response {
+            hook(&mut Response {
+                status: self.status,
+                meta: self.meta,
+                data: vec![],
+            });
+        }
+
+        self.body
+    }
+}
+
+impl From<Response> for Stream {
+    fn from(response: Response) -> Self {
+        Self {
+            status: response.status,
+            meta: response.meta,
+            body: Box::new(Cursor::new(response.data)),
+            on_response: None,
+            timing: None,
+            certificate: None,
+            identity: None,
+        }
+    }
+}
+
+/// What a request turned into.
+pub enum Loaded {
+    Page(Page),
+    /// The server wants some input before it can show a page.
+    Input(InputRequest),
+    /// The response is being saved to a file instead of being shown.
+    Download {
+        url: String,
+        path: PathBuf,
+    },
+    /// A gempub ebook.
+    Book {
+        url: String,
+        book: Book,
+    },
+    /// Audio to play in the player.
+    Audio {
+        url: String,
+        data: Vec<u8>,
+    },
+    /// A redirect went to a host on the blocklist, which wasn't connected
+    /// to.
+    Blocked {
+        url: String,
+        host: String,
+    },
+}
+
+/// A 1x response asking for input, which is sent back in the query.
+pub struct InputRequest {
+    pub url: String,
+    pub prompt: String,
+    /// 11 SENSITIVE INPUT, e.g. a password.
+    pub sensitive: bool,
+}
+
+/// Pages with more lines than this are cut short until you ask for the
+/// rest, since laying out every line happens on the UI thread and a huge
+/// page would freeze the window.
+const MAX_LINES: usize = 20_000;
+/// Lines longer than this, in bytes, are cut short too.
+const MAX_LINE_LENGTH: usize = 10_000;
+
+/// A page that finished loading.
+#[derive(Default)]
+pub struct Page {
+    /// Where the page was loaded from, after following redirects.
+    pub url: String,
+    /// Each redirect that was followed to get here, in order.
+    pub redirects: Vec<Redirect>,
+    pub nodes: Vec<GemtextNode>,
+    /// How long loading the page took, if it came over the network.
+    pub timing: Option<Timing>,
+    /// The mime type the server sent, or `None` for pages built into
+    /// gbrowse.
+    pub mime: Option<String>,
+    /// The size of the body in bytes.
+    pub size: usize,
+    /// The server's certificate, if it came over gemini rather than from
+    /// the cache.
+    pub certificate: Option<ServerCertificate>,
+    /// The client certificate the page was requested with, if any.
+    pub identity: Option<String>,
+    /// The whole page, when `nodes` was cut short because laying it all
+    /// out would freeze the window.
+    pub cut: Option<Vec<GemtextNode>>,
+}
+
+impl Page {
+    /// Cut the page short if it's longer than [`MAX_LINES`] or has lines
+    /// longer than [`MAX_LINE_LENGTH`], keeping the whole thing in `cut`.
+    fn limit(&mut self) {
+        let lines: usize = self.nodes.iter().map(line_count).sum();
+        let too_long = self
+            .nodes
+            .iter()
+            .any(|node| node_text(node).is_some_and(|text| text.len() > MAX_LINE_LENGTH));
+
+        if lines <= MAX_LINES && !too_long {
+            return;
+        }
+
+        let mut lines = 0;
+        let mut nodes = vec![];
+
+        for node in &self.nodes {
+            if lines >= MAX_LINES {
+                break;
+            }
+
+            let node = match node {
+                GemtextNode::Preformatted(text, alt) => {
+                    let kept: Vec<String> =
+                        text.lines().take(MAX_LINES - lines).map(shorten).collect();
+
+                    GemtextNode::Preformatted(kept.join("\n"), alt.clone())
+                }
+                GemtextNode::Text(text) => GemtextNode::Text(shorten(text)),
+                // links without a label show the url instead
+                GemtextNode::Link(url, label) => {
+                    let label = match label {
+                        Some(label) => Some(shorten(label)),
+                        None if url.len() > MAX_LINE_LENGTH => Some(shorten(url)),
+                        None => None,
+                    };
+
+                    GemtextNode::Link(url.clone(), label)
+                }
+                GemtextNode::Heading(text) => GemtextNode::Heading(shorten(text)),
+                GemtextNode::SubHeading(text) => GemtextNode::SubHeading(shorten(text)),
+                GemtextNode::SubSubHeading(text) => GemtextNode::SubSubHeading(shorten(text)),
+                GemtextNode::ListItem(text) => GemtextNode::ListItem(shorten(text)),
+                GemtextNode::Blockquote(text) => GemtextNode::Blockquote(shorten(text)),
+                node => node.clone(),
+            };
+
+            lines += line_count(&node);
+            nodes.push(node);
+        }
+
+        self.cut = Some(std::mem::replace(&mut self.nodes, nodes));
+    }
+}
+
+/// How many lines of the page a node takes up.
+fn line_count(node: &GemtextNode) -> usize {
+    match node {
+        GemtextNode::Preformatted(text, _) => text.lines().count().max(1),
+        _ => 1,
+    }
+}
+
+/// The text of a line, or all of a preformatted block's lines.
+fn node_text(node: &GemtextNode) -> Option<&str> {
+    match node {
+        GemtextNode::Text(text)
+        | GemtextNode::Heading(text)
+        | GemtextNode::SubHeading(text)
+        | GemtextNode::SubSubHeading(text)
+        | GemtextNode::ListItem(text)
+        | GemtextNode::Blockquote(text) => Some(text),
+        GemtextNode::Link(url, label) => Some(label.as_deref().unwrap_or(url)),
+        GemtextNode::Preformatted(text, _) => text.lines().max_by_key(|line| line.len()),
+        _ => None,
+    }
+}
+
+/// A line cut down to [`MAX_LINE_LENGTH`] bytes, if it's longer.
+fn shorten(text: &str) -> String {
+    if text.len() <= MAX_LINE_LENGTH {
+        return text.to_string();
+    }
+
+    let mut end = MAX_LINE_LENGTH;
+
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!("{}…", &text[..end])
+}
+
+/// A url that answered with a redirect.
+pub struct Redirect {
+    pub url: String,
+    pub status: u8,
+}
+
+/// Why a page couldn't be loaded.
+pub enum LoadError {
+    /// The server answered with a status we can't show as a page.
+    Status {
+        status: StatusCode,
+        meta: String,
+    },
+    Other(String),
+}
+
+impl LoadError {
+    /// 4x failures might go away if the request is made again.
+    pub fn is_temporary(&self) -> bool {
+        matches!(
+            self,
+            LoadError::Status {
+                status: StatusCode::TemporaryFailure(_),
+                ..
+            }
+        )
+    }
+
+    /// How long a 44 SLOW DOWN response asked us to wait.
+    pub fn slow_down(&self) -> Option<Duration> {
+        match self {
+            LoadError::Status {
+                status: StatusCode::TemporaryFailure(4),
+                meta,
+            } => meta.trim().parse().ok().map(Duration::from_secs),
+            _ => None,
+        }
+    }
+
+    /// A short title and a longer explanation of what went wrong.
+    pub fn describe(&self) -> (&'static str, &'static str) {
+        let LoadError::Status { status, .. } = self else {
+            return (
+                "Couldn't load this page",
+                "Something went wrong while loading the page.",
+            );
+        };
+
+        match u8::from(*status) {
+            41 => (
+                "Server unavailable",
+                "The server is down for maintenance or overloaded. Try again later.",
+            ),
+            42 => (
+                "CGI error",
+                "The program generating this page crashed or timed out. Try again later.",
+            ),
+            43 => (
+                "Proxy error",
+                "The server couldn't reach the capsule it was proxying for. Try again later.",
+            ),
+            44 => (
+                "Slow down",
+                "Too many requests were made to this capsule. Wait before trying again.",
+            ),
+            40..=49 => (
+                "Temporary failure",
+                "The server couldn't handle the request, but it might work if you try again.",
+            ),
+            51 => (
+                "Not found",
+                "There is nothing at this address. Check the url for typos.",
+            ),
+            52 => (
+                "Gone",
+                "This page used to exist but has been removed for good.",
+            ),
+            53 => (
+                "Proxy request refused",
+                "This server doesn't serve pages for other hosts.",
+            ),
+            59 => (
+                "Bad request",
+                "The server couldn't understand the request. The url may be malformed.",
+            ),
+            50..=58 => (
+                "Permanent failure",
+                "The server can't handle this request, and trying again won't help.",
+            ),
+            61 => (
+                "Certificate not authorised",
+                "Your client certificate isn't allowed to see this page.",
+            ),
+            62 => (
+                "Certificate not valid",
+                "The server rejected your client certificate. It may have expired.",
+            ),
+            60..=69 => (
+                "Client certificate required",
+                "This page needs a client certificate. Set an identity for this capsule in the config.",
+            ),
+            _ => (
+                "Unknown status",
+                "The server answered with a status code gbrowse doesn't understand.",
+            ),
+        }
+    }
+}
+
+impl From<String> for LoadError {
+    fn from(err: String) -> Self {
+        LoadError::Other(err)
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Status { status, meta } => {
+                write!(f, "{} {}: {meta}", u8::from(*status), self.describe().0)
+            }
+            LoadError::Other(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+/// How many redirects are followed before giving up.
+const MAX_REDIRECTS: usize = 5;
+
+/// Follow redirects until we get a page, then parse it as gemtext. Each
+/// host along the way is requested with its own settings, and redirects to
+/// blocked hosts aren't followed.
+pub fn load_page(
+    fetcher: &dyn Fetcher,
+    url: &Url,
+    sites: &Sites,
+    downloader: &Downloader,
+) -> Result<Loaded, LoadError> {
+    let mut url = url.clone();
+    let mut redirects = Vec::new();
+
+    let (mime, data, timing, certificate, identity) = loop {
+        let settings = sites.get(&url.authority.host);
+        let stream = fetcher.open(&url)?;
+        let mut timing = stream.timing.clone();
+        let certificate = stream.certificate.clone();
+        let identity = stream.identity.clone();
+
+        let action = match stream.status {
+            StatusCode::Success(_) => downloader.action(&stream.meta, &settings),
+            _ => MimeAction::Inline,
+        };
+
+        if action != MimeAction::Inline {
+            let body = stream.into_body();
+            let path = downloader.start(&url.to_string(), &file_name(&url), body, action)?;
+
+            return Ok(Loaded::Download {
+                url: url.to_string(),
+                path,
+            });
+        }
+
+        let response = stream.into_response()?;
+
+        if let Some(timing) = &mut timing {
+            timing.finish();
+        }
+
+        match response.status {
+            StatusCode::Redirect(_) => {
+                let target = nav::resolve(&url.to_string(), &response.meta)
+                    .and_then(|target| Url::try_from(file::with_host(&target).as_str()).ok())
+                    .ok_or_else(|| format!("Invalid redirect to {}", response.meta))?;
+
+                redirects.push(Redirect {
+                    url: url.to_string(),
+                    status: response.status.into(),
+                });
+
+                if redirects.len() > MAX_REDIRECTS {
+                    return Err(format!("Too many redirects, stopped at {target}").into());
+                }
+
+                if settings.same_host_redirects && target.authority.host != url.authority.host {
+                    return Err(LoadError::Other(format!(
+                        "Refusing to follow redirect to another host: {target}"
+                    )));
+                }
+
+                if sites.is_blocked(&target.authority.host) {
+                    return Ok(Loaded::Blocked {
+                        url: target.to_string(),
+                        host: target.authority.host,
+                    });
+                }
+
+                url = target;
+            }
+            StatusCode::Success(_) if response.meta.starts_with(gpub::MIME) => {
+                return Ok(Loaded::Book {
+                    url: url.to_string(),
+                    book: Book::parse(response.data)?,
+                })
+            }
+            StatusCode::Success(_) if response.meta.starts_with("audio/") => {
+                return Ok(Loaded::Audio {
+                    url: url.to_string(),
+                    data: response.data,
+                })
+            }
+            StatusCode::Success(_) => {
+                break (response.meta, response.data, timing, certificate, identity)
+            }
+            StatusCode::Input(kind) => {
+                return Ok(Loaded::Input(InputRequest {
+                    url: url.to_string(),
+                    prompt: response.meta,
+                    sensitive: kind == 1,
+                }))
+            }
+            status => {
+                return Err(LoadError::Status {
+                    status,
+                    meta: response.meta,
+                })
+            }
+        }
+    };
+
+    let text = match str::from_utf8(&data) {
+        Ok(text) => text,
+        Err(err) => return Err(format!("Text Formatting Error: {err}").into()),
+    };
+
+    let mut page = Page {
+        url: url.to_string(),
+        redirects,
+        nodes: to_gemtext(&mime, text),
+        timing,
+        mime: Some(mime.clone()),
+        size: data.len(),
+        certificate,
+        identity,
+        cut: None,
+    };
+
+    page.limit();
+
+    Ok(Loaded::Page(page))
+}
+
+/// Fetch a url, following redirects, for `gbrowse dump`. Anything but a
+/// successful response is an error, including a redirect to a blocked host.
+pub fn fetch_followed(
+    fetcher: &dyn Fetcher,
+    url: &str,
+    sites: &Sites,
+) -> Result<Response, LoadError> {
+    let mut url = url.to_string();
+
+    for _ in 0..=MAX_REDIRECTS {
+        let parsed = Url::try_from(file::with_host(&url).as_str())
+            .map_err(|err| format!("Incorrectly formatted url {url}: {err}"))?;
+        let response = fetcher.fetch(&parsed)?;
+
+        match response.status {
+            StatusCode::Success(_) => return Ok(response),
+            StatusCode::Redirect(_) => {
+                url = nav::resolve(&parsed.to_string(), &response.meta)
+                    .ok_or_else(|| format!("Invalid redirect to {}", response.meta))?;
+
+                let host = url::Url::parse(&url)
+                    .ok()
+                    .and_then(|url| url.host_str().map(str::to_string))
+                    .unwrap_or_default();
+
+                if sites.is_blocked(&host) {
+                    return Err(
+                        format!("Refusing to follow redirect to blocked host {host}").into(),
+                    );
+                }
+            }
+            status => {
+                return Err(LoadError::Status {
+                    status,
+                    meta: response.meta,
+                })
+            }
+        }
+    }
+
+    Err(format!("Too many redirects, stopped at {url}").into())
+}
+
+/// Parse a text response, converting markdown and html to gemtext.
+pub fn to_gemtext(mime: &str, text: &str) -> Vec<GemtextNode> {
+    if mime.starts_with("text/markdown") {
+        markdown::to_gemtext(text)
+    } else if mime.starts_with("text/html") {
+        html::to_gemtext(text)
+    } else {
+        gemtext::parse_gemtext(text)
+    }
+}
+
+/// What to call a download, after the last segment of its url.
+fn file_name(url: &Url) -> String {
+    url.path
+        .as_ref()
+        .and_then(|path| path.file_name())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("download")
+        .to_string()
+}
+
+/// Answers with canned responses instead of going to the network, for
+/// tests. Urls it doesn't know are 51 NOT FOUND.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockFetcher {
+    responses: std::collections::HashMap<String, (u8, String, String)>,
+    requested: std::sync::Mutex<Vec<String>>,
+}
+
+#[cfg(test)]
+impl MockFetcher {
+    pub fn respond(mut self, url: &str, status: u8, meta: &str, body: &str) -> Self {
+        self.responses.insert(
+            url.to_string(),
+            (status, meta.to_string(), body.to_string()),
+        );
+        self
+    }
+
+    /// Every url that was fetched, in order.
+    pub fn requested(&self) -> Vec<String> {
+        self.requested.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl Fetcher for MockFetcher {
+    fn fetch(&self, url: &Url) -> Result<Response, String> {
+        let url = url.to_string();
+        let (status, meta, body) = self.responses.get(&url).cloned().unwrap_or((
+            51,
+            "Not found".to_string(),
+            String::new(),
+        ));
+
+        self.requested.lock().unwrap().push(url);
+
+        Ok(Response {
+            status: StatusCode::from(status),
+            meta,
+            data: body.into_bytes(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, downloads::Downloads};
+
+    fn load(fetcher: &MockFetcher, url: &str, config: &Config) -> Result<Loaded, LoadError> {
+        let downloader = Downloads::new().downloader(config);
+
+        load_page(
+            fetcher,
+            &Url::try_from(url).unwrap(),
+            &config.sites(),
+            &downloader,
+        )
+    }
+
+    fn page(loaded: Result<Loaded, LoadError>) -> Page {
+        match loaded {
+            Ok(Loaded::Page(page)) => page,
+            _ => panic!("expected a page"),
+        }
+    }
+
+    #[test]
+    fn follows_redirect_chain() {
+        let fetcher = MockFetcher::default()
+            .respond("gemini://a.org/", 31, "gemini://b.org/old", "")
+            .respond("gemini://b.org/old", 30, "new/", "")
+            .respond("gemini://b.org/new/", 20, "text/gemini", "# New");
+
+        let page = page(load(&fetcher, "gemini://a.org/", &Config::default()));
+
+        assert_eq!(page.url, "gemini://b.org/new/");
+        assert_eq!(page.nodes, vec![GemtextNode::Heading("New".to_string())]);
+
+        let redirects: Vec<_> = page
+            .redirects
+            .iter()
+            .map(|redirect| (redirect.url.as_str(), redirect.status))
+            .collect();
+        assert_eq!(
+            redirects,
+            [("gemini://a.org/", 31), ("gemini://b.org/old", 30)]
+        );
+    }
+
+    #[test]
+    fn stops_after_too_many_redirects() {
+        let fetcher = MockFetcher::default().respond("gemini://a.org/", 30, "/", "");
+
+        let err = load(&fetcher, "gemini://a.org/", &Config::default()).err();
+
+        assert!(err.is_some_and(|err| err.to_string().starts_with("Too many redirects")));
+        assert_eq!(fetcher.requested().len(), MAX_REDIRECTS + 1);
+    }
+
+    #[test]
+    fn refuses_redirect_to_other_host() {
+        let fetcher = MockFetcher::default().respond("gemini://a.org/", 30, "gemini://b.org/", "");
+        let mut config = Config::default();
+        config
+            .sites
+            .entry("a.org".to_string())
+            .or_default()
+            .same_host_redirects = true;
+
+        assert!(load(&fetcher, "gemini://a.org/", &config).is_err());
+        assert_eq!(fetcher.requested(), ["gemini://a.org/"]);
+    }
+
+    #[test]
+    fn stops_at_blocked_redirect() {
+        let fetcher =
+            MockFetcher::default().respond("gemini://a.org/", 30, "gemini://spam.b.org/", "");
+        let config = Config {
+            blocked_hosts: vec!["b.org".to_string()],
+            ..Config::default()
+        };
+
+        match load(&fetcher, "gemini://a.org/", &config) {
+            Ok(Loaded::Blocked { url, host }) => {
+                assert_eq!(url, "gemini://spam.b.org/");
+                assert_eq!(host, "spam.b.org");
+            }
+            _ => panic!("expected the redirect to be blocked"),
+        }
+
+        assert_eq!(fetcher.requested(), ["gemini://a.org/"]);
+    }
+
+    #[test]
+    fn asks_for_input() {
+        let fetcher = MockFetcher::default()
+            .respond("gemini://a.org/search", 10, "Search for", "")
+            .respond("gemini://a.org/login", 11, "Password", "");
+
+        for (url, sensitive) in [
+            ("gemini://a.org/search", false),
+            ("gemini://a.org/login", true),
+        ] {
+            match load(&fetcher, url, &Config::default()) {
+                Ok(Loaded::Input(request)) => {
+                    assert_eq!(request.url, url);
+                    assert_eq!(request.sensitive, sensitive);
+                }
+                _ => panic!("expected an input prompt"),
+            }
+        }
+    }
+
+    #[test]
+    fn error_status() {
+        let fetcher = MockFetcher::default().respond("gemini://a.org/", 44, "30", "");
+
+        let err = load(&fetcher, "gemini://a.org/", &Config::default()).err();
+
+        assert!(err.as_ref().is_some_and(LoadError::is_temporary));
+        assert_eq!(
+            err.and_then(|err| err.slow_down()),
+            Some(Duration::from_secs(30))
+        );
+
+        let missing = load(&fetcher, "gemini://a.org/missing", &Config::default());
+
+        assert!(matches!(
+            missing,
+            Err(LoadError::Status {
+                status: StatusCode::PermanentFailure(1),
+                ..
+            })
+        ));
+    }
+
+    fn limited(nodes: Vec<GemtextNode>) -> Page {
+        let mut page = Page {
+            nodes,
+            ..Page::default()
+        };
+        page.limit();
+        page
+    }
+
+    fn lines(count: usize) -> Vec<GemtextNode> {
+        vec![GemtextNode::Text("line".to_string()); count]
+    }
+
+    #[test]
+    fn keeps_page_at_max_lines() {
+        let page = limited(lines(MAX_LINES));
+
+        assert!(page.cut.is_none());
+        assert_eq!(page.nodes.len(), MAX_LINES);
+    }
+
+    #[test]
+    fn cuts_page_past_max_lines() {
+        let page = limited(lines(MAX_LINES + 1));
+
+        assert_eq!(page.nodes.len(), MAX_LINES);
+        assert_eq!(page.cut.map(|cut| cut.len()), Some(MAX_LINES + 1));
+    }
+
+    #[test]
+    fn cuts_preformatted_block_at_max_lines() {
+        let mut nodes = lines(10);
+        nodes.push(GemtextNode::Preformatted(
+            vec!["code"; MAX_LINES].join("\n"),
+            None,
+        ));
+
+        let page = limited(nodes);
+
+        assert_eq!(page.nodes.iter().map(line_count).sum::<usize>(), MAX_LINES);
+        assert!(matches!(
+            page.nodes.last(),
+            Some(GemtextNode::Preformatted(text, _)) if text.lines().count() == MAX_LINES - 10
+        ));
+    }
+
+    #[test]
+    fn keeps_line_at_max_length() {
+        let page = limited(vec![GemtextNode::Text("a".repeat(MAX_LINE_LENGTH))]);
+
+        assert!(page.cut.is_none());
+    }
+
+    #[test]
+    fn cuts_line_past_max_length() {
+        let page = limited(vec![
+            GemtextNode::Text("a".repeat(MAX_LINE_LENGTH + 1)),
+            GemtextNode::Link("gemini://a.org/".to_string(), None),
+        ]);
+
+        assert!(page.cut.is_some());
+        assert_eq!(
+            page.nodes,
+            [
+                GemtextNode::Text(format!("{}…", "a".repeat(MAX_LINE_LENGTH))),
+                GemtextNode::Link("gemini://a.org/".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn shortens_on_char_boundary() {
+        // two byte characters, so the limit falls in the middle of one
+        let text = format!("a{}", "é".repeat(MAX_LINE_LENGTH / 2));
+        let short = shorten(&text);
+
+        assert!(short.len() <= MAX_LINE_LENGTH + '…'.len_utf8());
+        assert!(short.ends_with("é…"));
+    }
+}
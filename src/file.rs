@@ -0,0 +1,127 @@
+//! `file://` urls, for reading capsules on disk before uploading them.
+//! Directories are shown as a gemtext list of what's in them.
+//!
+//! The gemini url parser needs a host, so file urls are given `localhost`
+//! as one on the way in, like `file://localhost/home/me/capsule/`.
+
+use gmi::{
+    protocol::{Response, StatusCode},
+    url::Url,
+};
+
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    fetch::{Fetcher, Stream},
+    i18n::tr,
+    input, serve,
+};
+
+/// Reads `file://` urls itself and hands everything else to `inner`.
+pub struct FileFetcher {
+    inner: Arc<dyn Fetcher>,
+}
+
+impl FileFetcher {
+    pub fn new(inner: Arc<dyn Fetcher>) -> Self {
+        Self { inner }
+    }
+}
+
+impl Fetcher for FileFetcher {
+    fn fetch(&self, url: &Url) -> Result<Response, String> {
+        self.open(url)?.into_response()
+    }
+
+    fn open(&self, url: &Url) -> Result<Stream, String> {
+        if url.scheme.as_deref() != Some("file") {
+            return self.inner.open(url);
+        }
+
+        let raw_path = url
+            .path
+            .as_ref()
+            .map_or("/".to_string(), |path| path.to_string());
+        let path = PathBuf::from(input::decode(&raw_path));
+
+        if path.is_dir() {
+            // so relative links in the listing and the pages in it work
+            if !raw_path.ends_with('/') {
+                return Ok(Stream::from(Response {
+                    status: StatusCode::from(31),
+                    meta: format!("{}/", input::without_query(&url.to_string())),
+                    data: vec![],
+                }));
+            }
+
+            return Ok(Stream::from(Response {
+                status: StatusCode::from(20),
+                meta: "text/gemini; charset=utf-8".to_string(),
+                data: listing(&path)?.into_bytes(),
+            }));
+        }
+
+        let file =
+            File::open(&path).map_err(|err| format!("Error reading {}: {err}", path.display()))?;
+
+        Ok(Stream::new(
+            StatusCode::from(20),
+            serve::mime_type(&path).to_string(),
+            Box::new(file),
+        ))
+    }
+}
+
+/// Give a `file:///` url the host the gemini url parser needs.
+pub fn with_host(url: &str) -> String {
+    match url.strip_prefix("file:///") {
+        Some(path) => format!("file://localhost/{path}"),
+        None => url.to_string(),
+    }
+}
+
+/// A gemtext page linking to everything in a directory, directories first.
+/// Hidden files are left out.
+fn listing(dir: &Path) -> Result<String, String> {
+    let err = |err| format!("Error reading {}: {err}", dir.display());
+
+    let mut entries: Vec<(bool, String)> = fs::read_dir(dir)
+        .map_err(err)?
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_dir = entry.path().is_dir();
+
+            (!name.starts_with('.')).then_some((!is_dir, name))
+        })
+        .collect();
+
+    // directories sort first, since `false` comes before `true`
+    entries.sort();
+
+    let mut page = format!("# {}\n\n", dir.display());
+
+    if dir.parent().is_some() {
+        page += "=> ../ ⬆ ..\n";
+    }
+
+    for (is_file, name) in &entries {
+        let link = input::encode(name);
+
+        if *is_file {
+            page += &format!("=> {link} 📄 {name}\n");
+        } else {
+            page += &format!("=> {link}/ 📁 {name}/\n");
+        }
+    }
+
+    if entries.is_empty() {
+        page += &format!("\n{}\n", tr("This directory is empty."));
+    }
+
+    Ok(page)
+}
@@ -0,0 +1,228 @@
+//! Fonts for page text, picked in the config either by file or by the name
+//! of an installed font.
+
+use directories_next::BaseDirs;
+use eframe::egui::{FontData, FontDefinitions, FontFamily};
+use serde::{Deserialize, Serialize};
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The family used for the text of pages.
+pub const BODY: &str = "body";
+/// The family used for headings.
+pub const HEADING: &str = "heading";
+
+/// Emoji fonts with outlines egui can draw. egui can't draw color glyphs,
+/// so emoji come out in the text color, but that beats a box.
+const EMOJI_FONTS: &[&str] = &[
+    "Noto Emoji",
+    "Segoe UI Emoji",
+    "seguiemj",
+    "Symbola",
+    "Twemoji Mozilla",
+    "OpenMoji",
+];
+
+/// Fonts with Chinese, Japanese and Korean characters that are often
+/// installed, looked for when no fallback fonts are configured.
+const CJK_FONTS: &[&str] = &[
+    "Noto Sans CJK",
+    "Noto Sans CJK SC",
+    "Noto Sans CJK JP",
+    "Source Han Sans",
+    "WenQuanYi Micro Hei",
+    "WenQuanYi Zen Hei",
+    "Droid Sans Fallback",
+    "Droid Sans Fallback Full",
+    "msyh",
+    "msgothic",
+    "malgun",
+    "PingFang",
+    "Hiragino Sans GB",
+];
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct FontSettings {
+    /// A `.ttf` or `.otf` file, or the name of an installed font like
+    /// `"DejaVu Serif"`.
+    pub body: Option<String>,
+    /// Same as `body`, and falls back to it.
+    pub heading: Option<String>,
+    /// The font for preformatted text. A Nerd Font helps with ASCII art
+    /// that uses box drawing and symbol characters.
+    pub monospace: Option<String>,
+    /// Fonts to use for characters the others don't have. When empty,
+    /// installed emoji and CJK fonts are looked for so emoji and Chinese,
+    /// Japanese and Korean pages don't show up as boxes.
+    pub fallback: Vec<String>,
+}
+
+pub fn body() -> FontFamily {
+    FontFamily::Name(BODY.into())
+}
+
+pub fn heading() -> FontFamily {
+    FontFamily::Name(HEADING.into())
+}
+
+/// egui's default fonts with the configured ones in front. The page
+/// families are always defined, even when nothing is configured.
+pub fn definitions(settings: &FontSettings) -> FontDefinitions {
+    let mut fonts = FontDefinitions::default();
+
+    let proportional = fonts.families[&FontFamily::Proportional].clone();
+    let body = with_font(&mut fonts, settings.body.as_deref(), &proportional);
+    let heading = with_font(&mut fonts, settings.heading.as_deref(), &body);
+
+    let monospace = fonts.families[&FontFamily::Monospace].clone();
+    let monospace = with_font(&mut fonts, settings.monospace.as_deref(), &monospace);
+
+    fonts.families.insert(self::body(), body);
+    fonts.families.insert(self::heading(), heading);
+    fonts.families.insert(FontFamily::Monospace, monospace);
+
+    for (name, data) in fallbacks(settings) {
+        fonts
+            .font_data
+            .insert(name.clone(), FontData::from_owned(data));
+
+        for family in fonts.families.values_mut() {
+            family.push(name.clone());
+        }
+    }
+
+    fonts
+}
+
+fn fallbacks(settings: &FontSettings) -> Vec<(String, Vec<u8>)> {
+    if settings.fallback.is_empty() {
+        return [("emoji", EMOJI_FONTS), ("cjk", CJK_FONTS)]
+            .iter()
+            .filter_map(|(name, fonts)| {
+                let data = fs::read(find_installed(fonts)?).ok()?;
+                Some((name.to_string(), data))
+            })
+            .collect();
+    }
+
+    settings
+        .fallback
+        .iter()
+        .filter_map(|font| match load(font) {
+            Ok(data) => Some((font.clone(), data)),
+            Err(err) => {
+                eprintln!("{err}");
+                None
+            }
+        })
+        .collect()
+}
+
+/// Put a font in front of the fonts it falls back to.
+fn with_font(fonts: &mut FontDefinitions, font: Option<&str>, fallback: &[String]) -> Vec<String> {
+    let mut family = fallback.to_vec();
+
+    if let Some(font) = font {
+        match load(font) {
+            Ok(data) => {
+                fonts
+                    .font_data
+                    .insert(font.to_string(), FontData::from_owned(data));
+                family.insert(0, font.to_string());
+            }
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+
+    family
+}
+
+fn load(font: &str) -> Result<Vec<u8>, String> {
+    let path = if Path::new(font).is_file() {
+        PathBuf::from(font)
+    } else {
+        find_installed(&[font]).ok_or_else(|| format!("Font Error: can't find {font}"))?
+    };
+
+    fs::read(&path).map_err(|err| format!("Font Error: {}: {err}", path.display()))
+}
+
+/// Look through the usual font folders for a file named after one of the
+/// fonts, e.g. "DejaVu Serif" finds `DejaVuSerif.ttf`.
+fn find_installed(names: &[&str]) -> Option<PathBuf> {
+    let names: Vec<String> = names.iter().map(|name| simplify(name)).collect();
+
+    font_dirs().iter().find_map(|dir| find_in(dir, &names, 0))
+}
+
+fn font_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = [
+        "/usr/share/fonts",
+        "/usr/local/share/fonts",
+        "/Library/Fonts",
+        "/System/Library/Fonts",
+        "C:\\Windows\\Fonts",
+    ]
+    .iter()
+    .map(PathBuf::from)
+    .collect();
+
+    if let Some(base) = BaseDirs::new() {
+        dirs.push(base.data_dir().join("fonts"));
+        dirs.push(base.home_dir().join(".fonts"));
+        dirs.push(base.home_dir().join("Library/Fonts"));
+    }
+
+    dirs
+}
+
+fn find_in(dir: &Path, names: &[String], depth: usize) -> Option<PathBuf> {
+    // font folders aren't deep, don't wander off following links
+    if depth > 4 {
+        return None;
+    }
+
+    for entry in fs::read_dir(dir).ok()?.flatten() {
+        let path = entry.path();
+
+        if path.is_dir() {
+            if let Some(found) = find_in(&path, names, depth + 1) {
+                return Some(found);
+            }
+
+            continue;
+        }
+
+        let is_font = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .is_some_and(|ext| ["ttf", "otf", "ttc"].contains(&ext.as_str()));
+
+        let stem = path
+            .file_stem()
+            .map(|stem| simplify(&stem.to_string_lossy()))
+            .unwrap_or_default();
+
+        let matches = names
+            .iter()
+            .any(|name| stem == *name || stem == format!("{name}regular"));
+
+        if is_font && matches {
+            return Some(path);
+        }
+    }
+
+    None
+}
+
+/// Lowercase letters and numbers only, so names and file names compare.
+fn simplify(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
@@ -0,0 +1,68 @@
+//! Touchscreen gestures. egui already turns a finger dragging over a page
+//! into scrolling that keeps going after it's let go, so this only looks
+//! for swipes in from the sides of the window.
+
+use eframe::egui::{self, Event, Pos2, TouchId, TouchPhase};
+
+/// How close to the side of the window a swipe has to start.
+const EDGE: f32 = 24.0;
+/// How far a swipe has to go before it counts.
+const DISTANCE: f32 = 80.0;
+
+pub enum Swipe {
+    /// In from the left.
+    Back,
+    /// In from the right.
+    Forward,
+}
+
+#[derive(Default)]
+pub struct EdgeSwipe {
+    /// The touch that started at a side, and where it started.
+    touch: Option<(TouchId, Pos2, Swipe)>,
+}
+
+impl EdgeSwipe {
+    /// Watch this frame's touches, returning a swipe once one is finished.
+    pub fn update(&mut self, ctx: &egui::Context) -> Option<Swipe> {
+        let screen = ctx.input().screen_rect();
+        let events = ctx.input().events.clone();
+
+        for event in events {
+            let Event::Touch { id, phase, pos, .. } = event else {
+                continue;
+            };
+
+            match phase {
+                TouchPhase::Start if self.touch.is_none() => {
+                    if pos.x - screen.left() < EDGE {
+                        self.touch = Some((id, pos, Swipe::Back));
+                    } else if screen.right() - pos.x < EDGE {
+                        self.touch = Some((id, pos, Swipe::Forward));
+                    }
+                }
+                TouchPhase::End | TouchPhase::Cancel => {
+                    if self.touch.as_ref().is_none_or(|(touch, ..)| *touch != id) {
+                        continue;
+                    }
+
+                    let (_, start, swipe) = self.touch.take()?;
+                    let delta = pos - start;
+                    let inward = match swipe {
+                        Swipe::Back => delta.x,
+                        Swipe::Forward => -delta.x,
+                    };
+
+                    // mostly sideways, so scrolling near the edge doesn't count
+                    if phase == TouchPhase::End && inward > DISTANCE && inward > delta.y.abs() * 2.0
+                    {
+                        return Some(swipe);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+}
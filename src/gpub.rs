@@ -0,0 +1,143 @@
+//! Reads gempub (`.gpub`) ebooks: zip archives of gemtext with a
+//! `metadata.txt` and an index page linking to each chapter.
+
+use gmi::gemtext::{self, GemtextNode};
+use zip::ZipArchive;
+
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read},
+    path::{Component, Path, PathBuf},
+};
+
+pub const MIME: &str = "application/gpub+zip";
+
+pub struct Book {
+    pub title: String,
+    pub author: Option<String>,
+    /// The index page, which links to the chapters.
+    pub index: Vec<GemtextNode>,
+    pub chapters: Vec<Chapter>,
+    index_path: String,
+}
+
+pub struct Chapter {
+    pub title: String,
+    /// Where the chapter is inside the archive.
+    pub path: String,
+    pub nodes: Vec<GemtextNode>,
+}
+
+impl Book {
+    pub fn parse(data: Vec<u8>) -> Result<Self, String> {
+        let mut archive =
+            ZipArchive::new(Cursor::new(data)).map_err(|err| format!("Gempub Error: {err}"))?;
+
+        let metadata: HashMap<String, String> = read_text(&mut archive, "metadata.txt")
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(key, value)| (key.trim().to_lowercase(), value.trim().to_string()))
+            .collect();
+
+        let index_path = metadata
+            .get("index")
+            .cloned()
+            .unwrap_or_else(|| "index.gmi".to_string());
+
+        let index = gemtext::parse_gemtext(
+            &read_text(&mut archive, &index_path)
+                .ok_or_else(|| format!("Gempub Error: missing {index_path}"))?,
+        );
+
+        let base = Path::new(&index_path).parent().unwrap_or(Path::new(""));
+        let mut chapters = Vec::new();
+
+        for node in &index {
+            let GemtextNode::Link(link, text) = node else {
+                continue;
+            };
+
+            if link.contains("://") {
+                continue;
+            }
+
+            let path = normalize(&base.join(link));
+
+            if let Some(body) = read_text(&mut archive, &path) {
+                let nodes = gemtext::parse_gemtext(&body);
+
+                // the link's text, then the chapter's first heading, then its path
+                let title = text
+                    .clone()
+                    .or_else(|| first_heading(&nodes))
+                    .unwrap_or_else(|| link.clone());
+
+                chapters.push(Chapter { title, path, nodes });
+            }
+        }
+
+        if chapters.is_empty() {
+            return Err("Gempub Error: the index doesn't link to any chapters".to_string());
+        }
+
+        Ok(Self {
+            title: metadata
+                .get("title")
+                .cloned()
+                .unwrap_or_else(|| "Untitled book".to_string()),
+            author: metadata.get("author").cloned(),
+            index,
+            chapters,
+            index_path,
+        })
+    }
+
+    /// Which chapter a link inside the book points to.
+    pub fn chapter_for(&self, from: Option<usize>, link: &str) -> Option<usize> {
+        let from = match from {
+            Some(chapter) => &self.chapters[chapter].path,
+            None => &self.index_path,
+        };
+
+        let base = Path::new(from).parent().unwrap_or(Path::new(""));
+
+        let path = normalize(&base.join(link));
+
+        self.chapters
+            .iter()
+            .position(|chapter| chapter.path == path)
+    }
+}
+
+fn first_heading(nodes: &[GemtextNode]) -> Option<String> {
+    nodes.iter().find_map(|node| match node {
+        GemtextNode::Heading(title) => Some(title.trim().to_string()),
+        _ => None,
+    })
+}
+
+fn read_text(archive: &mut ZipArchive<Cursor<Vec<u8>>>, path: &str) -> Option<String> {
+    let mut file = archive.by_name(path).ok()?;
+    let mut text = String::new();
+
+    file.read_to_string(&mut text).ok()?;
+    Some(text)
+}
+
+/// Resolve `.` and `..` in a path inside the archive.
+fn normalize(path: &Path) -> String {
+    let mut parts = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                parts.pop();
+            }
+            Component::Normal(part) => parts.push(part),
+            _ => {}
+        }
+    }
+
+    parts.to_string_lossy().replace('\\', "/")
+}
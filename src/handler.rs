@@ -0,0 +1,85 @@
+//! `gbrowse register-handler`: make gbrowse what opens `gemini://` links
+//! clicked in other programs.
+
+use std::{env, fs, process::Command};
+
+/// Open `gemini://` links with this gbrowse executable.
+pub fn register() -> Result<(), String> {
+    let exe = env::current_exe()
+        .map_err(|err| format!("Couldn't find the gbrowse executable: {err}"))?
+        .display()
+        .to_string();
+
+    if cfg!(windows) {
+        register_windows(&exe)
+    } else if cfg!(target_os = "macos") {
+        // url schemes belong to app bundles there, which gbrowse isn't built as
+        Err("Registering a handler isn't supported on macOS".to_string())
+    } else {
+        register_xdg(&exe)
+    }
+}
+
+/// A desktop entry for gbrowse, set as the default for `gemini://`.
+fn register_xdg(exe: &str) -> Result<(), String> {
+    let dir = directories_next::BaseDirs::new()
+        .ok_or("Couldn't find your home directory")?
+        .data_dir()
+        .join("applications");
+    let path = dir.join("gbrowse.desktop");
+
+    let entry = format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=gbrowse\n\
+         Comment=A simple gemini browser\n\
+         Exec=\"{exe}\" browse %u\n\
+         Terminal=false\n\
+         Categories=Network;WebBrowser;\n\
+         MimeType=x-scheme-handler/gemini;\n"
+    );
+
+    fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(&path, entry))
+        .map_err(|err| format!("Error writing {}: {err}", path.display()))?;
+
+    run(
+        "xdg-mime",
+        &["default", "gbrowse.desktop", "x-scheme-handler/gemini"],
+    )
+}
+
+/// The `gemini` url protocol, in the current user's registry.
+fn register_windows(exe: &str) -> Result<(), String> {
+    let key = r"HKCU\Software\Classes\gemini";
+
+    run(
+        "reg",
+        &["add", key, "/ve", "/d", "URL:Gemini Protocol", "/f"],
+    )?;
+    run("reg", &["add", key, "/v", "URL Protocol", "/d", "", "/f"])?;
+    run(
+        "reg",
+        &[
+            "add",
+            &format!(r"{key}\shell\open\command"),
+            "/ve",
+            "/d",
+            &format!("\"{exe}\" browse \"%1\""),
+            "/f",
+        ],
+    )
+}
+
+fn run(program: &str, args: &[&str]) -> Result<(), String> {
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .map_err(|err| format!("Error running {program}: {err}"))?;
+
+    if !status.success() {
+        return Err(format!("{program} failed with {status}"));
+    }
+
+    Ok(())
+}
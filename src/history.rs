@@ -0,0 +1,244 @@
+use serde::{Deserialize, Serialize};
+
+use std::{
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::config;
+
+/// How many days it takes for a visit to count half as much.
+const HALF_LIFE_DAYS: f64 = 30.0;
+
+/// Every page that's been visited, kept in `history.ron`.
+pub struct History {
+    entries: Vec<HistoryEntry>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct HistoryEntry {
+    pub url: String,
+    pub title: String,
+    pub visits: u32,
+    /// Seconds since the unix epoch.
+    pub first_visit: u64,
+    pub last_visit: u64,
+}
+
+impl HistoryEntry {
+    /// Visit count decayed by how long ago the page was last visited, so
+    /// pages used often and recently come first.
+    pub fn frecency(&self, now: u64) -> f64 {
+        let days = now.saturating_sub(self.last_visit) as f64 / 86400.0;
+
+        self.visits as f64 * 0.5f64.powf(days / HALF_LIFE_DAYS)
+    }
+
+    /// Whether the url or title contain `text`, which should be lowercase.
+    fn matches(&self, text: &str) -> bool {
+        self.url.to_lowercase().contains(text) || self.title.to_lowercase().contains(text)
+    }
+}
+
+impl History {
+    pub fn load() -> Self {
+        Self {
+            entries: config::read_file("history.ron"),
+        }
+    }
+
+    pub fn save(&self) {
+        config::write_file("history.ron", &self.entries);
+    }
+
+    pub fn visit(&mut self, url: &str, title: &str) {
+        let now = now();
+
+        match self.entries.iter_mut().find(|entry| entry.url == url) {
+            Some(entry) => {
+                entry.visits += 1;
+                entry.last_visit = now;
+                entry.title = title.to_string();
+            }
+            None => self.entries.push(HistoryEntry {
+                url: url.to_string(),
+                title: title.to_string(),
+                visits: 1,
+                first_visit: now,
+                last_visit: now,
+            }),
+        }
+
+        self.save();
+    }
+
+    /// Add the history from another profile. Pages visited in both keep
+    /// the visits from both. Returns how many pages were new.
+    pub fn merge(&mut self, other: Vec<HistoryEntry>) -> usize {
+        let mut added = 0;
+
+        for other in other {
+            match self.entries.iter_mut().find(|entry| entry.url == other.url) {
+                Some(entry) => {
+                    entry.visits += other.visits;
+                    entry.first_visit = entry.first_visit.min(other.first_visit);
+
+                    if other.last_visit > entry.last_visit {
+                        entry.last_visit = other.last_visit;
+                        entry.title = other.title;
+                    }
+                }
+                None => {
+                    self.entries.push(other);
+                    added += 1;
+                }
+            }
+        }
+
+        self.save();
+        added
+    }
+
+    /// Pages whose url or title contain `text`, best first.
+    pub fn suggest(&self, text: &str, limit: usize) -> Vec<&HistoryEntry> {
+        let text = text.trim().to_lowercase();
+        let now = now();
+
+        let mut matching: Vec<&HistoryEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.matches(&text))
+            .collect();
+
+        matching.sort_by(|a, b| b.frecency(now).total_cmp(&a.frecency(now)));
+        matching.truncate(limit);
+        matching
+    }
+
+    /// Pages whose url or title contain `text`, most recently visited first.
+    pub fn recent(&self, text: &str, limit: usize) -> Vec<&HistoryEntry> {
+        let text = text.trim().to_lowercase();
+
+        let mut matching: Vec<&HistoryEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.matches(&text))
+            .collect();
+
+        matching.sort_by_key(|entry| std::cmp::Reverse(entry.last_visit));
+        matching.truncate(limit);
+        matching
+    }
+
+    /// Write the history to a `.json` or `.csv` file, depending on its
+    /// extension.
+    pub fn export(&self, path: &Path) -> Result<(), String> {
+        let text = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::to_string_pretty(&self.entries)
+                .map_err(|err| format!("Error serializing history: {err}"))?,
+            Some("csv") => self.to_csv(),
+            _ => return Err("History can only be exported to .json or .csv".to_string()),
+        };
+
+        fs::write(path, text).map_err(|err| format!("Error writing {}: {err}", path.display()))
+    }
+
+    fn to_csv(&self) -> String {
+        let quote = |field: &str| format!("\"{}\"", field.replace('"', "\"\""));
+        let mut csv = String::from("url,title,visits,first_visit,last_visit\n");
+
+        for entry in &self.entries {
+            csv.push_str(&format!(
+                "{},{},{},{},{}\n",
+                quote(&entry.url),
+                quote(&entry.title),
+                entry.visits,
+                entry.first_visit,
+                entry.last_visit
+            ));
+        }
+
+        csv
+    }
+}
+
+/// Seconds since the unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 86400;
+
+    fn entry(url: &str, visits: u32, last_visit: u64) -> HistoryEntry {
+        HistoryEntry {
+            url: url.to_string(),
+            visits,
+            last_visit,
+            ..HistoryEntry::default()
+        }
+    }
+
+    #[test]
+    fn frecency_halves_every_half_life() {
+        let now = 1000 * DAY;
+        let page = entry("gemini://a.org/", 8, now);
+
+        assert_eq!(page.frecency(now), 8.0);
+        assert_eq!(page.frecency(now + 30 * DAY), 4.0);
+        assert_eq!(page.frecency(now + 60 * DAY), 2.0);
+    }
+
+    #[test]
+    fn frecency_ignores_visits_in_the_future() {
+        // the clock was set back since the last visit
+        let page = entry("gemini://a.org/", 3, 1000 * DAY);
+
+        assert_eq!(page.frecency(999 * DAY), 3.0);
+    }
+
+    #[test]
+    fn suggests_recent_pages_over_old_frequent_ones() {
+        let now = now();
+        let history = History {
+            entries: vec![
+                entry("gemini://old.org/", 10, now - 150 * DAY),
+                entry("gemini://new.org/", 2, now),
+                entry("gemini://other.net/", 50, now),
+            ],
+        };
+
+        let urls: Vec<&str> = history
+            .suggest(" .ORG ", 5)
+            .iter()
+            .map(|entry| entry.url.as_str())
+            .collect();
+
+        assert_eq!(urls, ["gemini://new.org/", "gemini://old.org/"]);
+        assert_eq!(history.suggest("org", 1).len(), 1);
+    }
+
+    #[test]
+    fn merge_keeps_visits_from_both() {
+        let mut history = History {
+            entries: vec![entry("gemini://a.org/", 2, 10)],
+        };
+
+        let added = history.merge(vec![
+            entry("gemini://a.org/", 3, 20),
+            entry("gemini://b.org/", 1, 5),
+        ]);
+
+        assert_eq!(added, 1);
+        assert_eq!(history.entries[0].visits, 5);
+        assert_eq!(history.entries[0].last_visit, 20);
+    }
+}
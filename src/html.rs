@@ -0,0 +1,271 @@
+//! A last resort for text/html responses: the tags are stripped and the
+//! links pulled out, which is enough to read most pages.
+
+use gmi::gemtext::GemtextNode;
+
+/// Tags whose contents aren't text.
+const SKIPPED: &[&str] = &["script", "style", "template", "svg"];
+
+/// Tags that start a new line.
+const BLOCKS: &[&str] = &[
+    "p",
+    "div",
+    "br",
+    "li",
+    "tr",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ul",
+    "ol",
+    "table",
+    "blockquote",
+    "pre",
+    "section",
+    "article",
+    "header",
+    "footer",
+    "nav",
+    "hr",
+    "title",
+];
+
+pub fn to_gemtext(html: &str) -> Vec<GemtextNode> {
+    let mut renderer = Renderer::default();
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        match rest.find('<') {
+            Some(start) => {
+                renderer.text(&rest[..start]);
+                rest = &rest[start..];
+
+                let end = match rest.find('>') {
+                    Some(end) => end,
+                    None => break,
+                };
+
+                let tag = &rest[1..end];
+                rest = &rest[end + 1..];
+
+                // skip over everything inside script and style
+                if let Some(skipped) = SKIPPED
+                    .iter()
+                    .find(|skipped| !tag.starts_with('/') && tag_name(tag) == **skipped)
+                {
+                    let close = format!("</{skipped}");
+                    let lower = rest.to_ascii_lowercase();
+
+                    rest = match lower.find(&close) {
+                        Some(close) => &rest[close..],
+                        None => "",
+                    };
+
+                    continue;
+                }
+
+                renderer.tag(tag);
+            }
+            None => {
+                renderer.text(rest);
+                break;
+            }
+        }
+    }
+
+    renderer.flush();
+
+    let mut nodes = vec![
+        GemtextNode::Blockquote(
+            "⚠ This page is HTML, which gbrowse can only show as plain text.".to_string(),
+        ),
+        GemtextNode::EmptyLine,
+    ];
+
+    nodes.extend(renderer.nodes);
+    nodes
+}
+
+#[derive(Default)]
+struct Renderer {
+    nodes: Vec<GemtextNode>,
+    line: String,
+    /// The tag the current line is in, for headings and list items.
+    block: String,
+    /// Links in the current line, shown under it.
+    links: Vec<(String, String)>,
+    /// The href and text of the link being read.
+    link: Option<(String, String)>,
+}
+
+impl Renderer {
+    fn text(&mut self, text: &str) {
+        let text = decode_entities(text);
+        let leading = text.starts_with(char::is_whitespace);
+
+        for (i, word) in text.split_whitespace().enumerate() {
+            if (i > 0 || leading) && !self.line.is_empty() && !self.line.ends_with(' ') {
+                self.line.push(' ');
+            }
+
+            self.line.push_str(word);
+
+            if let Some((_, link_text)) = &mut self.link {
+                if !link_text.is_empty() {
+                    link_text.push(' ');
+                }
+
+                link_text.push_str(word);
+            }
+        }
+
+        if text.ends_with(char::is_whitespace) && !self.line.is_empty() {
+            self.line.push(' ');
+        }
+    }
+
+    fn tag(&mut self, tag: &str) {
+        let name = tag_name(tag);
+        let closing = tag.starts_with('/');
+
+        if name == "a" {
+            if closing {
+                if let Some((href, text)) = self.link.take() {
+                    let text = if text.is_empty() { href.clone() } else { text };
+                    self.links.push((href, text));
+                }
+            } else if let Some(href) = attribute(tag, "href") {
+                self.link = Some((href, String::new()));
+            }
+        }
+
+        if BLOCKS.contains(&name.as_str()) {
+            self.flush();
+
+            if !closing {
+                self.block = name;
+            }
+        }
+    }
+
+    /// Finish the current line.
+    fn flush(&mut self) {
+        let line = self.line.trim().to_string();
+        self.line.clear();
+
+        if !line.is_empty() {
+            self.nodes.push(match self.block.as_str() {
+                "h1" | "title" => GemtextNode::Heading(line),
+                "h2" => GemtextNode::SubHeading(line),
+                "h3" | "h4" | "h5" | "h6" => GemtextNode::SubSubHeading(line),
+                "li" => GemtextNode::ListItem(line),
+                "blockquote" => GemtextNode::Blockquote(line),
+                _ => GemtextNode::Text(line),
+            });
+        }
+
+        for (href, text) in self.links.drain(..) {
+            self.nodes.push(GemtextNode::Link(href, Some(text)));
+        }
+
+        self.block.clear();
+    }
+}
+
+fn tag_name(tag: &str) -> String {
+    tag.trim_start_matches('/')
+        .split(|c: char| c.is_whitespace() || c == '/')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// The value of an attribute, e.g. `href="..."`.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let start = lower.find(&format!("{name}="))? + name.len() + 1;
+    let value = &tag[start..];
+
+    let value = match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next()?,
+        _ => value.split(|c: char| c.is_whitespace()).next()?,
+    };
+
+    Some(decode_entities(value))
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The nodes after the warning at the top.
+    fn convert(html: &str) -> Vec<GemtextNode> {
+        to_gemtext(html).split_off(2)
+    }
+
+    fn text(text: &str) -> GemtextNode {
+        GemtextNode::Text(text.to_string())
+    }
+
+    #[test]
+    fn converts_blocks() {
+        assert_eq!(
+            convert(
+                "<title>T</title><h2>Sub</h2><p>Hello <b>big</b>\n world</p><ul><li>one</li></ul>"
+            ),
+            [
+                GemtextNode::Heading("T".to_string()),
+                GemtextNode::SubHeading("Sub".to_string()),
+                text("Hello big world"),
+                GemtextNode::ListItem("one".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_scripts_and_styles() {
+        assert_eq!(
+            convert("<p>a</p><SCRIPT>if (x < 1) {}</SCRIPT><style>p {}</style><p>b</p>"),
+            [text("a"), text("b")]
+        );
+        assert_eq!(convert("<p>a</p><script>never closed"), [text("a")]);
+    }
+
+    #[test]
+    fn moves_links_under_their_line() {
+        assert_eq!(
+            convert("<p>See <a href='/x?a=1&amp;b=2'>this page</a> and <a href=gemini://b.org/></a></p>"),
+            [
+                text("See this page and"),
+                GemtextNode::Link("/x?a=1&b=2".to_string(), Some("this page".to_string())),
+                GemtextNode::Link("gemini://b.org/".to_string(), Some("gemini://b.org/".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_entities_once() {
+        assert_eq!(
+            convert("<p>1 &lt; 2 &amp;&amp; &amp;lt;</p>"),
+            [text("1 < 2 && &lt;")]
+        );
+    }
+
+    #[test]
+    fn stops_at_unclosed_tag() {
+        assert_eq!(convert("<p>a <b"), [text("a")]);
+    }
+}
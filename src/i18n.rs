@@ -0,0 +1,57 @@
+//! Translations of the interface. The English text is the key, and each
+//! language is a RON map from it to the translation, in `locales/`. Anything
+//! that hasn't been translated stays in English.
+
+use std::{collections::HashMap, env, fmt::Display, sync::RwLock};
+
+/// The languages gbrowse has been translated into.
+const LOCALES: &[(&str, &str)] = &[
+    ("de", include_str!("../locales/de.ron")),
+    ("es", include_str!("../locales/es.ron")),
+];
+
+static TRANSLATIONS: RwLock<Option<HashMap<String, String>>> = RwLock::new(None);
+
+/// Switch to a language by its code, like `de`. `None` goes by the
+/// system's language.
+pub fn set_language(language: Option<&str>) {
+    let language = language.map(str::to_string).or_else(system_language);
+
+    let translations = language
+        .and_then(|language| LOCALES.iter().find(|(code, _)| language.starts_with(code)))
+        .and_then(|(code, text)| match ron::from_str(text) {
+            Ok(translations) => Some(translations),
+            Err(err) => {
+                eprintln!("Error reading the {code} translation: {err}");
+                None
+            }
+        });
+
+    if let Ok(mut current) = TRANSLATIONS.write() {
+        *current = translations;
+    }
+}
+
+/// The language from the environment, e.g. `de_DE.UTF-8`.
+fn system_language() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|language| !language.is_empty())
+}
+
+/// Translate a piece of the interface.
+pub fn tr(text: &str) -> String {
+    TRANSLATIONS
+        .read()
+        .ok()
+        .and_then(|translations| translations.as_ref()?.get(text).cloned())
+        .unwrap_or_else(|| text.to_string())
+}
+
+/// Translate text with `{name}` placeholders, then fill them in.
+pub fn trf(text: &str, args: &[(&str, &dyn Display)]) -> String {
+    args.iter().fold(tr(text), |text, (name, value)| {
+        text.replace(&format!("{{{name}}}"), &value.to_string())
+    })
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use crate::config;
+
+/// What was last typed into input prompts, keyed by the url that asked.
+pub struct Inputs {
+    inputs: HashMap<String, String>,
+}
+
+impl Inputs {
+    pub fn load() -> Self {
+        Self {
+            inputs: config::read_file("inputs.ron"),
+        }
+    }
+
+    pub fn get(&self, url: &str) -> Option<&String> {
+        self.inputs.get(url)
+    }
+
+    pub fn remember(&mut self, url: &str, input: &str) {
+        self.inputs.insert(url.to_string(), input.to_string());
+        config::write_file("inputs.ron", &self.inputs);
+    }
+
+    pub fn forget(&mut self, url: &str) {
+        if self.inputs.remove(url).is_some() {
+            config::write_file("inputs.ron", &self.inputs);
+        }
+    }
+}
+
+/// The url to request to answer an input prompt, with the input
+/// percent-encoded into the query.
+pub fn with_query(url: &str, input: &str) -> String {
+    format!("{}?{}", without_query(url), encode(input))
+}
+
+/// Undo percent-encoding, leaving anything that isn't valid as it is.
+pub fn decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Percent-encode everything but unreserved characters.
+pub fn encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{b:02X}")
+            }
+        })
+        .collect()
+}
+
+pub fn without_query(url: &str) -> &str {
+    url.split('?').next().unwrap_or(url)
+}
+
+/// An input prompt being shown in place of a page.
+pub struct InputPrompt {
+    pub url: String,
+    pub prompt: String,
+    pub text: String,
+    /// Masked while typing and never stored anywhere.
+    pub sensitive: bool,
+}
@@ -0,0 +1,179 @@
+//! A socket that scripts can drive a running gbrowse through, like a rofi
+//! launcher opening bookmarks. Each connection sends one command on a line
+//! and gets an answer back:
+//!
+//! - `open <url>`: open a url in a new tab
+//! - `reload`: reload the current tab
+//! - `tabs`: the url of each tab, with the current one marked by `*`
+//! - `dump`: the current page as gemtext
+//!
+//! Only on unix, where the socket can be kept to the user running gbrowse.
+
+use eframe::egui;
+
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, Sender},
+    time::Duration,
+};
+
+use crate::config;
+
+/// How long to wait on a connection or the app before giving up.
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// A url of up to 1024 bytes, the command and the newline.
+const MAX_COMMAND: u64 = 1100;
+
+pub enum Command {
+    Open(String),
+    Reload,
+    Tabs,
+    Dump,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let (name, arg) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+
+        match (name, arg.trim()) {
+            ("open", "") => Err("open needs a url".to_string()),
+            ("open", url) => Ok(Self::Open(url.to_string())),
+            ("reload", "") => Ok(Self::Reload),
+            ("tabs", "") => Ok(Self::Tabs),
+            ("dump", "") => Ok(Self::Dump),
+            _ => Err(format!("unknown command: {}", line.trim())),
+        }
+    }
+}
+
+/// A command waiting for the app to carry it out.
+pub struct Request {
+    pub command: Command,
+    reply: Sender<Result<String, String>>,
+}
+
+impl Request {
+    pub fn reply(self, reply: Result<String, String>) {
+        // whoever sent it might have given up waiting
+        self.reply.send(reply).ok();
+    }
+}
+
+pub struct Server {
+    rx: Receiver<Request>,
+    path: PathBuf,
+}
+
+impl Server {
+    /// Listen on the socket, waking up `ctx` when a command comes in.
+    #[cfg(unix)]
+    pub fn start(ctx: egui::Context) -> Result<Self, String> {
+        use std::{
+            fs,
+            io::{BufRead, BufReader, Read, Write},
+            os::unix::{
+                fs::PermissionsExt,
+                net::{UnixListener, UnixStream},
+            },
+            thread,
+        };
+
+        let path = socket_path().ok_or("Couldn't find where to put the remote control socket")?;
+        let err = |err| format!("Error opening {}: {err}", path.display());
+
+        if UnixStream::connect(&path).is_ok() {
+            return Err(format!(
+                "{} is in use, is gbrowse already running?",
+                path.display()
+            ));
+        }
+
+        // left over from a gbrowse that didn't shut down properly
+        fs::remove_file(&path).ok();
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(err)?;
+        }
+
+        let listener = UnixListener::bind(&path).map_err(err)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).map_err(err)?;
+
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let tx: Sender<Request> = tx.clone();
+                let ctx = ctx.clone();
+
+                thread::spawn(move || {
+                    stream.set_read_timeout(Some(TIMEOUT)).ok();
+
+                    let mut line = String::new();
+                    BufReader::new((&stream).take(MAX_COMMAND))
+                        .read_line(&mut line)
+                        .ok();
+
+                    let reply = Command::parse(&line).and_then(|command| {
+                        let (reply, answer) = mpsc::channel();
+
+                        tx.send(Request { command, reply })
+                            .map_err(|_| "gbrowse is shutting down".to_string())?;
+                        ctx.request_repaint();
+
+                        answer
+                            .recv_timeout(TIMEOUT)
+                            .map_err(|_| "gbrowse didn't answer".to_string())?
+                    });
+
+                    let reply = match reply {
+                        Ok(text) => text,
+                        Err(err) => format!("error: {err}\n"),
+                    };
+
+                    stream.write_all(reply.as_bytes()).ok();
+                });
+            }
+        });
+
+        Ok(Self { rx, path })
+    }
+
+    #[cfg(not(unix))]
+    pub fn start(_ctx: egui::Context) -> Result<Self, String> {
+        Err("Remote control isn't supported on this platform".to_string())
+    }
+
+    /// The commands that came in since last time.
+    pub fn requests(&self) -> Vec<Request> {
+        self.rx.try_iter().collect()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Whether another gbrowse is running and listening on the socket.
+pub fn is_running() -> bool {
+    #[cfg(unix)]
+    if let Some(path) = socket_path() {
+        return std::os::unix::net::UnixStream::connect(path).is_ok();
+    }
+
+    false
+}
+
+/// In the runtime directory where there is one, since it's only readable
+/// by the user and is cleared on logout.
+pub fn socket_path() -> Option<PathBuf> {
+    let dirs = directories_next::ProjectDirs::from("", "", "gbrowse")?;
+
+    Some(
+        dirs.runtime_dir()
+            .map(|dir| dir.to_path_buf())
+            .or_else(config::cache_dir)?
+            .join("gbrowse.sock"),
+    )
+}
@@ -0,0 +1,138 @@
+//! Breaking paragraphs into lines by hand so they can be justified, since
+//! egui only lays text out flush left. Long words are hyphenated to keep
+//! the gaps between words from getting too wide.
+
+use std::collections::VecDeque;
+
+/// Words shorter than this are never hyphenated.
+const MIN_HYPHENATED_LEN: usize = 6;
+/// How many letters have to be left on each side of a hyphen.
+const MIN_PART_LEN: usize = 3;
+
+/// A word placed on a line, with its width.
+pub struct Word {
+    pub text: String,
+    pub width: f32,
+}
+
+/// Fill lines up to `width`, measuring words with `measure`.
+pub fn lines(text: &str, width: f32, space: f32, measure: impl Fn(&str) -> f32) -> Vec<Vec<Word>> {
+    let mut words: VecDeque<String> = text.split_whitespace().map(str::to_string).collect();
+    let mut lines = vec![];
+    let mut line: Vec<Word> = vec![];
+    let mut line_width = 0.0;
+
+    while let Some(text) = words.pop_front() {
+        let word_width = measure(&text);
+
+        // a word that's too long for any line gets one to itself
+        if line.is_empty() || line_width + space + word_width <= width {
+            if !line.is_empty() {
+                line_width += space;
+            }
+
+            line_width += word_width;
+            line.push(Word {
+                text,
+                width: word_width,
+            });
+
+            continue;
+        }
+
+        let room = width - line_width - space;
+
+        match hyphenate(&text, |head| measure(&format!("{head}-")) <= room) {
+            Some((head, tail)) => {
+                let head = format!("{head}-");
+
+                line.push(Word {
+                    width: measure(&head),
+                    text: head,
+                });
+                words.push_front(tail);
+            }
+            None => words.push_front(text),
+        }
+
+        lines.push(std::mem::take(&mut line));
+        line_width = 0.0;
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Split a word so the first part fits, breaking before a consonant that's
+/// followed by a vowel ("hy-phen-ate"), which is right often enough.
+fn hyphenate(word: &str, fits: impl Fn(&str) -> bool) -> Option<(String, String)> {
+    let chars: Vec<char> = word.chars().collect();
+
+    if chars.len() < MIN_HYPHENATED_LEN || !chars.iter().all(|c| c.is_alphabetic()) {
+        return None;
+    }
+
+    let is_vowel = |c: char| "aeiouy".contains(c.to_ascii_lowercase());
+
+    (MIN_PART_LEN..=chars.len() - MIN_PART_LEN)
+        .rev()
+        .filter(|&i| !is_vowel(chars[i]) && is_vowel(chars[i + 1]))
+        .map(|i| {
+            let head: String = chars[..i].iter().collect();
+            let tail: String = chars[i..].iter().collect();
+            (head, tail)
+        })
+        .find(|(head, _)| fits(head))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lay out with every letter and space one wide.
+    fn layout(text: &str, width: f32) -> Vec<Vec<String>> {
+        lines(text, width, 1.0, |word| word.chars().count() as f32)
+            .into_iter()
+            .map(|line| line.into_iter().map(|word| word.text).collect())
+            .collect()
+    }
+
+    #[test]
+    fn fills_lines_up_to_the_width() {
+        assert_eq!(layout("aaa bbb", 7.0), [["aaa", "bbb"]]);
+        assert_eq!(layout("aaa bbb", 6.0), [["aaa"], ["bbb"]]);
+        assert!(layout(" \n ", 10.0).is_empty());
+    }
+
+    #[test]
+    fn gives_long_words_their_own_line() {
+        assert_eq!(layout("aaaaaaaaaa b", 5.0), [["aaaaaaaaaa"], ["b"]]);
+    }
+
+    #[test]
+    fn hyphenates_long_words() {
+        assert_eq!(
+            layout("aa hyphenate", 8.0),
+            [vec!["aa", "hyp-"], vec!["henate"]]
+        );
+    }
+
+    #[test]
+    fn doesnt_hyphenate_short_or_odd_words() {
+        assert_eq!(layout("aa hello", 6.0), [["aa"], ["hello"]]);
+        assert_eq!(layout("aa hyphen8te", 8.0), [["aa"], ["hyphen8te"]]);
+    }
+
+    #[test]
+    fn leaves_enough_letters_on_each_side() {
+        assert_eq!(
+            hyphenate("letter", |_| true),
+            Some(("let".to_string(), "ter".to_string()))
+        );
+        assert_eq!(hyphenate("banana", |_| true), None);
+        assert_eq!(hyphenate("letter", |_| false), None);
+    }
+}
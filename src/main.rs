@@ -1,291 +1,358 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 use argh::FromArgs;
-use eframe::{
-    egui::{self, containers::Frame, output::OpenUrl, style::Margin, RichText, ScrollArea},
-    epaint::Color32,
-    NativeOptions, Renderer,
-};
-use gmi::{
-    gemtext::{self, GemtextNode},
-    protocol::StatusCode,
-    request,
-    url::Url,
-};
+use eframe::{NativeOptions, Renderer};
 
 use std::{
-    ffi::OsStr,
+    env,
+    io::{self, Read, Write},
     path::PathBuf,
-    str,
-    sync::{
-        mpsc::{self, Receiver, Sender, TryRecvError},
-        Arc,
-    },
-    thread,
+    process,
+    sync::Arc,
 };
 
-const DEFAULT_STARTING_PAGE: &'static str = "gemini://gemini.circumlunar.space";
+mod app;
+mod audio;
+mod bookmark_sync;
+mod bookmarks;
+mod cache;
+mod config;
+mod dns;
+mod downloads;
+mod favicon;
+mod feeds;
+mod fetch;
+mod file;
+mod fonts;
+mod gestures;
+mod gpub;
+mod handler;
+mod history;
+mod html;
+mod i18n;
+mod input;
+mod ipc;
+mod justify;
+mod markdown;
+mod monitor;
+mod nav;
+mod plugin;
+mod prefetch;
+mod request_log;
+mod resolver;
+mod rtl;
+mod screenshot;
+mod serve;
+mod session;
+mod settings;
+mod shortcuts;
+mod tab;
+mod toolbar;
+mod transport;
+mod tray;
+mod view;
+mod watch;
+mod welcome;
+mod window;
+
+use app::Gbrowse;
+use bookmarks::Bookmarks;
+use config::{Config, Theme};
+use fetch::GeminiFetcher;
+use file::FileFetcher;
+use history::History;
+use plugin::{LogPlugin, PluginFetcher, Plugins};
+use window::Geometry;
+
+const DEFAULT_STARTING_PAGE: &str = "gemini://gemini.circumlunar.space";
 
 fn main() {
-    let mut options = NativeOptions::default();
-
-    options.renderer = Renderer::Wgpu;
-
-    eframe::run_native("gbrowse", options, Box::new(|_cc| Box::new(Gbrowse::new())));
-}
-
-#[derive(FromArgs)]
-/// A simple gemini browser.
-struct GbrowseArgs {
-    /// what page to start on
-    #[argh(option, short = 'p')]
-    page: Option<String>,
-}
+    let args = args();
+
+    let result = match args.command {
+        None => browse(BrowseArgs::default(), None),
+        Some(Command::Browse(args)) => browse(args, None),
+        Some(Command::Dump(args)) => dump(args),
+        Some(Command::Serve(args)) => serve(args),
+        Some(Command::RegisterHandler(_)) => handler::register(),
+        Some(Command::ExportHistory(args)) => History::load().export(&args.path),
+        Some(Command::ImportProfile(args)) => import_profile(args),
+    };
 
-struct Gbrowse {
-    tx: Sender<Result<Vec<GemtextNode>, String>>,
-    rx: Receiver<Result<Vec<GemtextNode>, String>>,
-    sites: Vec<String>,
-    content: Option<Vec<GemtextNode>>,
-    error: Option<String>,
-    loading: bool,
-    url: String,
+    if let Err(err) = result {
+        eprintln!("{err}");
+        process::exit(1);
+    }
 }
 
-impl Gbrowse {
-    pub fn new() -> Self {
-        let args: GbrowseArgs = argh::from_env();
+/// Like [`argh::from_env`], but `gbrowse -` and `gbrowse --stdin` are
+/// short for `gbrowse browse --stdin`. argh won't take `-` as an argument,
+/// so it's passed as `--stdin` instead.
+fn args() -> GbrowseArgs {
+    let strings: Vec<String> = env::args().collect();
+    let mut args: Vec<&str> = strings.iter().skip(1).map(String::as_str).collect();
 
-        let (tx, rx) = mpsc::channel();
+    if matches!(args.first(), Some(&"-" | &"--stdin")) {
+        args.insert(0, "browse");
+    }
 
-        Self {
-            tx,
-            rx,
-            sites: vec![],
-            content: None,
-            error: None,
-            loading: false,
-            url: args.page.unwrap_or(DEFAULT_STARTING_PAGE.to_string()),
+    if args.first() == Some(&"browse") {
+        for arg in &mut args {
+            if *arg == "-" {
+                *arg = "--stdin";
+            }
         }
     }
 
-    pub fn change_site(&mut self, url: &str, moving_back: bool) {
-        self.error = None;
-        self.content = None;
+    GbrowseArgs::from_args(&["gbrowse"], &args).unwrap_or_else(|early_exit| {
+        process::exit(match early_exit.status {
+            Ok(()) => {
+                println!("{}", early_exit.output);
+                0
+            }
+            Err(()) => {
+                eprintln!(
+                    "{}\nRun gbrowse --help for more information.",
+                    early_exit.output
+                );
+                1
+            }
+        })
+    })
+}
 
-        println!("going to {url}");
+/// Open the browser, watching `served` for changes if it's serving a
+/// directory.
+fn browse(args: BrowseArgs, served: Option<(PathBuf, String)>) -> Result<(), String> {
+    let config = Config::load();
 
-        self.url = url.to_string();
+    let mut options = NativeOptions {
+        renderer: Renderer::Wgpu,
+        always_on_top: config.always_on_top,
+        follow_system_theme: config.theme == Theme::System,
+        ..NativeOptions::default()
+    };
 
-        let url_structured = match Url::try_from(url) {
-            Ok(url_structured) => url_structured,
-            Err(err) => {
-                self.error = Some(format!("Incorrectly formatted url: {err}"));
+    Geometry::load().apply(&mut options);
 
-                return;
-            }
-        };
+    let mut plugins = Plugins::default();
 
-        let url = Arc::new(url_structured);
-        let tx = self.tx.clone();
+    if config.log_requests {
+        plugins.register(LogPlugin);
+    }
 
-        if !moving_back {
-            self.sites.push(self.url.clone());
-        }
+    for plugin in config.plugins.clone() {
+        plugins.register(plugin);
+    }
+
+    let tray_icon = config.tray_icon;
+    let mut start_page = args.url.unwrap_or(config.home_page().to_string());
+    let mut piped = None;
+
+    if args.stdin {
+        let mut text = String::new();
 
-        self.loading = true;
+        io::stdin()
+            .read_to_string(&mut text)
+            .map_err(|err| format!("Error reading stdin: {err}"))?;
 
-        thread::spawn(move || {
-            tx.send(make_request(&url)).unwrap();
-        });
+        start_page = app::STDIN_URL.to_string();
+        piped = Some(text);
     }
 
-    pub fn get_content(&mut self) -> Option<Vec<GemtextNode>> {
-        match self.rx.try_recv() {
-            Ok(content) => match content {
-                Ok(content) => {
-                    self.loading = false;
-                    Some(content)
-                }
-                Err(err) => {
-                    self.error = Some(err);
-                    self.loading = false;
-
-                    None
-                }
-            },
-            Err(err) => {
-                if err != TryRecvError::Empty {
-                    self.error = Some(format!("Error Recieving From Other Thread: {err}"));
-                    self.loading = false;
-                }
-
-                None
-            }
-        }
+    if let Some(version) = args.ip_version {
+        dns::override_ip_version(version);
     }
-}
 
-impl eframe::App for Gbrowse {
-    fn update(&mut self, ctx: &egui::Context, _fame: &mut eframe::Frame) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            // get content back from other thread
-            if let Some(content) = self.get_content() {
-                self.content = Some(content);
+    eframe::run_native(
+        "gbrowse",
+        options,
+        Box::new(move |cc| {
+            let mut app = Gbrowse::new(plugins, start_page);
+            app.load_fonts(&cc.egui_ctx);
+            app.listen(&cc.egui_ctx);
+
+            if tray_icon {
+                app.show_tray(&cc.egui_ctx);
             }
 
-            // search bar
-            ScrollArea::horizontal()
-                .id_source("horizontal scroll")
-                .show(ui, |ui| {
-                    ui.horizontal(|ui| {
-                        if self.sites.len() > 1 {
-                            if ui.button("⏪").clicked() {
-                                self.sites.pop();
-                                if let Some(before) = self.sites.clone().last() {
-                                    self.change_site(before, true);
-                                }
-                            }
-                        }
-
-                        ui.text_edit_singleline(&mut self.url);
-
-                        if ui.button("🚀").clicked() {
-                            self.change_site(&self.url.clone(), false);
-                        }
-
-                        if self.loading {
-                            ui.label("loading...");
-                        }
-                    });
-                });
-
-            ui.separator();
-
-            // display error
-            if let Some(err) = &self.error {
-                ui.label(RichText::new(err).color(Color32::RED).strong());
+            if let Some(text) = piped {
+                app.set_piped(text);
             }
 
-            // display text
-            if let Some(content) = &self.content.clone() {
-                ScrollArea::vertical()
-                    .id_source("vertical scroll")
-                    .auto_shrink([false, false])
-                    .show(ui, |ui| {
-                        for block in content {
-                            match block {
-                                GemtextNode::Text(text) => {
-                                    ui.label(text);
-                                }
-                                GemtextNode::Link(url, label) => {
-                                    let link = ui
-                                        .link(label.as_ref().unwrap_or(&url))
-                                        .on_hover_text_at_pointer(url);
-
-                                    if link.clicked() {
-                                        // if full url
-                                        if let Ok(parsed_url) = url::Url::parse(url) {
-                                            if parsed_url.scheme() == "http"
-                                                || parsed_url.scheme() == "https"
-                                            {
-                                                ui.ctx().output().open_url =
-                                                    Some(OpenUrl::new_tab(url));
-                                            // if gemini link
-                                            } else if parsed_url.scheme() == "gemini" {
-                                                self.change_site(url.as_str(), false);
-                                            }
-                                        } else {
-                                            // if relative url
-                                            let mut new_url =
-                                                url::Url::parse(&self.url.clone()).unwrap();
-                                            let mut new_path =
-                                                PathBuf::from(new_url.path());
-
-                                            let addition = PathBuf::from(url.clone());
-
-                                            if addition.is_absolute() {
-                                                new_path = addition;
-                                            } else {
-                                                if addition.extension() == Some(OsStr::new("gmi")) {
-                                                    new_path.pop();
-                                                }
-
-                                                new_path.push(addition);
-                                            }
-
-                                            new_url.set_path(
-                                                new_path.to_str().unwrap_or_default(),
-                                            );
-                                            self.change_site(new_url.as_str(), false);
-                                        }
-                                    }
-                                }
-                                GemtextNode::Heading(text) => {
-                                    ui.label(RichText::new(text).size(30.0));
-                                }
-                                GemtextNode::SubHeading(text) => {
-                                    ui.label(RichText::new(text).size(25.0));
-                                }
-                                GemtextNode::SubSubHeading(text) => {
-                                    ui.label(RichText::new(text).size(20.0));
-                                }
-                                GemtextNode::ListItem(text) => {
-                                    ui.label(format!("  • {text}"));
-                                }
-                                GemtextNode::Blockquote(text) => {
-                                    let frame = Frame {
-                                        outer_margin: Margin {
-                                            left: 15.0,
-                                            ..Margin::default()
-                                        },
-                                        ..Frame::default()
-                                    };
-
-                                    frame.show(ui, |ui| {
-                                        ui.label(text);
-                                    });
-                                }
-                                GemtextNode::Preformatted(text, _) => {
-                                    ui.code(text);
-                                }
-                                GemtextNode::EmptyLine => {
-                                    ui.add_space(10.0);
-                                }
-                            };
-                        }
-                    });
+            if let Some((dir, url)) = &served {
+                app.watch(dir, url);
             }
-        });
 
-        if self.loading {
-            ctx.request_repaint();
-        }
+            if let Some(scale) = args.scale {
+                app.set_ui_scale(scale);
+            }
+
+            Box::new(app)
+        }),
+    );
+
+    Ok(())
+}
+
+/// Print a page to stdout without opening a window.
+fn dump(args: DumpArgs) -> Result<(), String> {
+    let config = Config::load();
+
+    dns::set_resolver(config.resolver.as_deref());
+    dns::set_ip_version(config.ip_version);
+    transport::set_identities(&config.identities());
+
+    if let Some(version) = args.ip_version {
+        dns::override_ip_version(version);
+    }
+
+    // not the request log, which would end up in the page
+    let mut plugins = Plugins::default();
+
+    for plugin in config.plugins.clone() {
+        plugins.register(plugin);
     }
+
+    let fetcher = FileFetcher::new(Arc::new(PluginFetcher::new(
+        Arc::new(GeminiFetcher),
+        plugins,
+    )));
+    let response = fetch::fetch_followed(&fetcher, &args.url, &config.sites())
+        .map_err(|err| err.to_string())?;
+
+    io::stdout()
+        .write_all(&response.data)
+        .map_err(|err| format!("Error writing the page: {err}"))
 }
 
-fn make_request(url: &Url) -> Result<Vec<GemtextNode>, String> {
-    let mut url = url.clone();
+fn serve(args: ServeArgs) -> Result<(), String> {
+    let url = serve::start(&args.dir, args.port)?;
 
-    let data: Vec<u8> = loop {
-        let response = match request::make_request(&url) {
-            Ok(response) => response,
-            Err(err) => return Err(format!("Request Error: {err}")),
-        };
+    println!("Serving {} at {url}", args.dir.display());
 
-        match response.status {
-            StatusCode::Redirect(_) => url = Url::try_from(response.meta.as_str()).unwrap(),
-            StatusCode::Success(_) => break response.data,
-            s => return Err(format!("Error: unknown status code: {:?}", s)),
-        }
+    let browse_args = BrowseArgs {
+        url: Some(url.clone()),
+        ..BrowseArgs::default()
     };
 
-    let text = match str::from_utf8(&data) {
-        Ok(text) => text,
-        Err(err) => return Err(format!("Text Formatting Error: {err}")),
-    };
+    browse(browse_args, Some((args.dir, url)))
+}
+
+/// Merge the bookmarks and history from another profile's directory.
+fn import_profile(args: ImportProfileArgs) -> Result<(), String> {
+    // it would write over them with its own copy
+    if ipc::is_running() {
+        return Err("Close gbrowse before importing a profile".to_string());
+    }
+
+    let bookmarks_path = args.dir.join("bookmarks.ron");
+    let history_path = args.dir.join("history.ron");
+
+    if !bookmarks_path.exists() && !history_path.exists() {
+        return Err(format!(
+            "{} has no bookmarks.ron or history.ron",
+            args.dir.display()
+        ));
+    }
 
-    let gemtext = gemtext::parse_gemtext(text);
+    if bookmarks_path.exists() {
+        let added = Bookmarks::load().merge(config::read_path(&bookmarks_path)?);
+        println!("Bookmarks added: {added}");
+    }
+
+    if history_path.exists() {
+        let added = History::load().merge(config::read_path(&history_path)?);
+        println!("Pages added to the history: {added}");
+    }
+
+    Ok(())
+}
+
+#[derive(FromArgs)]
+/// A simple gemini browser.
+struct GbrowseArgs {
+    #[argh(subcommand)]
+    command: Option<Command>,
+}
 
-    Ok(gemtext)
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    Browse(BrowseArgs),
+    Dump(DumpArgs),
+    Serve(ServeArgs),
+    RegisterHandler(RegisterHandlerArgs),
+    ExportHistory(ExportHistoryArgs),
+    ImportProfile(ImportProfileArgs),
+}
+
+#[derive(FromArgs, Default)]
+/// Open the browser, which is what happens without a command too.
+#[argh(subcommand, name = "browse")]
+struct BrowseArgs {
+    /// what page to start on, instead of the home page
+    #[argh(positional)]
+    url: Option<String>,
+
+    /// show gemtext piped in, also written as -
+    #[argh(switch)]
+    stdin: bool,
+
+    /// scale the interface, e.g. 1.5 for 150%
+    #[argh(option)]
+    scale: Option<f32>,
+
+    /// connect over any, prefer-ipv6, prefer-ipv4, ipv6 or ipv4
+    #[argh(option)]
+    ip_version: Option<dns::IpVersion>,
+}
+
+#[derive(FromArgs)]
+/// Print a page's body to stdout, following redirects.
+#[argh(subcommand, name = "dump")]
+struct DumpArgs {
+    /// the url to fetch
+    #[argh(positional)]
+    url: String,
+
+    /// connect over any, prefer-ipv6, prefer-ipv4, ipv6 or ipv4
+    #[argh(option)]
+    ip_version: Option<dns::IpVersion>,
+}
+
+#[derive(FromArgs)]
+/// Preview a directory as a capsule, served over gemini on localhost.
+#[argh(subcommand, name = "serve")]
+struct ServeArgs {
+    /// the directory to serve
+    #[argh(positional)]
+    dir: PathBuf,
+
+    /// the port to serve on, or any free one if it's taken
+    #[argh(option, default = "1965")]
+    port: u16,
+}
+
+#[derive(FromArgs)]
+/// Open gemini:// links from other programs in gbrowse.
+#[argh(subcommand, name = "register-handler")]
+struct RegisterHandlerArgs {}
+
+#[derive(FromArgs)]
+/// Export the browsing history to a .json or .csv file.
+#[argh(subcommand, name = "export-history")]
+struct ExportHistoryArgs {
+    /// the file to write
+    #[argh(positional)]
+    path: PathBuf,
+}
+
+#[derive(FromArgs)]
+/// Merge another profile's bookmarks and history into this one.
+#[argh(subcommand, name = "import-profile")]
+struct ImportProfileArgs {
+    /// a copy of gbrowse's config directory from another computer
+    #[argh(positional)]
+    dir: PathBuf,
 }
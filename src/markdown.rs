@@ -0,0 +1,280 @@
+//! Turns markdown into gemtext nodes so it can be shown like any other
+//! page. Only the common parts are handled: headings, emphasis, lists,
+//! quotes, links and code. Pages can be turned back into markdown too.
+
+use gmi::gemtext::GemtextNode;
+
+use crate::nav;
+
+pub fn to_gemtext(text: &str) -> Vec<GemtextNode> {
+    let mut nodes = Vec::new();
+    let mut lines = text.lines();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+
+        // fenced code blocks
+        if let Some(alt) = trimmed.strip_prefix("```") {
+            let mut code = Vec::new();
+
+            for line in lines.by_ref() {
+                if line.trim_start().starts_with("```") {
+                    break;
+                }
+
+                code.push(line);
+            }
+
+            let alt = Some(alt.trim().to_string()).filter(|alt| !alt.is_empty());
+            nodes.push(GemtextNode::Preformatted(code.join("\n"), alt));
+            continue;
+        }
+
+        if trimmed.is_empty() {
+            nodes.push(GemtextNode::EmptyLine);
+            continue;
+        }
+
+        let (node, links) = if let Some(heading) = heading(trimmed) {
+            heading
+        } else if let Some(quote) = trimmed.strip_prefix('>') {
+            let (text, links) = inline(quote.trim());
+            (GemtextNode::Blockquote(text), links)
+        } else if let Some(item) = list_item(trimmed) {
+            let (text, links) = inline(item);
+            (GemtextNode::ListItem(text), links)
+        } else {
+            let (text, links) = inline(trimmed);
+            (GemtextNode::Text(text), links)
+        };
+
+        nodes.push(node);
+
+        // gemtext links go on their own line, so put them under the text
+        nodes.extend(
+            links
+                .into_iter()
+                .map(|(text, url)| GemtextNode::Link(url, Some(text))),
+        );
+    }
+
+    nodes
+}
+
+/// Write a page out as markdown, with links made absolute so they still
+/// work when pasted somewhere else.
+pub fn from_gemtext(nodes: &[GemtextNode], base: &str) -> String {
+    let mut blocks: Vec<String> = Vec::new();
+    let mut in_list = false;
+
+    for node in nodes {
+        let block = match node {
+            GemtextNode::Text(text) => text.clone(),
+            GemtextNode::Link(url, label) => {
+                let url = nav::resolve(base, url).unwrap_or_else(|| url.clone());
+                format!("[{}](<{url}>)", label.as_deref().unwrap_or(&url))
+            }
+            GemtextNode::Heading(text) => format!("# {text}"),
+            GemtextNode::SubHeading(text) => format!("## {text}"),
+            GemtextNode::SubSubHeading(text) => format!("### {text}"),
+            GemtextNode::ListItem(text) => {
+                // keep list items together
+                if in_list {
+                    if let Some(list) = blocks.last_mut() {
+                        list.push_str(&format!("\n- {text}"));
+                        continue;
+                    }
+                }
+
+                in_list = true;
+                blocks.push(format!("- {text}"));
+                continue;
+            }
+            GemtextNode::Blockquote(text) => format!("> {text}"),
+            GemtextNode::Preformatted(text, alt) => {
+                format!("```{}\n{text}\n```", alt.as_deref().unwrap_or_default())
+            }
+            // blocks are already separated by blank lines
+            GemtextNode::EmptyLine => continue,
+        };
+
+        in_list = false;
+        blocks.push(block);
+    }
+
+    let mut markdown = blocks.join("\n\n");
+    markdown.push('\n');
+    markdown
+}
+
+type Links = Vec<(String, String)>;
+
+fn heading(line: &str) -> Option<(GemtextNode, Links)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let text = line[level..].strip_prefix(' ')?;
+    let (text, links) = inline(text.trim_end_matches('#').trim());
+
+    let node = match level {
+        1 => GemtextNode::Heading(text),
+        2 => GemtextNode::SubHeading(text),
+        3..=6 => GemtextNode::SubSubHeading(text),
+        _ => return None,
+    };
+
+    Some((node, links))
+}
+
+fn list_item(line: &str) -> Option<&str> {
+    if let Some(item) = ["- ", "* ", "+ "]
+        .iter()
+        .find_map(|marker| line.strip_prefix(marker))
+    {
+        return Some(item);
+    }
+
+    // numbered lists
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+
+    line[digits..].strip_prefix(". ").filter(|_| digits > 0)
+}
+
+/// Strip emphasis and code markers, and pull out the links.
+fn inline(text: &str) -> (String, Links) {
+    let mut out = String::new();
+    let mut links = Vec::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '*' | '_' | '`' => rest = &rest[1..],
+            '!' if rest.starts_with("![") => rest = &rest[1..],
+            '[' => match link(rest) {
+                Some((label, url, len)) => {
+                    out.push_str(&label);
+                    links.push((label, url));
+                    rest = &rest[len..];
+                }
+                None => {
+                    out.push(c);
+                    rest = &rest[1..];
+                }
+            },
+            c => {
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    (out, links)
+}
+
+/// Parse a `[label](url)` link at the start of `text`, returning its label,
+/// url and length.
+fn link(text: &str) -> Option<(String, String, usize)> {
+    let close = text.find("](")?;
+    let end = close + text[close..].find(')')?;
+
+    let (label, _) = inline(&text[1..close]);
+    let url = text[close + 2..end].split_whitespace().next()?.to_string();
+
+    Some((label, url, end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> GemtextNode {
+        GemtextNode::Text(text.to_string())
+    }
+
+    #[test]
+    fn converts_headings_up_to_six_levels() {
+        assert_eq!(
+            to_gemtext("# One\n## Two\n### Three ###\n###### Six\n####### Seven\n#tag"),
+            [
+                GemtextNode::Heading("One".to_string()),
+                GemtextNode::SubHeading("Two".to_string()),
+                GemtextNode::SubSubHeading("Three".to_string()),
+                GemtextNode::SubSubHeading("Six".to_string()),
+                text("####### Seven"),
+                text("#tag"),
+            ]
+        );
+    }
+
+    #[test]
+    fn converts_lists_and_quotes() {
+        assert_eq!(
+            to_gemtext("- a\n* b\n12. c\n1.5 apples\n-d\n> quoted\n\n"),
+            [
+                GemtextNode::ListItem("a".to_string()),
+                GemtextNode::ListItem("b".to_string()),
+                GemtextNode::ListItem("c".to_string()),
+                text("1.5 apples"),
+                text("-d"),
+                GemtextNode::Blockquote("quoted".to_string()),
+                GemtextNode::EmptyLine,
+            ]
+        );
+    }
+
+    #[test]
+    fn moves_links_under_their_line() {
+        assert_eq!(
+            to_gemtext(
+                "Read **[the docs](gemini://a.org/ \"Docs\")** or ![a cat](cat.png) [not a link"
+            ),
+            [
+                text("Read the docs or a cat [not a link"),
+                GemtextNode::Link("gemini://a.org/".to_string(), Some("the docs".to_string())),
+                GemtextNode::Link("cat.png".to_string(), Some("a cat".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_code_blocks_as_they_are() {
+        assert_eq!(
+            to_gemtext("```rust\n# not a heading\n  *x*\n```\nafter"),
+            [
+                GemtextNode::Preformatted(
+                    "# not a heading\n  *x*".to_string(),
+                    Some("rust".to_string())
+                ),
+                text("after"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_code_block_runs_to_the_end() {
+        assert_eq!(
+            to_gemtext("```\ncode\nmore"),
+            [GemtextNode::Preformatted("code\nmore".to_string(), None)]
+        );
+    }
+
+    #[test]
+    fn writes_markdown_with_absolute_links() {
+        let nodes = [
+            GemtextNode::Heading("Title".to_string()),
+            GemtextNode::EmptyLine,
+            text("Hi"),
+            GemtextNode::ListItem("a".to_string()),
+            GemtextNode::EmptyLine,
+            GemtextNode::ListItem("b".to_string()),
+            GemtextNode::Link("post.gmi".to_string(), None),
+            GemtextNode::Link("gemini://b.org/".to_string(), Some("B".to_string())),
+            GemtextNode::Preformatted("code".to_string(), None),
+        ];
+
+        assert_eq!(
+            from_gemtext(&nodes, "gemini://a.org/dir/index.gmi"),
+            "# Title\n\nHi\n\n- a\n- b\n\n\
+             [gemini://a.org/dir/post.gmi](<gemini://a.org/dir/post.gmi>)\n\n\
+             [B](<gemini://b.org/>)\n\n```\ncode\n```\n"
+        );
+    }
+}
@@ -0,0 +1,309 @@
+//! Watched pages: gbrowse fetches them every so often in the background
+//! and flags the ones that changed since they were last looked at, with
+//! a diff of what changed. Kept in `watched.ron`.
+
+use gmi::gemtext::GemtextNode;
+use serde::{Deserialize, Serialize};
+
+use std::{
+    collections::HashSet,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    config::{self, Sites},
+    fetch::{self, Fetcher, Page},
+    history::now,
+    i18n::{tr, trf},
+    input,
+};
+
+/// The diff for a watched page is at `about:diff?` and its url.
+pub const DIFF_URL: &str = "about:diff";
+/// Lines of unchanged text shown around each change.
+const CONTEXT_LINES: usize = 2;
+/// Bigger pages than this (lines times lines) are shown as all removed
+/// and all added, instead of working out which lines changed.
+const MAX_DIFF_SIZE: usize = 4_000_000;
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct WatchedPage {
+    pub url: String,
+    pub title: String,
+    /// The page as it was last fetched, or `None` before the first check.
+    pub text: Option<String>,
+    /// The page before it last changed.
+    pub previous: Option<String>,
+    /// Changed since it was last looked at.
+    pub changed: bool,
+    /// Seconds since the unix epoch.
+    pub checked: u64,
+}
+
+pub struct Monitor {
+    pages: Vec<WatchedPage>,
+    /// Urls being fetched right now.
+    checking: HashSet<String>,
+    tx: Sender<(String, Result<String, String>)>,
+    rx: Receiver<(String, Result<String, String>)>,
+}
+
+impl Monitor {
+    pub fn load() -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        Self {
+            pages: config::read_file("watched.ron"),
+            checking: HashSet::new(),
+            tx,
+            rx,
+        }
+    }
+
+    fn save(&self) {
+        config::write_file("watched.ron", &self.pages);
+    }
+
+    pub fn pages(&self) -> &[WatchedPage] {
+        &self.pages
+    }
+
+    pub fn is_watched(&self, url: &str) -> bool {
+        self.pages.iter().any(|page| page.url == url)
+    }
+
+    /// How many pages changed since they were looked at.
+    pub fn changed(&self) -> usize {
+        self.pages.iter().filter(|page| page.changed).count()
+    }
+
+    pub fn is_checking(&self) -> bool {
+        !self.checking.is_empty()
+    }
+
+    /// Start watching a page. It's fetched right away to have something
+    /// to compare against.
+    pub fn watch(&mut self, url: &str, title: &str) {
+        if !self.is_watched(url) {
+            self.pages.push(WatchedPage {
+                url: url.to_string(),
+                title: title.to_string(),
+                ..Default::default()
+            });
+            self.save();
+        }
+    }
+
+    pub fn unwatch(&mut self, url: &str) {
+        self.pages.retain(|page| page.url != url);
+        self.save();
+    }
+
+    /// The page has been looked at, so it's not flagged anymore.
+    pub fn seen(&mut self, url: &str) {
+        for page in &mut self.pages {
+            if page.url == url && page.changed {
+                page.changed = false;
+                self.save();
+                return;
+            }
+        }
+    }
+
+    /// Check every page on the next [`Monitor::check_due`].
+    pub fn check_all(&mut self) {
+        for page in &mut self.pages {
+            page.checked = 0;
+        }
+    }
+
+    /// Fetch the pages that haven't been checked for `interval` in the
+    /// background.
+    pub fn check_due(&mut self, fetcher: Arc<dyn Fetcher>, sites: &Sites, interval: Duration) {
+        let now = now();
+
+        for page in &self.pages {
+            if now.saturating_sub(page.checked) < interval.as_secs()
+                || self.checking.contains(&page.url)
+            {
+                continue;
+            }
+
+            let url = page.url.clone();
+            let fetcher = fetcher.clone();
+            let sites = sites.clone();
+            let tx = self.tx.clone();
+
+            self.checking.insert(url.clone());
+
+            thread::spawn(move || {
+                let result = fetch::fetch_followed(&*fetcher, &url, &sites)
+                    .map(|response| String::from_utf8_lossy(&response.data).into_owned())
+                    .map_err(|err| err.to_string());
+
+                tx.send((url, result)).ok();
+            });
+        }
+    }
+
+    /// Compare the pages that finished fetching with how they were.
+    pub fn poll(&mut self) {
+        let results: Vec<_> = self.rx.try_iter().collect();
+
+        if results.is_empty() {
+            return;
+        }
+
+        for (url, result) in results {
+            self.checking.remove(&url);
+
+            let Some(page) = self.pages.iter_mut().find(|page| page.url == url) else {
+                continue;
+            };
+
+            // try again next time instead of right away
+            page.checked = now();
+
+            let text = match result {
+                Ok(text) => text,
+                Err(err) => {
+                    eprintln!("Error checking {url} for changes: {err}");
+                    continue;
+                }
+            };
+
+            match &page.text {
+                Some(old) if *old != text => {
+                    page.previous = page.text.replace(text);
+                    page.changed = true;
+                }
+                Some(_) => {}
+                None => page.text = Some(text),
+            }
+        }
+
+        self.save();
+    }
+
+    /// A page showing what changed on a watched page, for `about:diff`.
+    pub fn diff_page(&self, diff_url: &str) -> Option<Page> {
+        let url = input::decode(diff_url.strip_prefix(DIFF_URL)?.strip_prefix('?')?);
+        let page = self.pages.iter().find(|page| page.url == url)?;
+
+        let mut nodes = vec![
+            GemtextNode::Heading(trf("Changes to {title}", &[("title", &page.title)])),
+            GemtextNode::Link(page.url.clone(), Some(page.url.clone())),
+            GemtextNode::EmptyLine,
+        ];
+
+        match (&page.previous, &page.text) {
+            (Some(old), Some(new)) => {
+                nodes.push(GemtextNode::Preformatted(
+                    diff(old, new),
+                    Some("diff".to_string()),
+                ));
+            }
+            _ => nodes.push(GemtextNode::Text(tr(
+                "The page hasn't changed since it was first watched.",
+            ))),
+        }
+
+        Some(Page {
+            url: diff_url.to_string(),
+            redirects: vec![],
+            nodes,
+            ..Default::default()
+        })
+    }
+}
+
+pub fn diff_url(url: &str) -> String {
+    format!("{DIFF_URL}?{}", input::encode(url))
+}
+
+/// The lines that were taken out (`-`) and put in (`+`), with a few
+/// unchanged lines around them.
+fn diff(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    let lines = if old.len() * new.len() > MAX_DIFF_SIZE {
+        old.iter()
+            .map(|line| ('-', *line))
+            .chain(new.iter().map(|line| ('+', *line)))
+            .collect()
+    } else {
+        diff_lines(&old, &new)
+    };
+
+    // keep the lines near a change
+    let near_change = |index: usize| {
+        let start = index.saturating_sub(CONTEXT_LINES);
+        let end = (index + CONTEXT_LINES + 1).min(lines.len());
+
+        lines[start..end].iter().any(|(kind, _)| *kind != ' ')
+    };
+
+    let mut out = String::new();
+    let mut skipped = false;
+
+    for (index, (kind, line)) in lines.iter().enumerate() {
+        if !near_change(index) {
+            skipped = true;
+            continue;
+        }
+
+        if skipped && !out.is_empty() {
+            out += "…\n";
+        }
+
+        skipped = false;
+        out += &format!("{kind} {line}\n");
+    }
+
+    out.trim_end().to_string()
+}
+
+/// Each line of both, marked as unchanged (` `), removed or added, from
+/// the longest common subsequence of lines.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(char, &'a str)> {
+    // lengths[i][j] is the longest common subsequence of old[i..] and new[j..]
+    let mut lengths = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = vec![];
+
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            lines.push(('-', old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', new[j]));
+            j += 1;
+        }
+    }
+
+    lines.extend(old[i..].iter().map(|line| ('-', *line)));
+    lines.extend(new[j..].iter().map(|line| ('+', *line)));
+    lines
+}
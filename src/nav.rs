@@ -0,0 +1,152 @@
+//! Working out where to go from the current url.
+
+/// Where a link on the page at `base` goes.
+pub fn resolve(base: &str, link: &str) -> Option<String> {
+    // if full url
+    if url::Url::parse(link).is_ok() {
+        return Some(link.to_string());
+    }
+
+    // relative to the directory the page is in, so `dir/` and `dir/page.gmi`
+    // link to `dir/other.gmi` the same way
+    url::Url::parse(base)
+        .ok()?
+        .join(link)
+        .ok()
+        .map(String::from)
+}
+
+/// Each level of a url's path and the url of that level, starting with the
+/// host, e.g. `host`, `dir`, `page.gmi`.
+pub fn breadcrumbs(url: &str) -> Vec<(String, String)> {
+    let Ok(mut url) = url::Url::parse(url) else {
+        return vec![];
+    };
+
+    let Some(host) = url.host_str().map(str::to_string) else {
+        return vec![];
+    };
+
+    url.set_query(None);
+    url.set_fragment(None);
+
+    let path = url.path().to_string();
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    url.set_path("/");
+    let mut crumbs = vec![(host, url.to_string())];
+
+    for (i, segment) in segments.iter().enumerate() {
+        let mut crumb = format!("/{}", segments[..=i].join("/"));
+
+        // everything above the page is a directory
+        if i + 1 < segments.len() || path.ends_with('/') {
+            crumb.push('/');
+        }
+
+        url.set_path(&crumb);
+        crumbs.push((segment.to_string(), url.to_string()));
+    }
+
+    crumbs
+}
+
+/// The directory above the current page.
+pub fn parent(url: &str) -> Option<String> {
+    let crumbs = breadcrumbs(url);
+
+    crumbs
+        .len()
+        .checked_sub(2)
+        .map(|parent| crumbs[parent].1.clone())
+}
+
+/// The top of the capsule.
+pub fn root(url: &str) -> Option<String> {
+    let crumbs = breadcrumbs(url);
+
+    Some(crumbs.first()?.1.clone()).filter(|_| crumbs.len() > 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = "gemini://a.org/dir/page.gmi";
+
+    #[test]
+    fn keeps_absolute_links() {
+        assert_eq!(
+            resolve(PAGE, "gemini://b.org/x.gmi").as_deref(),
+            Some("gemini://b.org/x.gmi")
+        );
+        assert_eq!(
+            resolve(PAGE, "https://b.org/").as_deref(),
+            Some("https://b.org/")
+        );
+        assert_eq!(
+            resolve(PAGE, "mercury://b.org/").as_deref(),
+            Some("mercury://b.org/")
+        );
+        assert_eq!(
+            resolve(PAGE, "//b.org/x.gmi").as_deref(),
+            Some("gemini://b.org/x.gmi")
+        );
+    }
+
+    #[test]
+    fn resolves_relative_to_the_directory() {
+        for base in [PAGE, "gemini://a.org/dir/"] {
+            assert_eq!(
+                resolve(base, "other.gmi").as_deref(),
+                Some("gemini://a.org/dir/other.gmi")
+            );
+        }
+
+        assert_eq!(
+            resolve("gemini://a.org/dir", "other.gmi").as_deref(),
+            Some("gemini://a.org/other.gmi")
+        );
+        assert_eq!(
+            resolve("gemini://a.org", "other.gmi").as_deref(),
+            Some("gemini://a.org/other.gmi")
+        );
+    }
+
+    #[test]
+    fn resolves_parent_and_root_paths() {
+        assert_eq!(
+            resolve(PAGE, "../up.gmi").as_deref(),
+            Some("gemini://a.org/up.gmi")
+        );
+        assert_eq!(
+            resolve(PAGE, "../../../../up.gmi").as_deref(),
+            Some("gemini://a.org/up.gmi")
+        );
+        assert_eq!(
+            resolve(PAGE, "/top.gmi").as_deref(),
+            Some("gemini://a.org/top.gmi")
+        );
+    }
+
+    #[test]
+    fn resolves_queries_and_fragments() {
+        assert_eq!(
+            resolve(PAGE, "?q=1").as_deref(),
+            Some("gemini://a.org/dir/page.gmi?q=1")
+        );
+        assert_eq!(
+            resolve(PAGE, "#end").as_deref(),
+            Some("gemini://a.org/dir/page.gmi#end")
+        );
+    }
+
+    #[test]
+    fn needs_a_valid_base_for_relative_links() {
+        assert_eq!(resolve("not a url", "other.gmi"), None);
+        assert_eq!(
+            resolve("not a url", "gemini://b.org/").as_deref(),
+            Some("gemini://b.org/")
+        );
+    }
+}
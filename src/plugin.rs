@@ -0,0 +1,345 @@
+use eframe::egui::Ui;
+use gmi::{
+    gemtext::GemtextNode,
+    protocol::{Response, StatusCode},
+    url::Url,
+};
+use serde::{Deserialize, Serialize};
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+    sync::Arc,
+    thread,
+};
+
+use crate::fetch::{self, Fetcher, Stream};
+
+/// Hooks into requests and rendering.
+///
+/// Every method has a default that does nothing, so a plugin only needs to
+/// implement the hooks it cares about.
+pub trait Plugin: Send + Sync {
+    /// Called before every request, including each hop of a redirect. The
+    /// url may be rewritten, and returning a response skips the network
+    /// entirely, which is how a plugin can serve its own scheme.
+    fn before_request(&self, _url: &mut Url) -> Option<Result<Response, String>> {
+        None
+    }
+
+    /// Called with every response that came from the network. Downloads
+    /// are streamed to disk, so their `data` is empty.
+    fn after_response(&self, _url: &Url, _response: &mut Response) {}
+
+    /// Draw a node in place of the default renderer, returning `true` if
+    /// the node was handled.
+    fn render_node(&self, _ui: &mut Ui, _node: &GemtextNode) -> bool {
+        false
+    }
+
+    /// The schemes this plugin serves in [`Plugin::before_request`], so
+    /// links to them are opened in gbrowse.
+    fn schemes(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// The registered plugins, called in the order they were registered.
+#[derive(Clone, Default)]
+pub struct Plugins {
+    plugins: Vec<Arc<dyn Plugin>>,
+}
+
+impl Plugins {
+    pub fn register(&mut self, plugin: impl Plugin + 'static) {
+        self.plugins.push(Arc::new(plugin));
+    }
+
+    pub fn render_node(&self, ui: &mut Ui, node: &GemtextNode) -> bool {
+        self.plugins
+            .iter()
+            .any(|plugin| plugin.render_node(ui, node))
+    }
+
+    /// Whether gbrowse can load urls with this scheme, by itself or with a
+    /// plugin.
+    pub fn serves(&self, scheme: &str) -> bool {
+        fetch::SCHEMES.contains(&scheme)
+            || self
+                .plugins
+                .iter()
+                .any(|plugin| plugin.schemes().iter().any(|served| served == scheme))
+    }
+}
+
+/// Runs the request hooks of every plugin around another fetcher.
+pub struct PluginFetcher {
+    inner: Arc<dyn Fetcher>,
+    plugins: Plugins,
+}
+
+impl PluginFetcher {
+    pub fn new(inner: Arc<dyn Fetcher>, plugins: Plugins) -> Self {
+        Self { inner, plugins }
+    }
+}
+
+impl Fetcher for PluginFetcher {
+    fn fetch(&self, url: &Url) -> Result<Response, String> {
+        self.open(url)?.into_response()
+    }
+
+    fn open(&self, url: &Url) -> Result<Stream, String> {
+        let mut url = url.clone();
+
+        for plugin in &self.plugins.plugins {
+            if let Some(response) = plugin.before_request(&mut url) {
+                return response.map(Stream::from);
+            }
+        }
+
+        let mut stream = self.inner.open(&url)?;
+        let plugins = self.plugins.clone();
+
+        stream.on_response = Some(Box::new(move |response| {
+            for plugin in &plugins.plugins {
+                plugin.after_response(&url, response);
+            }
+        }));
+
+        Ok(stream)
+    }
+}
+
+/// Prints every request and its status to stdout. Queries are left out
+/// since they may hold sensitive input.
+pub struct LogPlugin;
+
+impl Plugin for LogPlugin {
+    fn before_request(&self, url: &mut Url) -> Option<Result<Response, String>> {
+        println!("going to {}", without_query(url));
+        None
+    }
+
+    fn after_response(&self, url: &Url, response: &mut Response) {
+        println!(
+            "{}: {:?} {}",
+            without_query(url),
+            response.status,
+            response.meta
+        );
+    }
+}
+
+/// A program from `plugins` in the config, so plugins can be added without
+/// rebuilding gbrowse. It's run once for each request or response it asks
+/// for, with `GBROWSE_HOOK`, `GBROWSE_URL` and `GBROWSE_META` set:
+///
+/// - `request`, for urls with one of its `schemes`: it prints a gemini
+///   response, a `20 text/gemini` line and then the body.
+/// - `response`, for successful responses with one of its `filter` MIME
+///   types: it reads the body on stdin and prints the body to show instead.
+///
+/// Drawing nodes needs egui, so only plugins built into gbrowse can do it.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ExternalPlugin {
+    /// The program and its arguments, e.g. `["python3", "/home/me/finger.py"]`.
+    pub command: Vec<String>,
+    /// Schemes the program serves, e.g. `["finger"]`.
+    pub schemes: Vec<String>,
+    /// MIME types of the responses the program filters, e.g. `["text/gemini"]`.
+    pub filter: Vec<String>,
+}
+
+impl ExternalPlugin {
+    /// Run the program for a hook, with `input` on stdin.
+    fn run(&self, hook: &str, url: &Url, meta: &str, input: Vec<u8>) -> Result<Vec<u8>, String> {
+        let (program, args) = self
+            .command
+            .split_first()
+            .ok_or("A plugin in the config has no command")?;
+
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .env("GBROWSE_HOOK", hook)
+            .env("GBROWSE_URL", url.to_string())
+            .env("GBROWSE_META", meta)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+
+            const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+            command.creation_flags(CREATE_NO_WINDOW);
+        }
+
+        let mut child = command
+            .spawn()
+            .map_err(|err| format!("Error running plugin {program}: {err}"))?;
+
+        // written from another thread so a program printing as it reads
+        // doesn't get stuck on a full pipe
+        if let Some(mut stdin) = child.stdin.take() {
+            thread::spawn(move || stdin.write_all(&input));
+        }
+
+        let output = child
+            .wait_with_output()
+            .map_err(|err| format!("Error running plugin {program}: {err}"))?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "Plugin {program} failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(output.stdout)
+    }
+}
+
+impl Plugin for ExternalPlugin {
+    fn before_request(&self, url: &mut Url) -> Option<Result<Response, String>> {
+        let scheme = url.scheme.as_deref().unwrap_or("gemini");
+
+        if !self.schemes.iter().any(|served| served == scheme) {
+            return None;
+        }
+
+        Some(
+            self.run("request", url, "", vec![])
+                .and_then(|output| parse_response(&output)),
+        )
+    }
+
+    fn after_response(&self, url: &Url, response: &mut Response) {
+        let mime = response.meta.split(';').next().unwrap_or_default().trim();
+
+        if !matches!(response.status, StatusCode::Success(_))
+            || response.data.is_empty()
+            || !self.filter.iter().any(|filtered| filtered == mime)
+        {
+            return;
+        }
+
+        match self.run("response", url, &response.meta, response.data.clone()) {
+            Ok(data) => response.data = data,
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+
+    fn schemes(&self) -> Vec<String> {
+        self.schemes.clone()
+    }
+}
+
+/// A gemini response as a plugin printed it.
+fn parse_response(output: &[u8]) -> Result<Response, String> {
+    let end = output
+        .iter()
+        .position(|byte| *byte == b'\n')
+        .unwrap_or(output.len());
+    let header = String::from_utf8_lossy(&output[..end]);
+    let (status, meta) = header.trim_end().split_once(' ').unwrap_or((&header, ""));
+
+    let status: u8 = status
+        .trim()
+        .parse()
+        .map_err(|_| format!("A plugin answered with a bad header: {header}"))?;
+
+    Ok(Response {
+        status: StatusCode::from(status),
+        meta: meta.trim().to_string(),
+        data: output.get(end + 1..).unwrap_or_default().to_vec(),
+    })
+}
+
+fn without_query(url: &Url) -> Url {
+    Url {
+        query: None,
+        ..url.clone()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn shell(script: &str) -> ExternalPlugin {
+        ExternalPlugin {
+            command: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+            ..ExternalPlugin::default()
+        }
+    }
+
+    fn url(url: &str) -> Url {
+        Url::try_from(url).unwrap()
+    }
+
+    #[test]
+    fn serves_its_schemes() {
+        let plugin = ExternalPlugin {
+            schemes: vec!["finger".to_string()],
+            ..shell(r#"printf '20 text/gemini\r\n# %s' "$GBROWSE_URL""#)
+        };
+
+        let response = plugin
+            .before_request(&mut url("finger://a.org/me"))
+            .unwrap()
+            .unwrap();
+
+        assert!(matches!(response.status, StatusCode::Success(_)));
+        assert_eq!(response.meta, "text/gemini");
+        assert_eq!(response.data, b"# finger://a.org/me");
+        assert!(plugin.before_request(&mut url("gemini://a.org/")).is_none());
+
+        let mut plugins = Plugins::default();
+        plugins.register(plugin);
+
+        assert!(plugins.serves("finger"));
+        assert!(plugins.serves("gemini"));
+        assert!(!plugins.serves("https"));
+    }
+
+    #[test]
+    fn filters_its_mime_types() {
+        let plugin = ExternalPlugin {
+            filter: vec!["text/gemini".to_string()],
+            ..shell("tr a-z A-Z")
+        };
+        let response = |meta: &str| Response {
+            status: StatusCode::from(20),
+            meta: meta.to_string(),
+            data: b"# hi".to_vec(),
+        };
+
+        let mut gemtext = response("text/gemini; charset=utf-8");
+        plugin.after_response(&url("gemini://a.org/"), &mut gemtext);
+        assert_eq!(gemtext.data, b"# HI");
+
+        let mut plain = response("text/plain");
+        plugin.after_response(&url("gemini://a.org/"), &mut plain);
+        assert_eq!(plain.data, b"# hi");
+    }
+
+    #[test]
+    fn reports_failures() {
+        let plugin = ExternalPlugin {
+            schemes: vec!["finger".to_string()],
+            ..shell("echo oops >&2; exit 1")
+        };
+
+        let err = plugin
+            .before_request(&mut url("finger://a.org/"))
+            .unwrap()
+            .unwrap_err();
+
+        assert!(err.ends_with("failed: oops"), "{err}");
+        assert!(parse_response(b"hello\r\n").is_err());
+    }
+}
@@ -0,0 +1,154 @@
+//! Loading links before they're followed, to preview them when they're
+//! hovered. What's fetched lands in the cache, so following the link is
+//! instant afterwards.
+
+use gmi::{gemtext::GemtextNode, protocol::StatusCode, url::Url};
+
+use std::{
+    collections::{HashMap, HashSet},
+    str,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::fetch::{self, Fetcher};
+
+/// How much of the first paragraph goes in a preview.
+const SNIPPET_LEN: usize = 200;
+/// Redirects followed before giving up on a preview.
+const MAX_REDIRECTS: usize = 5;
+
+/// The start of a page, shown when a link to it is hovered.
+pub struct Preview {
+    pub title: Option<String>,
+    pub snippet: Option<String>,
+}
+
+impl Preview {
+    fn from_nodes(nodes: &[GemtextNode]) -> Option<Self> {
+        let title = nodes.iter().find_map(|node| match node {
+            GemtextNode::Heading(text)
+            | GemtextNode::SubHeading(text)
+            | GemtextNode::SubSubHeading(text) => Some(text.trim().to_string()),
+            _ => None,
+        });
+
+        let snippet = nodes.iter().find_map(|node| match node {
+            GemtextNode::Text(text) if !text.trim().is_empty() => {
+                let text = text.trim();
+
+                Some(if text.chars().count() > SNIPPET_LEN {
+                    text.chars().take(SNIPPET_LEN - 1).chain(['…']).collect()
+                } else {
+                    text.to_string()
+                })
+            }
+            _ => None,
+        });
+
+        (title.is_some() || snippet.is_some()).then_some(Self { title, snippet })
+    }
+}
+
+pub struct Prefetcher {
+    /// When each link was fetched, and `None` if it isn't a page we can
+    /// preview.
+    previews: HashMap<String, (Instant, Option<Preview>)>,
+    loading: HashSet<String>,
+    tx: Sender<(String, Option<Preview>)>,
+    rx: Receiver<(String, Option<Preview>)>,
+}
+
+impl Prefetcher {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        Self {
+            previews: HashMap::new(),
+            loading: HashSet::new(),
+            tx,
+            rx,
+        }
+    }
+
+    /// Pick up the previews that finished loading.
+    pub fn poll(&mut self) {
+        while let Ok((url, preview)) = self.rx.try_recv() {
+            self.loading.remove(&url);
+            self.previews.insert(url, (Instant::now(), preview));
+        }
+    }
+
+    pub fn in_progress(&self) -> bool {
+        !self.loading.is_empty()
+    }
+
+    /// How many links are being loaded.
+    pub fn active(&self) -> usize {
+        self.loading.len()
+    }
+
+    pub fn is_loading(&self, url: &str) -> bool {
+        self.loading.contains(url)
+    }
+
+    /// Forget what's been fetched, so it's fetched again.
+    pub fn clear(&mut self) {
+        self.previews.clear();
+    }
+
+    pub fn get(&self, url: &str) -> Option<&Preview> {
+        self.previews.get(url)?.1.as_ref()
+    }
+
+    /// Fetch a gemini page in the background, unless it was fetched less
+    /// than `max_age` ago and is still in the cache.
+    pub fn start(&mut self, fetcher: Arc<dyn Fetcher>, url: &str, max_age: Duration) {
+        let fresh = self
+            .previews
+            .get(url)
+            .is_some_and(|(fetched, _)| fetched.elapsed() < max_age);
+
+        if fresh || self.is_loading(url) || !url.starts_with("gemini://") {
+            return;
+        }
+
+        self.loading.insert(url.to_string());
+
+        let tx = self.tx.clone();
+        let url = url.to_string();
+
+        thread::spawn(move || {
+            let preview = fetch_preview(fetcher.as_ref(), &url);
+            tx.send((url, preview)).ok();
+        });
+    }
+}
+
+/// Load a page only if it's text, so hovering a link to a big file doesn't
+/// download it.
+fn fetch_preview(fetcher: &dyn Fetcher, url: &str) -> Option<Preview> {
+    let mut url = Url::try_from(url).ok()?;
+
+    for _ in 0..MAX_REDIRECTS {
+        let stream = fetcher.open(&url).ok()?;
+
+        match stream.status {
+            StatusCode::Redirect(_) => url = Url::try_from(stream.meta.as_str()).ok()?,
+            StatusCode::Success(_) if stream.meta.starts_with("text/") => {
+                let mime = stream.meta.clone();
+                let response = stream.into_response().ok()?;
+                let text = str::from_utf8(&response.data).ok()?;
+
+                return Preview::from_nodes(&fetch::to_gemtext(&mime, text));
+            }
+            _ => return None,
+        }
+    }
+
+    None
+}
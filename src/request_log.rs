@@ -0,0 +1,191 @@
+//! Every request made this session, for debugging capsules or reporting
+//! gbrowse bugs. It's listed on `about:requests` and can be exported as
+//! JSON.
+
+use gmi::{protocol::Response, url::Url};
+use serde::Serialize;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    fetch::{Fetcher, Stream},
+    input,
+    transport::Timing,
+};
+
+/// Older requests are dropped past this many.
+const MAX_ENTRIES: usize = 1000;
+
+#[derive(Serialize, Clone)]
+pub struct Entry {
+    #[serde(skip)]
+    id: u64,
+    /// Seconds since the unix epoch.
+    pub time: u64,
+    /// Without the query, since it may hold sensitive input.
+    pub url: String,
+    pub status: Option<u8>,
+    pub meta: String,
+    /// Bytes in the body, once it's been read. Downloads are streamed to
+    /// disk, so it's `0` for them.
+    pub size: Option<usize>,
+    pub timing: Option<Millis>,
+    pub error: Option<String>,
+}
+
+/// [`Timing`] in milliseconds.
+#[derive(Serialize, Clone)]
+pub struct Millis {
+    pub dns: u128,
+    pub connect: u128,
+    pub tls: Option<u128>,
+    pub first_byte: u128,
+    pub total: Option<u128>,
+}
+
+impl From<&Timing> for Millis {
+    fn from(timing: &Timing) -> Self {
+        Self {
+            dns: timing.dns.as_millis(),
+            connect: timing.connect.as_millis(),
+            tls: timing.tls.map(|tls| tls.as_millis()),
+            first_byte: timing.first_byte.as_millis(),
+            total: timing.total.map(|total| total.as_millis()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Entries {
+    entries: Vec<Entry>,
+    next_id: u64,
+}
+
+/// Clones share the same log.
+#[derive(Clone, Default)]
+pub struct RequestLog {
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl RequestLog {
+    /// Add a request, returning its id to fill in the rest later.
+    fn push(&self, mut entry: Entry) -> u64 {
+        let Ok(mut entries) = self.entries.lock() else {
+            return 0;
+        };
+
+        entry.id = entries.next_id;
+        entries.next_id += 1;
+        entries.entries.push(entry);
+
+        if entries.entries.len() > MAX_ENTRIES {
+            entries.entries.remove(0);
+        }
+
+        entries.next_id - 1
+    }
+
+    fn update(&self, id: u64, update: impl FnOnce(&mut Entry)) {
+        if let Ok(mut entries) = self.entries.lock() {
+            if let Some(entry) = entries
+                .entries
+                .iter_mut()
+                .rev()
+                .find(|entry| entry.id == id)
+            {
+                update(entry);
+            }
+        }
+    }
+
+    pub fn entries(&self) -> Vec<Entry> {
+        self.entries
+            .lock()
+            .map(|entries| entries.entries.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.entries.clear();
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(&self.entries())
+            .map_err(|err| format!("Error serializing the request log: {err}"))
+    }
+}
+
+/// Writes down every request `inner` makes.
+pub struct LoggingFetcher {
+    inner: Arc<dyn Fetcher>,
+    log: RequestLog,
+}
+
+impl LoggingFetcher {
+    pub fn new(inner: Arc<dyn Fetcher>, log: RequestLog) -> Self {
+        Self { inner, log }
+    }
+}
+
+impl Fetcher for LoggingFetcher {
+    fn fetch(&self, url: &Url) -> Result<Response, String> {
+        self.open(url)?.into_response()
+    }
+
+    fn open(&self, url: &Url) -> Result<Stream, String> {
+        let mut entry = Entry {
+            id: 0,
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or(Duration::ZERO)
+                .as_secs(),
+            url: input::without_query(&url.to_string()).to_string(),
+            status: None,
+            meta: String::new(),
+            size: None,
+            timing: None,
+            error: None,
+        };
+
+        let mut stream = match self.inner.open(url) {
+            Ok(stream) => stream,
+            Err(err) => {
+                entry.error = Some(err.clone());
+                self.log.push(entry);
+
+                return Err(err);
+            }
+        };
+
+        entry.status = Some(stream.status.into());
+        entry.meta = stream.meta.clone();
+        entry.timing = stream.timing.as_ref().map(Millis::from);
+
+        let id = self.log.push(entry);
+        let log = self.log.clone();
+        let hook = stream.on_response.take();
+        let mut timing = stream.timing.clone();
+
+        stream.on_response = Some(Box::new(move |response| {
+            if let Some(hook) = hook {
+                hook(response);
+            }
+
+            if let Some(timing) = &mut timing {
+                timing.finish();
+            }
+
+            log.update(id, |entry| {
+                entry.size = Some(response.data.len());
+                entry.timing = timing.as_ref().map(Millis::from);
+            });
+        }));
+
+        Ok(stream)
+    }
+}
@@ -0,0 +1,494 @@
+//! Looking up hosts with a DNS server of your choosing, either over plain
+//! DNS or DNS-over-HTTPS, instead of the system's resolver.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::rand::{SecureRandom, SystemRandom};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, ServerName, StreamOwned};
+
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Where the certificates of trusted authorities usually are on Linux and
+/// the BSDs, for checking DNS-over-HTTPS servers.
+#[cfg(not(any(target_os = "macos", windows)))]
+const CA_BUNDLES: &[&str] = &[
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/ca-bundle.pem",
+    "/etc/ssl/cert.pem",
+];
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Resolver {
+    System,
+    /// A DNS server, asked over UDP.
+    Dns(SocketAddr),
+    /// A DNS-over-HTTPS endpoint.
+    Https(url::Url),
+}
+
+impl Resolver {
+    /// Read a resolver from the config: an address like `9.9.9.9` or
+    /// `[2620:fe::fe]:53`, or an `https://` url.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+
+        if text.starts_with("https://") {
+            let url = url::Url::parse(text).map_err(|err| format!("{text}: {err}"))?;
+
+            return match url.host_str() {
+                Some(_) => Ok(Self::Https(url)),
+                None => Err(format!("{text}: missing host")),
+            };
+        }
+
+        if let Ok(ip) = text.parse::<IpAddr>() {
+            return Ok(Self::Dns(SocketAddr::new(ip, 53)));
+        }
+
+        text.parse()
+            .map(Self::Dns)
+            .map_err(|_| format!("{text} isn't an IP address or an https:// url"))
+    }
+
+    /// The IPv6 and IPv4 addresses of a host.
+    pub fn resolve(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse() {
+            return Ok(vec![ip]);
+        }
+
+        let mut addrs = vec![];
+        let mut last_err = None;
+
+        for qtype in [TYPE_AAAA, TYPE_A] {
+            let answer = match self {
+                Self::System => {
+                    return Ok((host, 0).to_socket_addrs()?.map(|addr| addr.ip()).collect())
+                }
+                Self::Dns(server) => ask_udp(*server, host, qtype),
+                Self::Https(url) => ask_https(url, host, qtype),
+            };
+
+            match answer {
+                Ok(found) => addrs.extend(found),
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        match last_err {
+            Some(err) if addrs.is_empty() => Err(err),
+            _ => Ok(addrs),
+        }
+    }
+}
+
+fn ask_udp(server: SocketAddr, host: &str, qtype: u16) -> io::Result<Vec<IpAddr>> {
+    let local: SocketAddr = if server.is_ipv6() {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    };
+
+    let socket = UdpSocket::bind(local)?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+    socket.connect(server)?;
+
+    // something a forged answer would have to guess
+    let mut id = [0; 2];
+    SystemRandom::new()
+        .fill(&mut id)
+        .map_err(|_| io::Error::other("couldn't pick a random query id"))?;
+
+    let query = query(u16::from_be_bytes(id), host, qtype)?;
+    socket.send(&query)?;
+
+    let started = Instant::now();
+    let mut buf = [0; 4096];
+
+    // skip anything that isn't the answer to this query, stale or forged
+    while started.elapsed() < TIMEOUT {
+        let len = socket.recv(&mut buf)?;
+
+        if answers(&buf[..len], &query) {
+            return parse_answer(&buf[..len], &query);
+        }
+    }
+
+    Err(io::ErrorKind::TimedOut.into())
+}
+
+/// Ask over HTTPS, as in RFC 8484.
+fn ask_https(url: &url::Url, host: &str, qtype: u16) -> io::Result<Vec<IpAddr>> {
+    let server = url.host_str().unwrap_or_default();
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    let tcp = TcpStream::connect((server, port))?;
+    tcp.set_read_timeout(Some(TIMEOUT))?;
+
+    let name = ServerName::try_from(server).map_err(io::Error::other)?;
+    let config = TLS_CONFIG
+        .get_or_init(|| tls_config().map_err(|err| err.to_string()))
+        .clone()
+        .map_err(io::Error::other)?;
+
+    let client = ClientConnection::new(config, name).map_err(io::Error::other)?;
+    let mut stream = StreamOwned::new(client, tcp);
+
+    let query = query(0, host, qtype)?;
+    let path = match url.query() {
+        Some(query) => format!("{}?{query}", url.path()),
+        None => url.path().to_string(),
+    };
+
+    write!(
+        stream,
+        "POST {path} HTTP/1.1\r\n\
+         Host: {server}\r\n\
+         Accept: application/dns-message\r\n\
+         Content-Type: application/dns-message\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        query.len()
+    )?;
+    stream.write_all(&query)?;
+
+    let mut response = vec![];
+
+    // plenty of servers close without a TLS close_notify
+    match stream.read_to_end(&mut response) {
+        Err(err) if err.kind() != io::ErrorKind::UnexpectedEof => return Err(err),
+        _ => {}
+    }
+
+    let split = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| io::Error::other("bad response from the DNS-over-HTTPS server"))?;
+
+    let head = String::from_utf8_lossy(&response[..split]).to_lowercase();
+    let mut body = response[split + 4..].to_vec();
+
+    let status = head.split_whitespace().nth(1).unwrap_or_default();
+
+    if status != "200" {
+        return Err(io::Error::other(format!(
+            "the DNS-over-HTTPS server answered {status}"
+        )));
+    }
+
+    if head.contains("transfer-encoding: chunked") {
+        body = dechunk(&body);
+    }
+
+    parse_answer(&body, &query)
+}
+
+/// Made once and shared, so the session with the server is resumed.
+static TLS_CONFIG: OnceLock<Result<Arc<ClientConfig>, String>> = OnceLock::new();
+
+/// Trust the same authorities as the rest of the system.
+fn tls_config() -> io::Result<Arc<ClientConfig>> {
+    let certs = match env::var("SSL_CERT_FILE") {
+        Ok(path) => pem_certificates(&fs::read_to_string(path)?),
+        Err(_) => system_certificates()?,
+    };
+
+    let mut roots = RootCertStore::empty();
+    let (added, _) = roots.add_parsable_certificates(&certs);
+
+    if added == 0 {
+        return Err(io::Error::other("couldn't find the system's certificates"));
+    }
+
+    Ok(Arc::new(
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+    ))
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn system_certificates() -> io::Result<Vec<Vec<u8>>> {
+    let path = CA_BUNDLES
+        .iter()
+        .find(|path| fs::metadata(path).is_ok())
+        .ok_or_else(|| io::Error::other("couldn't find the system's certificates"))?;
+
+    Ok(pem_certificates(&fs::read_to_string(path)?))
+}
+
+/// The roots in the system keychains, which `security` prints as PEM.
+#[cfg(target_os = "macos")]
+fn system_certificates() -> io::Result<Vec<Vec<u8>>> {
+    let output = std::process::Command::new("/usr/bin/security")
+        .args([
+            "find-certificate",
+            "-a",
+            "-p",
+            "/System/Library/Keychains/SystemRootCertificates.keychain",
+            "/Library/Keychains/System.keychain",
+        ])
+        .output()?;
+
+    Ok(pem_certificates(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// The roots in the machine's and user's certificate stores, which
+/// PowerShell prints as a line of base64 each.
+#[cfg(windows)]
+fn system_certificates() -> io::Result<Vec<Vec<u8>>> {
+    use std::os::windows::process::CommandExt;
+
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    let output = std::process::Command::new("powershell")
+        .args([
+            "-NoProfile",
+            "-NonInteractive",
+            "-Command",
+            "Get-ChildItem Cert:\\LocalMachine\\Root, Cert:\\CurrentUser\\Root \
+             | ForEach-Object { [Convert]::ToBase64String($_.RawData) }",
+        ])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| STANDARD.decode(line.trim()).ok())
+        .collect())
+}
+
+/// The certificates in a PEM file, in DER.
+fn pem_certificates(pem: &str) -> Vec<Vec<u8>> {
+    pem.split("-----BEGIN CERTIFICATE-----")
+        .filter_map(|block| {
+            let (base64, _) = block.split_once("-----END CERTIFICATE-----")?;
+            let base64: String = base64.split_whitespace().collect();
+
+            STANDARD.decode(base64).ok()
+        })
+        .collect()
+}
+
+fn dechunk(mut body: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+
+    while let Some(end) = body.windows(2).position(|window| window == b"\r\n") {
+        let size = String::from_utf8_lossy(&body[..end]);
+        let size = size.split(';').next().unwrap_or_default().trim();
+
+        let Ok(size) = usize::from_str_radix(size, 16) else {
+            break;
+        };
+
+        let start = end + 2;
+
+        if size == 0 || start + size > body.len() {
+            break;
+        }
+
+        out.extend_from_slice(&body[start..start + size]);
+        body = body.get(start + size + 2..).unwrap_or_default();
+    }
+
+    out
+}
+
+/// A DNS question asking for one type of record.
+fn query(id: u16, host: &str, qtype: u16) -> io::Result<Vec<u8>> {
+    let mut query = vec![];
+
+    query.extend(id.to_be_bytes());
+    // a standard query, recursion desired, one question
+    query.extend([0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{host} isn't a valid host name"),
+            ));
+        }
+
+        query.push(label.len() as u8);
+        query.extend(label.as_bytes());
+    }
+
+    query.push(0);
+    query.extend(qtype.to_be_bytes());
+    // class IN
+    query.extend([0, 1]);
+
+    Ok(query)
+}
+
+/// Whether a message is a response with the same id and question as the
+/// query. Names are compared ignoring case, since some servers change it.
+fn answers(message: &[u8], query: &[u8]) -> bool {
+    let question = &query[12..];
+
+    message.get(..2) == query.get(..2)
+        && message.get(2).is_some_and(|flags| flags & 0x80 != 0)
+        && message.get(4..6) == Some(&[0, 1])
+        && message
+            .get(12..12 + question.len())
+            .is_some_and(|asked| asked.eq_ignore_ascii_case(question))
+}
+
+/// The addresses in the answer to a query.
+fn parse_answer(message: &[u8], query: &[u8]) -> io::Result<Vec<IpAddr>> {
+    let bad = || io::Error::other("bad answer from the DNS server");
+    let read_u16 = |at: usize| -> io::Result<u16> {
+        message
+            .get(at..at + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+            .ok_or_else(bad)
+    };
+
+    if !answers(message, query) {
+        return Err(bad());
+    }
+
+    match read_u16(2)? & 0x0f {
+        0 => {}
+        3 => return Ok(vec![]),
+        code => {
+            return Err(io::Error::other(format!(
+                "the DNS server answered with error {code}"
+            )))
+        }
+    }
+
+    let answers = read_u16(6)?;
+    // just past the one question, which is the same as the query's
+    let mut at = query.len();
+    let mut addrs = vec![];
+
+    for _ in 0..answers {
+        at = skip_name(message, at).ok_or_else(bad)?;
+
+        let rtype = read_u16(at)?;
+        let len = read_u16(at + 8)? as usize;
+        let data = message.get(at + 10..at + 10 + len).ok_or_else(bad)?;
+
+        match (rtype, <[u8; 4]>::try_from(data), <[u8; 16]>::try_from(data)) {
+            (TYPE_A, Ok(ip), _) => addrs.push(IpAddr::from(ip)),
+            (TYPE_AAAA, _, Ok(ip)) => addrs.push(IpAddr::from(ip)),
+            // CNAMEs and the like come with the records they point to
+            _ => {}
+        }
+
+        at += 10 + len;
+    }
+
+    Ok(addrs)
+}
+
+/// Where the record after a name starts.
+fn skip_name(message: &[u8], mut at: usize) -> Option<usize> {
+    loop {
+        let len = *message.get(at)?;
+
+        match len {
+            0 => return Some(at + 1),
+            // a pointer to a name earlier in the message
+            len if len & 0xc0 == 0xc0 => return Some(at + 2),
+            len => at += 1 + len as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An answer to `query` with one A record.
+    fn answer(query: &[u8], ip: [u8; 4]) -> Vec<u8> {
+        let mut message = query.to_vec();
+
+        // a response, with one answer
+        message[2] |= 0x80;
+        message[7] = 1;
+        // a pointer to the name in the question, type A, class IN, a ttl
+        message.extend([0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4]);
+        message.extend(ip);
+        message
+    }
+
+    #[test]
+    fn reads_matching_answer() {
+        let asked = query(0x1234, "a.org", TYPE_A).unwrap();
+
+        assert_eq!(
+            parse_answer(&answer(&asked, [1, 2, 3, 4]), &asked).unwrap(),
+            [IpAddr::from([1, 2, 3, 4])]
+        );
+
+        let shouted = query(0x1234, "A.ORG", TYPE_A).unwrap();
+        assert!(answers(&answer(&shouted, [1, 2, 3, 4]), &asked));
+    }
+
+    #[test]
+    fn rejects_answers_to_other_queries() {
+        let asked = query(0x1234, "a.org", TYPE_A).unwrap();
+
+        for other in [
+            query(0x1235, "a.org", TYPE_A).unwrap(),
+            query(0x1234, "b.org", TYPE_A).unwrap(),
+            query(0x1234, "a.org", TYPE_AAAA).unwrap(),
+        ] {
+            assert!(!answers(&answer(&other, [6, 6, 6, 6]), &asked));
+            assert!(parse_answer(&answer(&other, [6, 6, 6, 6]), &asked).is_err());
+        }
+
+        // the query itself isn't an answer
+        assert!(!answers(&asked, &asked));
+        assert!(!answers(&[], &asked));
+    }
+
+    #[test]
+    fn skips_forged_udp_answers() {
+        let server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = server.local_addr().unwrap();
+
+        let serving = std::thread::spawn(move || {
+            let mut buf = [0; 512];
+            let (len, client) = server.recv_from(&mut buf).unwrap();
+            let asked = &buf[..len];
+
+            let mut forged = asked.to_vec();
+            forged[0] ^= 0xff;
+
+            server
+                .send_to(&answer(&forged, [6, 6, 6, 6]), client)
+                .unwrap();
+            server
+                .send_to(&answer(asked, [1, 2, 3, 4]), client)
+                .unwrap();
+        });
+
+        assert_eq!(
+            ask_udp(addr, "a.org", TYPE_A).unwrap(),
+            [IpAddr::from([1, 2, 3, 4])]
+        );
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn reads_pem_certificates() {
+        let pem = "junk\n-----BEGIN CERTIFICATE-----\nAQID\nBA==\n-----END CERTIFICATE-----\n\
+                   -----BEGIN CERTIFICATE-----\nnot base64!\n-----END CERTIFICATE-----\n";
+
+        assert_eq!(pem_certificates(pem), [vec![1, 2, 3, 4]]);
+    }
+}
@@ -0,0 +1,99 @@
+//! Right-to-left text. egui only lays text out left to right, so lines in
+//! Hebrew, Arabic and the like are flipped and right aligned before they're
+//! drawn. egui doesn't shape text either, so Arabic letters aren't joined.
+
+/// Whether text reads right to left, going by its first letter.
+pub fn is_rtl(text: &str) -> bool {
+    text.chars()
+        .find(|c| c.is_alphabetic())
+        .is_some_and(is_rtl_char)
+}
+
+fn is_rtl_char(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF | 0x10800..=0x10FFF | 0x1E800..=0x1EFFF
+    )
+}
+
+/// A word as it's drawn in a right-to-left line. Right-to-left letters are
+/// reversed, while numbers and latin words keep their order.
+pub fn visual(word: &str) -> String {
+    if !word.chars().any(is_rtl_char) {
+        return word.to_string();
+    }
+
+    let mut runs: Vec<(bool, Vec<char>)> = vec![];
+
+    for c in word.chars() {
+        let ltr = c.is_alphanumeric() && !is_rtl_char(c);
+
+        match runs.last_mut() {
+            Some((run_ltr, run)) if *run_ltr == ltr => run.push(c),
+            _ => runs.push((ltr, vec![c])),
+        }
+    }
+
+    runs.iter()
+        .rev()
+        .flat_map(|(ltr, run)| -> Vec<char> {
+            if *ltr {
+                run.clone()
+            } else {
+                run.iter().rev().map(|c| mirror(*c)).collect()
+            }
+        })
+        .collect()
+}
+
+/// Brackets point the other way in right-to-left text.
+fn mirror(c: char) -> char {
+    match c {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '<' => '>',
+        '>' => '<',
+        c => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn goes_by_the_first_letter() {
+        assert!(is_rtl("שלום world"));
+        assert!(is_rtl("2022 — مرحبا"));
+        assert!(!is_rtl("hello שלום"));
+        assert!(!is_rtl("123"));
+        assert!(!is_rtl(""));
+    }
+
+    #[test]
+    fn covers_the_rtl_blocks() {
+        assert!(!is_rtl_char('\u{058F}'));
+        assert!(is_rtl_char('\u{0590}'));
+        assert!(is_rtl_char('\u{08FF}'));
+        assert!(!is_rtl_char('\u{0900}'));
+        assert!(is_rtl_char('\u{FB1D}'));
+        assert!(is_rtl_char('\u{10800}'));
+    }
+
+    #[test]
+    fn reverses_rtl_letters() {
+        assert_eq!(visual("hello"), "hello");
+        assert_eq!(visual("שלום"), "םולש");
+        assert_eq!(visual("(שלום)"), "(םולש)");
+    }
+
+    #[test]
+    fn keeps_numbers_in_order() {
+        assert_eq!(visual("שלום123"), "123םולש");
+        assert_eq!(visual("abשלוםcd"), "cdםולשab");
+    }
+}
@@ -0,0 +1,247 @@
+//! Saves a whole page as a picture, not just the part that fits in the
+//! window. The page is laid out again in an offscreen egui context and the
+//! triangles egui produces are drawn in software.
+
+use eframe::{
+    egui::{self, Color32, FontDefinitions, Pos2, RawInput, Rect, Style, Vec2},
+    epaint::{ClippedPrimitive, ImageData, ImageDelta, Primitive, TextureId, Vertex},
+};
+use gmi::gemtext::GemtextNode;
+
+use std::sync::Arc;
+
+use crate::{
+    plugin::Plugins,
+    view::{GemtextTheme, GemtextView},
+};
+
+/// Longer pages are cut off here, so the image stays a sane size.
+const MAX_HEIGHT: f32 = 30_000.0;
+
+pub struct Screenshot {
+    width: usize,
+    height: usize,
+    /// Premultiplied, but every pixel ends up opaque.
+    pixels: Vec<Color32>,
+}
+
+impl Screenshot {
+    /// Draw a page `width` points wide and as tall as it needs to be.
+    pub fn render(
+        nodes: &[GemtextNode],
+        theme: &GemtextTheme,
+        plugins: &Plugins,
+        fonts: FontDefinitions,
+        style: Arc<Style>,
+        width: f32,
+        pixels_per_point: f32,
+    ) -> Self {
+        let ctx = egui::Context::default();
+        ctx.set_fonts(fonts);
+        ctx.set_style(style);
+
+        let mut font_texture = Texture::default();
+        let mut height = 1.0;
+        let mut shapes = vec![];
+
+        // the first pass measures the page, the second draws all of it
+        for _ in 0..2 {
+            let input = RawInput {
+                screen_rect: Some(Rect::from_min_size(Pos2::ZERO, Vec2::new(width, height))),
+                pixels_per_point: Some(pixels_per_point),
+                ..Default::default()
+            };
+
+            let mut bottom = 0.0;
+
+            let output = ctx.run(input, |ctx| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    bottom = ui
+                        .add(
+                            GemtextView::new(nodes)
+                                .theme(theme.clone())
+                                .plugins(plugins),
+                        )
+                        .rect
+                        .bottom();
+                });
+            });
+
+            for (id, delta) in &output.textures_delta.set {
+                if *id == TextureId::default() {
+                    font_texture.apply(delta);
+                }
+            }
+
+            // leave the same margin at the bottom as the panel has at the top
+            height = (bottom + 8.0).min(MAX_HEIGHT);
+            shapes = output.shapes;
+        }
+
+        let width_px = (width * pixels_per_point).round() as usize;
+        let height_px = (height * pixels_per_point).round() as usize;
+
+        let mut screenshot = Self {
+            width: width_px,
+            height: height_px,
+            pixels: vec![Color32::BLACK; width_px * height_px],
+        };
+
+        for ClippedPrimitive {
+            clip_rect,
+            primitive,
+        } in ctx.tessellate(shapes)
+        {
+            if let Primitive::Mesh(mesh) = primitive {
+                let texture = (mesh.texture_id == TextureId::default()).then_some(&font_texture);
+                let clip_rect = Rect::from_min_max(
+                    (clip_rect.min.to_vec2() * pixels_per_point).to_pos2(),
+                    (clip_rect.max.to_vec2() * pixels_per_point).to_pos2(),
+                );
+
+                for triangle in mesh.indices.chunks_exact(3) {
+                    let [a, b, c] = [0, 1, 2].map(|i| {
+                        let mut vertex = mesh.vertices[triangle[i] as usize];
+                        vertex.pos = (vertex.pos.to_vec2() * pixels_per_point).to_pos2();
+                        vertex
+                    });
+
+                    screenshot.fill_triangle([a, b, c], clip_rect, texture);
+                }
+            }
+        }
+
+        screenshot
+    }
+
+    fn fill_triangle(
+        &mut self,
+        [a, b, c]: [Vertex; 3],
+        clip_rect: Rect,
+        texture: Option<&Texture>,
+    ) {
+        let area = edge(a.pos, b.pos, c.pos);
+
+        if area.abs() < f32::EPSILON {
+            return;
+        }
+
+        // only look at pixels near the triangle
+        let min = a
+            .pos
+            .min(b.pos)
+            .min(c.pos)
+            .max(clip_rect.min)
+            .max(Pos2::ZERO);
+        let max = a.pos.max(b.pos).max(c.pos).min(clip_rect.max);
+
+        let max_x = (max.x.ceil() as usize).min(self.width);
+        let max_y = (max.y.ceil() as usize).min(self.height);
+
+        for y in min.y.floor() as usize..max_y {
+            for x in min.x.floor() as usize..max_x {
+                let p = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+
+                // barycentric coordinates, all positive inside the triangle
+                let wa = edge(b.pos, c.pos, p) / area;
+                let wb = edge(c.pos, a.pos, p) / area;
+                let wc = 1.0 - wa - wb;
+
+                if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                    continue;
+                }
+
+                let uv = a.uv.to_vec2() * wa + b.uv.to_vec2() * wb + c.uv.to_vec2() * wc;
+                let texel = texture.map_or(Color32::WHITE, |texture| texture.sample(uv));
+
+                let channel = |i: usize| {
+                    let color =
+                        a.color[i] as f32 * wa + b.color[i] as f32 * wb + c.color[i] as f32 * wc;
+                    color * texel[i] as f32 / 255.0
+                };
+
+                let source = [channel(0), channel(1), channel(2), channel(3)];
+                let pixel = &mut self.pixels[y * self.width + x];
+
+                // premultiplied "over"
+                let blended = [0, 1, 2, 3].map(|i| {
+                    (source[i] + pixel[i] as f32 * (1.0 - source[3] / 255.0)).round() as u8
+                });
+
+                *pixel = Color32::from_rgba_premultiplied(
+                    blended[0], blended[1], blended[2], blended[3],
+                );
+            }
+        }
+    }
+
+    pub fn to_png(&self) -> Result<Vec<u8>, String> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, self.width as u32, self.height as u32);
+
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let data: Vec<u8> = self
+            .pixels
+            .iter()
+            .flat_map(|pixel| pixel.to_srgba_unmultiplied())
+            .collect();
+
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&data))
+            .map_err(|err| format!("Screenshot Error: {err}"))?;
+
+        Ok(png)
+    }
+}
+
+/// Twice the signed area of the triangle `a`, `b`, `p`.
+fn edge(a: Pos2, b: Pos2, p: Pos2) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+/// A copy of egui's font atlas, built up from the texture updates.
+#[derive(Default)]
+struct Texture {
+    size: [usize; 2],
+    pixels: Vec<Color32>,
+}
+
+impl Texture {
+    fn apply(&mut self, delta: &ImageDelta) {
+        let size = delta.image.size();
+        let pixels: Vec<Color32> = match &delta.image {
+            ImageData::Color(image) => image.pixels.clone(),
+            ImageData::Font(image) => image.srgba_pixels(1.0).collect(),
+        };
+
+        let Some([x, y]) = delta.pos else {
+            self.size = size;
+            self.pixels = pixels;
+            return;
+        };
+
+        for row in 0..size[1] {
+            let start = (y + row) * self.size[0] + x;
+            let end = (start + size[0]).min(self.pixels.len());
+
+            if start < end {
+                self.pixels[start..end]
+                    .copy_from_slice(&pixels[row * size[0]..row * size[0] + end - start]);
+            }
+        }
+    }
+
+    fn sample(&self, uv: Vec2) -> Color32 {
+        if self.pixels.is_empty() {
+            return Color32::WHITE;
+        }
+
+        let x = ((uv.x * self.size[0] as f32) as usize).min(self.size[0] - 1);
+        let y = ((uv.y * self.size[1] as f32) as usize).min(self.size[1] - 1);
+
+        self.pixels[y * self.size[0] + x]
+    }
+}
@@ -0,0 +1,301 @@
+//! `gbrowse serve <dir>`: a Gemini server for a directory, on localhost,
+//! so capsule authors can see their pages the way visitors will before
+//! uploading them. It makes a new self-signed certificate every time.
+
+use ring::{
+    rand::{SecureRandom, SystemRandom},
+    signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+};
+use rustls::{Certificate, PrivateKey, ServerConfig, ServerConnection, StreamOwned};
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use crate::input;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// A url of up to 1024 bytes and the CRLF after it.
+const MAX_REQUEST: u64 = 1026;
+
+/// Serve `dir` in the background, returning the url it's served at.
+pub fn start(dir: &Path, port: u16) -> Result<String, String> {
+    let root = dir
+        .canonicalize()
+        .map_err(|err| format!("Error opening {}: {err}", dir.display()))?;
+
+    if !root.is_dir() {
+        return Err(format!("{} isn't a directory", dir.display()));
+    }
+
+    let (cert, key) = self_signed()?;
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert], key)
+        .map_err(|err| format!("Error making a certificate: {err}"))?;
+    let config = Arc::new(config);
+
+    // fall back to any free port if the usual one is taken
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .or_else(|_| TcpListener::bind((Ipv4Addr::LOCALHOST, 0)))
+        .map_err(|err| format!("Error starting the server: {err}"))?;
+
+    let port = listener
+        .local_addr()
+        .map_err(|err| format!("Error starting the server: {err}"))?
+        .port();
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let config = config.clone();
+            let root = root.clone();
+
+            thread::spawn(move || {
+                if let Err(err) = handle(stream, config, &root) {
+                    eprintln!("Error serving a request: {err}");
+                }
+            });
+        }
+    });
+
+    Ok(format!("gemini://localhost:{port}/"))
+}
+
+fn handle(stream: TcpStream, config: Arc<ServerConfig>, root: &Path) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+
+    let connection = ServerConnection::new(config).map_err(io::Error::other)?;
+    let mut stream = StreamOwned::new(connection, stream);
+
+    let mut request = vec![];
+    BufReader::new((&mut stream).take(MAX_REQUEST)).read_until(b'\n', &mut request)?;
+
+    let request = String::from_utf8_lossy(&request);
+    let (header, body) = respond(root, request.trim());
+
+    eprintln!("{} {header}", request.trim());
+
+    stream.write_all(format!("{header}\r\n").as_bytes())?;
+    stream.write_all(&body)?;
+    stream.conn.send_close_notify();
+    stream.flush()
+}
+
+/// The response header and body for a request.
+fn respond(root: &Path, request: &str) -> (String, Vec<u8>) {
+    let Ok(url) = url::Url::parse(request) else {
+        return ("59 Bad request".to_string(), vec![]);
+    };
+
+    if url.scheme() != "gemini" {
+        return ("53 Only gemini:// urls are served here".to_string(), vec![]);
+    }
+
+    let Some(mut path) = file_path(root, url.path()) else {
+        return ("51 Not found".to_string(), vec![]);
+    };
+
+    if path.is_dir() {
+        if !url.path().ends_with('/') {
+            return (format!("31 {}/", url.path()), vec![]);
+        }
+
+        path.push("index.gmi");
+    }
+
+    match fs::read(&path) {
+        Ok(body) => (format!("20 {}", mime_type(&path)), body),
+        Err(_) => ("51 Not found".to_string(), vec![]),
+    }
+}
+
+/// Where a url's path is under `root`, as long as it doesn't lead out of
+/// it or into hidden files.
+fn file_path(root: &Path, url_path: &str) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+
+    for component in Path::new(&input::decode(url_path)).components() {
+        match component {
+            Component::Normal(name) if !name.to_string_lossy().starts_with('.') => path.push(name),
+            Component::RootDir | Component::CurDir => {}
+            _ => return None,
+        }
+    }
+
+    Some(path)
+}
+
+pub fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+
+    match extension.as_deref() {
+        Some("gmi" | "gemini") => "text/gemini; charset=utf-8",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("md") => "text/markdown; charset=utf-8",
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("mp3") => "audio/mpeg",
+        Some("ogg") => "audio/ogg",
+        Some("flac") => "audio/flac",
+        Some("wav") => "audio/wav",
+        Some("pdf") => "application/pdf",
+        Some("gpub") => "application/gpub+zip",
+        Some("zip") => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+/// A certificate for `localhost`, signed by its own P-256 key.
+fn self_signed() -> Result<(Certificate, PrivateKey), String> {
+    let err = |_| "Error making a certificate".to_string();
+    let rng = SystemRandom::new();
+
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).map_err(err)?;
+    let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref())
+        .map_err(|_| "Error making a certificate".to_string())?;
+
+    let mut serial = [0; 8];
+    rng.fill(&mut serial).map_err(err)?;
+    // positive, and without a leading zero byte
+    serial[0] = serial[0] & 0x7f | 0x40;
+
+    let ecdsa_with_sha256 = der(0x30, &der(0x06, &[0x2a, 0x86, 0x48, 0xce, 0x3d, 4, 3, 2]));
+    let name = der(
+        0x30,
+        &der(
+            0x31,
+            &der(
+                0x30,
+                &[der(0x06, &[0x55, 4, 3]), der(0x0c, b"localhost")].concat(),
+            ),
+        ),
+    );
+    let validity = der(
+        0x30,
+        &[der(0x17, b"200101000000Z"), der(0x17, b"491231235959Z")].concat(),
+    );
+    let public_key = der(
+        0x30,
+        &[
+            der(
+                0x30,
+                &[
+                    // id-ecPublicKey, prime256v1
+                    der(0x06, &[0x2a, 0x86, 0x48, 0xce, 0x3d, 2, 1]),
+                    der(0x06, &[0x2a, 0x86, 0x48, 0xce, 0x3d, 3, 1, 7]),
+                ]
+                .concat(),
+            ),
+            der(0x03, &[&[0], key.public_key().as_ref()].concat()),
+        ]
+        .concat(),
+    );
+    let subject_alt_name = der(
+        0xa3,
+        &der(
+            0x30,
+            &der(
+                0x30,
+                &[
+                    der(0x06, &[0x55, 0x1d, 0x11]),
+                    der(0x04, &der(0x30, &der(0x82, b"localhost"))),
+                ]
+                .concat(),
+            ),
+        ),
+    );
+
+    let tbs = der(
+        0x30,
+        &[
+            // version 3
+            der(0xa0, &der(0x02, &[2])),
+            der(0x02, &serial),
+            ecdsa_with_sha256.clone(),
+            name.clone(),
+            validity,
+            name,
+            public_key,
+            subject_alt_name,
+        ]
+        .concat(),
+    );
+
+    let signature = key.sign(&rng, &tbs).map_err(err)?;
+    let cert = der(
+        0x30,
+        &[
+            tbs,
+            ecdsa_with_sha256,
+            der(0x03, &[&[0], signature.as_ref()].concat()),
+        ]
+        .concat(),
+    );
+
+    Ok((Certificate(cert), PrivateKey(pkcs8.as_ref().to_vec())))
+}
+
+/// A DER value with its tag and length.
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+
+    match len {
+        0..=0x7f => out.push(len as u8),
+        0x80..=0xff => out.extend([0x81, len as u8]),
+        _ => out.extend([0x82, (len >> 8) as u8, len as u8]),
+    }
+
+    out.extend(content);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn served(url_path: &str) -> Option<PathBuf> {
+        file_path(Path::new("/srv/capsule"), url_path)
+    }
+
+    #[test]
+    fn finds_files_under_root() {
+        assert_eq!(served("/"), Some(PathBuf::from("/srv/capsule")));
+        assert_eq!(served(""), Some(PathBuf::from("/srv/capsule")));
+        assert_eq!(
+            served("/dir/./a%20b.gmi"),
+            Some(PathBuf::from("/srv/capsule/dir/a b.gmi"))
+        );
+        assert_eq!(
+            served("/notes..gmi"),
+            Some(PathBuf::from("/srv/capsule/notes..gmi"))
+        );
+    }
+
+    #[test]
+    fn rejects_paths_out_of_root() {
+        assert_eq!(served("/../secret"), None);
+        assert_eq!(served("/dir/../../secret"), None);
+        assert_eq!(served("/dir/%2E%2E/%2e%2e/secret"), None);
+    }
+
+    #[test]
+    fn rejects_hidden_files() {
+        assert_eq!(served("/.git/config"), None);
+        assert_eq!(served("/dir/.env"), None);
+        assert_eq!(served("/dir/%2Ehidden"), None);
+    }
+}
@@ -0,0 +1,116 @@
+//! Sets of open tabs. They're autosaved every so often so a crash or power
+//! loss doesn't lose them, and can be saved under a name to open later.
+//!
+//! The autosave is deleted when gbrowse closes normally, so if it's still
+//! there at startup, gbrowse didn't close normally.
+
+use serde::{Deserialize, Serialize};
+
+use std::{collections::BTreeMap, fs};
+
+use crate::{config, tab::Tab};
+
+const FILE: &str = "session.ron";
+const SESSIONS_FILE: &str = "sessions.ron";
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Session {
+    pub tabs: Vec<SessionTab>,
+    /// The index of the selected tab.
+    pub current: usize,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SessionTab {
+    /// The pages that can be gone back to, ending with the one that was open.
+    pub sites: Vec<String>,
+    pub forward: Vec<String>,
+    pub scroll: f32,
+}
+
+impl Session {
+    /// Everything but the pinned tabs, which are opened again anyway.
+    pub fn from_tabs(tabs: &[Tab], current: usize) -> Self {
+        let unpinned: Vec<&Tab> = tabs.iter().filter(|tab| !tab.pinned).collect();
+
+        Self {
+            tabs: unpinned
+                .iter()
+                .filter(|tab| !tab.sites.is_empty())
+                .map(|tab| SessionTab {
+                    sites: tab.sites.clone(),
+                    forward: tab.forward.clone(),
+                    scroll: tab.scroll,
+                })
+                .collect(),
+            current: unpinned
+                .iter()
+                .position(|tab| tab.id == tabs[current].id)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The session left behind if gbrowse didn't close normally last time.
+    pub fn load_autosaved() -> Option<Self> {
+        let session: Self = config::read_file(FILE);
+
+        (!session.tabs.is_empty()).then_some(session)
+    }
+
+    pub fn autosave(&self) {
+        config::write_file(FILE, self);
+    }
+
+    /// Called when gbrowse closes normally.
+    pub fn remove_autosaved() {
+        let Some(path) = config::config_dir().map(|dir| dir.join(FILE)) else {
+            return;
+        };
+
+        if let Err(err) = fs::remove_file(&path) {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                eprintln!("Error removing {}: {err}", path.display());
+            }
+        }
+    }
+}
+
+/// Sets of tabs saved under a name, like "research", to open again later.
+#[derive(Default)]
+pub struct Sessions {
+    sessions: BTreeMap<String, Session>,
+}
+
+impl Sessions {
+    pub fn load() -> Self {
+        Self {
+            sessions: config::read_file(SESSIONS_FILE),
+        }
+    }
+
+    fn save(&self) {
+        config::write_file(SESSIONS_FILE, &self.sessions);
+    }
+
+    /// Save a session, replacing the one with the same name.
+    pub fn insert(&mut self, name: &str, session: Session) {
+        self.sessions.insert(name.trim().to_string(), session);
+        self.save();
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.sessions.remove(name);
+        self.save();
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Session> {
+        self.sessions.get(name)
+    }
+
+    /// Every session, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Session)> {
+        self.sessions.iter()
+    }
+}
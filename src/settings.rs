@@ -0,0 +1,317 @@
+//! Every key in the config as a flat list, for `about:config`. The config
+//! goes through JSON, so keys can be listed and edited without a hand
+//! written editor for each one.
+//!
+//! Settings changed in gbrowse are kept apart from `config.ron`, as the
+//! [`Change`]s from what it says, so the hand written file is never
+//! rewritten.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::config::Config;
+
+pub struct Setting {
+    /// Dotted for nested settings, like `cache.max_age`.
+    pub key: String,
+    pub value: Value,
+    pub default: Value,
+}
+
+impl Setting {
+    pub fn is_default(&self) -> bool {
+        self.value == self.default
+    }
+}
+
+/// Every setting, sorted by key.
+pub fn settings(config: &Config) -> Vec<Setting> {
+    let (Ok(value), Ok(default)) = (
+        serde_json::to_value(config),
+        serde_json::to_value(Config::default()),
+    ) else {
+        return vec![];
+    };
+
+    let mut settings = vec![];
+    flatten("", &value, &default, &mut settings);
+    settings
+}
+
+/// Settings inside a struct are listed one by one, but maps like `sites`
+/// are edited whole, since they start out empty.
+fn flatten(key: &str, value: &Value, default: &Value, settings: &mut Vec<Setting>) {
+    match default {
+        Value::Object(fields) if !fields.is_empty() => {
+            for (name, default) in fields {
+                let key = match key {
+                    "" => name.clone(),
+                    key => format!("{key}.{name}"),
+                };
+
+                flatten(
+                    &key,
+                    value.get(name).unwrap_or(&Value::Null),
+                    default,
+                    settings,
+                );
+            }
+        }
+        _ => settings.push(Setting {
+            key: key.to_string(),
+            value: value.clone(),
+            default: default.clone(),
+        }),
+    }
+}
+
+/// The config with one setting changed, if the value fits.
+pub fn set(config: &Config, key: &str, new: Value) -> Result<Config, String> {
+    let mut value = serde_json::to_value(config).map_err(|err| err.to_string())?;
+    let mut target = &mut value;
+
+    for name in key.split('.') {
+        target = target
+            .get_mut(name)
+            .ok_or_else(|| format!("{key} isn't a setting"))?;
+    }
+
+    *target = new;
+
+    serde_json::from_value(value).map_err(|err| format!("{key}: {err}"))
+}
+
+/// A setting changed in gbrowse, by its path in the config, like
+/// `["sites", "example.org", "style", "zoom"]`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum Change {
+    Set(Vec<String>, Value),
+    /// An entry taken out of a map, like a search engine.
+    Remove(Vec<String>),
+}
+
+/// What changed from `written` to `config`. Maps are gone into, so
+/// changing one capsule's zoom leaves the rest of `sites` to `config.ron`.
+pub fn changes(mut written: Config, config: &Config) -> Vec<Change> {
+    // capsules first set up in gbrowse are compared with the defaults, so
+    // only what was changed about them is kept
+    for host in config.sites.keys() {
+        written.sites.entry(host.clone()).or_default();
+    }
+
+    let (Ok(written), Ok(config)) = (serde_json::to_value(&written), serde_json::to_value(config))
+    else {
+        return vec![];
+    };
+
+    let mut changes = vec![];
+    diff(&mut vec![], &written, &config, &mut changes);
+    changes
+}
+
+fn diff(path: &mut Vec<String>, written: &Value, value: &Value, changes: &mut Vec<Change>) {
+    let (Value::Object(written), Value::Object(fields)) = (written, value) else {
+        if written != value {
+            changes.push(Change::Set(path.clone(), value.clone()));
+        }
+        return;
+    };
+
+    for (name, value) in fields {
+        path.push(name.clone());
+        diff(
+            path,
+            written.get(name).unwrap_or(&Value::Null),
+            value,
+            changes,
+        );
+        path.pop();
+    }
+
+    for name in written.keys().filter(|name| !fields.contains_key(*name)) {
+        path.push(name.clone());
+        changes.push(Change::Remove(path.clone()));
+        path.pop();
+    }
+}
+
+/// The config with the changes made on top of it. Changes that don't fit
+/// anymore, like ones to a setting that was renamed, are left out.
+pub fn apply(config: Config, changes: &[Change]) -> Config {
+    let Ok(mut value) = serde_json::to_value(&config) else {
+        return config;
+    };
+
+    for change in changes {
+        let mut changed = value.clone();
+
+        let path = match change {
+            Change::Set(path, _) | Change::Remove(path) => path,
+        };
+        let Some((last, parents)) = path.split_last() else {
+            continue;
+        };
+
+        let Some(fields) = parent(&mut changed, parents) else {
+            continue;
+        };
+
+        match change {
+            Change::Set(_, new) => fields.insert(last.clone(), new.clone()),
+            Change::Remove(_) => fields.remove(last),
+        };
+
+        if serde_json::from_value::<Config>(changed.clone()).is_ok() {
+            value = changed;
+        } else {
+            eprintln!("Error applying a saved change to {}", path.join("."));
+        }
+    }
+
+    serde_json::from_value(value).unwrap_or(config)
+}
+
+/// The settings under `path`, which are made on the way if they're missing.
+fn parent<'a>(value: &'a mut Value, path: &[String]) -> Option<&'a mut Map<String, Value>> {
+    path.iter()
+        .try_fold(value, |target, name| {
+            Some(
+                target
+                    .as_object_mut()?
+                    .entry(name.clone())
+                    .or_insert_with(|| Value::Object(Map::new())),
+            )
+        })?
+        .as_object_mut()
+}
+
+/// A setting's value for editing as text. Strings are shown without quotes
+/// and unset options as nothing.
+pub fn to_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// What text typed into a setting could mean, most likely first: JSON, or
+/// else a string. Nothing means unset, or an empty string.
+pub fn from_text(text: &str) -> Vec<Value> {
+    let text = text.trim();
+
+    if text.is_empty() {
+        return vec![Value::Null, Value::String(String::new())];
+    }
+
+    serde_json::from_str(text)
+        .into_iter()
+        .chain([Value::String(text.to_string())])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{MimeAction, SiteSettings};
+
+    fn copy(config: &Config) -> Config {
+        serde_json::from_value(serde_json::to_value(config).unwrap()).unwrap()
+    }
+
+    /// Changes as they'd be read back from `state.ron`.
+    fn saved(written: &Config, config: &Config) -> Vec<Change> {
+        let text = ron::to_string(&changes(copy(written), config)).unwrap();
+        ron::from_str(&text).unwrap()
+    }
+
+    #[test]
+    fn nothing_changed() {
+        assert!(changes(Config::default(), &Config::default()).is_empty());
+    }
+
+    #[test]
+    fn zoom_leaves_rest_of_site_alone() {
+        let mut written = Config::default();
+        written.sites.insert(
+            "a.org".to_string(),
+            SiteSettings {
+                same_host_redirects: true,
+                ..SiteSettings::default()
+            },
+        );
+
+        let mut config = copy(&written);
+        config.sites.get_mut("a.org").unwrap().style.zoom = Some(1.5);
+        config
+            .sites
+            .entry("b.org".to_string())
+            .or_default()
+            .style
+            .zoom = Some(2.0);
+
+        let changes = saved(&written, &config);
+        assert_eq!(changes.len(), 2);
+
+        // b.org is set up in config.ron by hand later on
+        written.sites.insert(
+            "b.org".to_string(),
+            SiteSettings {
+                download: vec!["image/*".to_string()],
+                ..SiteSettings::default()
+            },
+        );
+
+        // config.ron is edited by hand after the zoom was changed
+        written.sites.get_mut("a.org").unwrap().forget_inputs = true;
+
+        let loaded = apply(written, &changes);
+        let a = &loaded.sites["a.org"];
+        assert!(a.same_host_redirects && a.forget_inputs);
+        assert_eq!(a.style.zoom, Some(1.5));
+        assert_eq!(loaded.sites["b.org"].style.zoom, Some(2.0));
+        assert_eq!(loaded.sites["b.org"].download, ["image/*"]);
+    }
+
+    #[test]
+    fn toggles_and_removals() {
+        let mut written = Config {
+            blocked_hosts: vec!["a.org".to_string(), "b.org".to_string()],
+            ..Config::default()
+        };
+        written.mime_types.insert(
+            "video/*".to_string(),
+            MimeAction::Command("mpv".to_string()),
+        );
+        written
+            .mime_types
+            .insert("application/pdf".to_string(), MimeAction::Open);
+
+        let mut config = copy(&written);
+        config.auto_retry = true;
+        config.unblock("a.org");
+        config.mime_types.remove("application/pdf");
+
+        let loaded = apply(copy(&written), &saved(&written, &config));
+
+        assert!(loaded.auto_retry);
+        assert_eq!(loaded.blocked_hosts, ["b.org"]);
+        assert_eq!(loaded.mime_types.keys().collect::<Vec<_>>(), ["video/*"]);
+    }
+
+    #[test]
+    fn skips_changes_that_dont_fit() {
+        let changes = [
+            Change::Set(
+                vec!["auto_retry".to_string()],
+                Value::String("yes".to_string()),
+            ),
+            Change::Set(vec!["show_timing".to_string()], Value::Bool(true)),
+        ];
+
+        let loaded = apply(Config::default(), &changes);
+
+        assert!(!loaded.auto_retry);
+        assert!(loaded.show_timing);
+    }
+}
@@ -0,0 +1,282 @@
+//! Every keyboard shortcut, in one place. The app asks [`pressed`] about an
+//! action instead of checking for keys itself, so the cheat sheet (F1 or
+//! `?`) is made from the same list and can't go out of date.
+
+use eframe::egui::{self, Event, Key, Modifiers};
+
+use crate::i18n::tr;
+
+const COMMAND_SHIFT: Modifiers = Modifiers {
+    shift: true,
+    ..Modifiers::COMMAND
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    NewTab,
+    ReopenTab,
+    CloseTab,
+    NextTab,
+    PreviousTab,
+    SearchTabs,
+    HistoryPanel,
+    BookmarksPanel,
+    Outline,
+    Reload,
+    NextUnread,
+    FocusUrl,
+    Up,
+    Root,
+    FindLink,
+    Top,
+    Bottom,
+    PageUp,
+    PageDown,
+    LineUp,
+    LineDown,
+    NextHeading,
+    PreviousHeading,
+    ResetZoom,
+    ReadingMode,
+    LeaveReadingMode,
+    Help,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Category {
+    Tabs,
+    Navigation,
+    Scrolling,
+    View,
+}
+
+impl Category {
+    pub const ALL: &[Self] = &[Self::Tabs, Self::Navigation, Self::Scrolling, Self::View];
+
+    pub fn name(self) -> String {
+        match self {
+            Self::Tabs => tr("Tabs"),
+            Self::Navigation => tr("Navigation"),
+            Self::Scrolling => tr("Scrolling"),
+            Self::View => tr("View"),
+        }
+    }
+}
+
+impl Action {
+    pub fn category(self) -> Category {
+        match self {
+            Self::NewTab
+            | Self::ReopenTab
+            | Self::CloseTab
+            | Self::NextTab
+            | Self::PreviousTab
+            | Self::SearchTabs => Category::Tabs,
+            Self::Reload
+            | Self::NextUnread
+            | Self::FocusUrl
+            | Self::Up
+            | Self::Root
+            | Self::FindLink => Category::Navigation,
+            Self::Top
+            | Self::Bottom
+            | Self::PageUp
+            | Self::PageDown
+            | Self::LineUp
+            | Self::LineDown
+            | Self::NextHeading
+            | Self::PreviousHeading => Category::Scrolling,
+            Self::ResetZoom
+            | Self::ReadingMode
+            | Self::LeaveReadingMode
+            | Self::HistoryPanel
+            | Self::BookmarksPanel
+            | Self::Outline
+            | Self::Help => Category::View,
+        }
+    }
+
+    pub fn describe(self) -> String {
+        match self {
+            Self::NewTab => tr("New tab"),
+            Self::ReopenTab => tr("Reopen the last closed tab"),
+            Self::CloseTab => tr("Close the tab"),
+            Self::NextTab => tr("Switch to the last used tab"),
+            Self::PreviousTab => tr("Switch tabs the other way"),
+            Self::SearchTabs => tr("Search every tab"),
+            Self::Reload => tr("Reload"),
+            Self::NextUnread => tr("Next unread post from your subscriptions"),
+            Self::FocusUrl => tr("Go to the url bar"),
+            Self::Up => tr("Up a directory"),
+            Self::Root => tr("Capsule root"),
+            Self::FindLink => tr("Find a link by typing part of it"),
+            Self::Top => tr("Top of the page"),
+            Self::Bottom => tr("Bottom of the page"),
+            Self::PageUp => tr("Up a page"),
+            Self::PageDown => tr("Down a page"),
+            Self::LineUp => tr("Scroll up"),
+            Self::LineDown => tr("Scroll down"),
+            Self::NextHeading => tr("Next heading"),
+            Self::PreviousHeading => tr("Previous heading"),
+            Self::ResetZoom => tr("Reset zoom"),
+            Self::ReadingMode => tr("Reading mode"),
+            Self::LeaveReadingMode => tr("Leave reading mode"),
+            Self::HistoryPanel => tr("Show or hide the history"),
+            Self::BookmarksPanel => tr("Show or hide the bookmarks sidebar"),
+            Self::Outline => tr("Show the outline, or hide it"),
+            Self::Help => tr("Keyboard shortcuts"),
+        }
+    }
+}
+
+/// How a shortcut is pressed.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Binding {
+    Key(Modifiers, Key),
+    /// A character, for ones that are on different keys on different
+    /// layouts. Only works when nothing's being typed into.
+    Text(&'static str),
+}
+
+impl Binding {
+    pub fn name(self) -> String {
+        match self {
+            Self::Key(modifiers, key) => {
+                let mut name = String::new();
+
+                if modifiers.command {
+                    name += if cfg!(target_os = "macos") {
+                        "Cmd+"
+                    } else {
+                        "Ctrl+"
+                    };
+                }
+
+                if modifiers.alt {
+                    name += "Alt+";
+                }
+
+                if modifiers.shift {
+                    name += "Shift+";
+                }
+
+                name + &key_name(key)
+            }
+            Self::Text(text) => text.to_string(),
+        }
+    }
+}
+
+/// Actions with more than one binding can be pressed either way.
+pub const SHORTCUTS: &[(Action, Binding)] = &[
+    (Action::NewTab, Binding::Key(Modifiers::COMMAND, Key::T)),
+    (Action::ReopenTab, Binding::Key(COMMAND_SHIFT, Key::T)),
+    (Action::CloseTab, Binding::Key(Modifiers::COMMAND, Key::W)),
+    (Action::NextTab, Binding::Key(Modifiers::COMMAND, Key::Tab)),
+    (Action::PreviousTab, Binding::Key(COMMAND_SHIFT, Key::Tab)),
+    (Action::SearchTabs, Binding::Key(COMMAND_SHIFT, Key::F)),
+    (Action::Reload, Binding::Key(Modifiers::NONE, Key::F5)),
+    (Action::Reload, Binding::Key(Modifiers::COMMAND, Key::R)),
+    (Action::NextUnread, Binding::Key(COMMAND_SHIFT, Key::N)),
+    (Action::FocusUrl, Binding::Key(Modifiers::COMMAND, Key::L)),
+    (Action::Up, Binding::Key(Modifiers::COMMAND, Key::ArrowUp)),
+    (Action::Root, Binding::Key(COMMAND_SHIFT, Key::ArrowUp)),
+    (Action::FindLink, Binding::Text("'")),
+    (Action::Top, Binding::Key(Modifiers::NONE, Key::Home)),
+    (Action::Bottom, Binding::Key(Modifiers::NONE, Key::End)),
+    (Action::PageUp, Binding::Key(Modifiers::NONE, Key::PageUp)),
+    (Action::PageUp, Binding::Key(Modifiers::SHIFT, Key::Space)),
+    (
+        Action::PageDown,
+        Binding::Key(Modifiers::NONE, Key::PageDown),
+    ),
+    (Action::PageDown, Binding::Key(Modifiers::NONE, Key::Space)),
+    (Action::LineUp, Binding::Key(Modifiers::NONE, Key::ArrowUp)),
+    (
+        Action::LineDown,
+        Binding::Key(Modifiers::NONE, Key::ArrowDown),
+    ),
+    (Action::NextHeading, Binding::Text("]")),
+    (Action::PreviousHeading, Binding::Text("[")),
+    (
+        Action::ResetZoom,
+        Binding::Key(Modifiers::COMMAND, Key::Num0),
+    ),
+    (Action::ReadingMode, Binding::Key(Modifiers::NONE, Key::F11)),
+    (
+        Action::LeaveReadingMode,
+        Binding::Key(Modifiers::NONE, Key::Escape),
+    ),
+    (
+        Action::HistoryPanel,
+        Binding::Key(Modifiers::COMMAND, Key::H),
+    ),
+    (
+        Action::BookmarksPanel,
+        Binding::Key(Modifiers::COMMAND, Key::B),
+    ),
+    (Action::Outline, Binding::Key(COMMAND_SHIFT, Key::O)),
+    (Action::Help, Binding::Key(Modifiers::NONE, Key::F1)),
+    (Action::Help, Binding::Text("?")),
+];
+
+/// Whether any of an action's shortcuts were pressed this frame. The key
+/// press is used up, so nothing else reacts to it too.
+pub fn pressed(ctx: &egui::Context, action: Action) -> bool {
+    let typing = ctx.memory().focus().is_some();
+    let mut input = ctx.input_mut();
+    let mut found = false;
+
+    for (_, binding) in SHORTCUTS.iter().filter(|(a, _)| *a == action) {
+        match *binding {
+            Binding::Key(modifiers, key) => found |= input.consume_key(modifiers, key),
+            Binding::Text(text) if !typing => input.events.retain(|event| match event {
+                Event::Text(typed) if typed == text && !found => {
+                    found = true;
+                    false
+                }
+                _ => true,
+            }),
+            Binding::Text(_) => {}
+        }
+    }
+
+    found
+}
+
+/// The ways to press an action, like "F5 / Ctrl+R".
+pub fn bindings(action: Action) -> String {
+    SHORTCUTS
+        .iter()
+        .filter(|(a, _)| *a == action)
+        .map(|(_, binding)| binding.name())
+        .collect::<Vec<_>>()
+        .join(" / ")
+}
+
+/// Each action once, in the order they're listed in.
+pub fn actions(category: Category) -> Vec<Action> {
+    let mut actions: Vec<Action> = vec![];
+
+    for (action, _) in SHORTCUTS {
+        if action.category() == category && !actions.contains(action) {
+            actions.push(*action);
+        }
+    }
+
+    actions
+}
+
+fn key_name(key: Key) -> String {
+    match key {
+        Key::ArrowUp => "↑".to_string(),
+        Key::ArrowDown => "↓".to_string(),
+        Key::ArrowLeft => "←".to_string(),
+        Key::ArrowRight => "→".to_string(),
+        Key::Escape => "Esc".to_string(),
+        Key::PageUp => "Page Up".to_string(),
+        Key::PageDown => "Page Down".to_string(),
+        Key::Num0 => "0".to_string(),
+        key => format!("{key:?}"),
+    }
+}
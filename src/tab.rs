@@ -0,0 +1,260 @@
+use gmi::{gemtext::GemtextNode, url::Url};
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::Sites,
+    downloads::Downloader,
+    fetch::{self, Fetcher, LoadError, Loaded, Page},
+    gpub::Book,
+    input::{self, InputPrompt},
+};
+
+/// Everything about a single open tab.
+pub struct Tab {
+    /// Stays the same when tabs are moved around, unlike the index.
+    pub id: u64,
+    pub url: String,
+    pub sites: Vec<String>,
+    /// Pages that were gone back from, most recent last.
+    pub forward: Vec<String>,
+    /// The pages in `sites` and `forward` as they were left, so going back
+    /// and forward shows them again without loading them.
+    pub snapshots: HashMap<String, Snapshot>,
+    pub page: Option<Page>,
+    pub book: Option<Book>,
+    /// The chapter of the book being read, or `None` for its index.
+    pub chapter: Option<usize>,
+    pub error: Option<LoadError>,
+    pub loading: bool,
+    /// Set when navigation stopped at a blocked host.
+    pub blocked: Option<String>,
+    /// How many automatic retries have been made for the current page.
+    pub retry_attempt: u32,
+    pub retry_at: Option<Instant>,
+    /// Reload the page this often, for status pages and the like. Turned
+    /// off when going to another page.
+    pub auto_refresh: Option<Duration>,
+    /// When the next automatic reload is due.
+    pub refresh_at: Option<Instant>,
+    pub input: Option<InputPrompt>,
+    /// Keep the query of the current page out of sight, since it holds
+    /// sensitive input.
+    pub hide_query: bool,
+    /// Pinned tabs are drawn as a stub, can't be closed and are opened
+    /// again at startup.
+    pub pinned: bool,
+    /// How far down the page is scrolled.
+    pub scroll: f32,
+    /// Scroll here once the page has loaded, when reopening a closed tab.
+    pub scroll_to: Option<f32>,
+    /// Counts up with every request, so the results of ones that were
+    /// replaced or stopped can be told apart and dropped.
+    generation: u64,
+    tx: Sender<(u64, Result<Loaded, LoadError>)>,
+    rx: Receiver<(u64, Result<Loaded, LoadError>)>,
+}
+
+impl Tab {
+    pub fn new(url: String) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        let (tx, rx) = mpsc::channel();
+
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            url,
+            sites: vec![],
+            forward: vec![],
+            snapshots: HashMap::new(),
+            page: None,
+            book: None,
+            chapter: None,
+            error: None,
+            loading: false,
+            blocked: None,
+            retry_attempt: 0,
+            retry_at: None,
+            auto_refresh: None,
+            refresh_at: None,
+            input: None,
+            hide_query: false,
+            pinned: false,
+            scroll: 0.0,
+            scroll_to: None,
+            generation: 0,
+            tx,
+            rx,
+        }
+    }
+
+    /// The first heading of the page, or the host if it doesn't have one.
+    pub fn title(&self) -> String {
+        if let Some(book) = &self.book {
+            return book.title.clone();
+        }
+
+        let heading = self.page.as_ref().and_then(|page| {
+            page.nodes.iter().find_map(|node| match node {
+                GemtextNode::Heading(text)
+                | GemtextNode::SubHeading(text)
+                | GemtextNode::SubSubHeading(text) => Some(text.trim().to_string()),
+                _ => None,
+            })
+        });
+
+        heading
+            .filter(|heading| !heading.is_empty())
+            .or_else(|| self.current_host())
+            .unwrap_or_else(|| self.url.clone())
+    }
+
+    /// What to show in the tab bar. Pinned tabs only get their favicon, or
+    /// a few letters if there isn't one.
+    pub fn label(&self, icon: Option<&str>) -> String {
+        let title = self.title();
+        let max = if self.pinned { 3 } else { 24 };
+
+        let title = if title.chars().count() > max {
+            title.chars().take(max - 1).chain(['…']).collect()
+        } else {
+            title
+        };
+
+        match icon {
+            Some(icon) if self.pinned => icon.to_string(),
+            Some(icon) => format!("{icon} {title}"),
+            None => title,
+        }
+    }
+
+    /// The host of the page being shown.
+    pub fn current_host(&self) -> Option<String> {
+        self.sites
+            .last()
+            .and_then(|site| url::Url::parse(site).ok())
+            .and_then(|site| site.host_str().map(str::to_string))
+    }
+
+    /// Start loading a page on another thread.
+    pub fn request(
+        &mut self,
+        fetcher: Arc<dyn Fetcher>,
+        sites: Sites,
+        downloader: Downloader,
+        url: Url,
+    ) {
+        let tx = self.tx.clone();
+
+        self.generation += 1;
+        self.loading = true;
+
+        let generation = self.generation;
+
+        thread::spawn(move || {
+            let result = fetch::load_page(fetcher.as_ref(), &url, &sites, &downloader);
+
+            // the tab might have been closed in the meantime
+            tx.send((generation, result)).ok();
+        });
+    }
+
+    /// Forget about the request in progress, if there is one, since
+    /// something else is being shown now.
+    pub fn stop_loading(&mut self) {
+        self.generation += 1;
+        self.loading = false;
+    }
+
+    /// Get the result of the last request, if it has finished. Results of
+    /// earlier requests are dropped.
+    pub fn try_recv(&mut self) -> Option<Result<Loaded, LoadError>> {
+        loop {
+            match self.rx.try_recv() {
+                Ok((generation, _)) if generation != self.generation => continue,
+                Ok((_, content)) => {
+                    self.loading = false;
+                    return Some(content);
+                }
+                Err(TryRecvError::Empty) => return None,
+                Err(err) => {
+                    self.loading = false;
+                    return Some(Err(LoadError::Other(format!(
+                        "Error Recieving From Other Thread: {err}"
+                    ))));
+                }
+            }
+        }
+    }
+
+    /// Put the page away as it's being navigated from, so it can be shown
+    /// again when coming back to it.
+    pub fn leave(&mut self) {
+        let Some(page) = self.page.take() else {
+            return;
+        };
+
+        // pages from sensitive input aren't kept around
+        if self.hide_query {
+            return;
+        }
+
+        if let Some(url) = self.sites.last() {
+            self.snapshots.insert(
+                url.clone(),
+                Snapshot {
+                    page,
+                    scroll: self.scroll,
+                },
+            );
+        }
+    }
+
+    /// Drop the snapshots of pages that can't be gone back or forward to
+    /// anymore.
+    pub fn forget_snapshots(&mut self) {
+        let (sites, forward) = (&self.sites, &self.forward);
+
+        self.snapshots
+            .retain(|url, _| sites.contains(url) || forward.contains(url));
+    }
+
+    /// Show where the redirects ended up, leaving out the query if it's
+    /// the answer to a sensitive prompt. Returns the url as it's shown.
+    pub fn show_final_url(&mut self, url: &str) -> String {
+        let url = if self.hide_query {
+            input::without_query(url)
+        } else {
+            url
+        };
+
+        self.url = url.to_string();
+
+        if let Some(site) = self.sites.last_mut() {
+            *site = url.to_string();
+        }
+
+        self.url.clone()
+    }
+}
+
+/// A page that was navigated away from.
+pub struct Snapshot {
+    pub page: Page,
+    pub scroll: f32,
+}
+
+/// What's kept of a closed tab so it can be reopened.
+pub struct ClosedTab {
+    pub url: String,
+    pub scroll: f32,
+}
@@ -0,0 +1,148 @@
+//! Which buttons are in the top bar, and in what order. Back and forward
+//! are always first, and the url bar and window menu can be moved but not
+//! taken out, since the toolbar is changed from the window menu.
+
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::tr;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolbarItem {
+    Home,
+    Reload,
+    /// Up a directory and to the capsule root.
+    Up,
+    Url,
+    PageInfo,
+    Bookmark,
+    Share,
+    AutoRefresh,
+    Bookmarks,
+    Sessions,
+    Window,
+    Downloads,
+    Watched,
+    Subscriptions,
+    History,
+}
+
+impl ToolbarItem {
+    pub const ALL: &[Self] = &[
+        Self::Home,
+        Self::Reload,
+        Self::Up,
+        Self::Url,
+        Self::PageInfo,
+        Self::Bookmark,
+        Self::Share,
+        Self::AutoRefresh,
+        Self::Bookmarks,
+        Self::Sessions,
+        Self::Window,
+        Self::Downloads,
+        Self::Watched,
+        Self::Subscriptions,
+        Self::History,
+    ];
+
+    pub fn name(self) -> String {
+        match self {
+            Self::Home => tr("🏠 Home"),
+            Self::Reload => tr("🔄 Reload"),
+            Self::Up => tr("⬆ Up a directory"),
+            Self::Url => tr("Url bar"),
+            Self::PageInfo => tr("ℹ Page info"),
+            Self::Bookmark => tr("☆ Bookmark this page"),
+            Self::Share => tr("🔗 Share"),
+            Self::AutoRefresh => tr("⏱ Auto refresh"),
+            Self::Bookmarks => tr("📚 Bookmarks"),
+            Self::Sessions => tr("🗂 Sessions"),
+            Self::Window => tr("🗖 Window"),
+            Self::Downloads => tr("📥 Downloads"),
+            Self::Watched => tr("👁 Watched pages"),
+            Self::Subscriptions => tr("📰 Subscriptions"),
+            Self::History => tr("🕘 History"),
+        }
+    }
+
+    /// Whether it can be taken out of the toolbar.
+    pub fn removable(self) -> bool {
+        !matches!(self, Self::Url | Self::Window)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(transparent)]
+pub struct Toolbar {
+    items: Vec<ToolbarItem>,
+}
+
+impl Default for Toolbar {
+    fn default() -> Self {
+        Self {
+            items: vec![
+                ToolbarItem::Reload,
+                ToolbarItem::Up,
+                ToolbarItem::Url,
+                ToolbarItem::PageInfo,
+                ToolbarItem::Bookmark,
+                ToolbarItem::Share,
+                ToolbarItem::AutoRefresh,
+                ToolbarItem::Bookmarks,
+                ToolbarItem::Sessions,
+                ToolbarItem::Watched,
+                ToolbarItem::Subscriptions,
+                ToolbarItem::Window,
+                ToolbarItem::Downloads,
+            ],
+        }
+    }
+}
+
+impl Toolbar {
+    /// The items in order, with the ones that can't be taken out added to
+    /// the end if they're missing from the config.
+    pub fn items(&self) -> Vec<ToolbarItem> {
+        let mut items = self.items.clone();
+
+        for &item in ToolbarItem::ALL {
+            if !item.removable() && !items.contains(&item) {
+                items.push(item);
+            }
+        }
+
+        items
+    }
+
+    pub fn contains(&self, item: ToolbarItem) -> bool {
+        self.items().contains(&item)
+    }
+
+    /// Add an item to the end, or take it out.
+    pub fn toggle(&mut self, item: ToolbarItem) {
+        match self.items.iter().position(|&i| i == item) {
+            Some(index) if item.removable() => {
+                self.items.remove(index);
+            }
+            Some(_) => {}
+            None => self.items.push(item),
+        }
+    }
+
+    /// Move an item one place left (`-1`) or right (`1`).
+    pub fn shift(&mut self, item: ToolbarItem, by: isize) {
+        self.items = self.items();
+
+        let Some(index) = self.items.iter().position(|&i| i == item) else {
+            return;
+        };
+
+        let Some(to) = index.checked_add_signed(by) else {
+            return;
+        };
+
+        if to < self.items.len() {
+            self.items.swap(index, to);
+        }
+    }
+}
@@ -0,0 +1,459 @@
+//! A gemini (and mercury) client that hands back the body as a stream, so
+//! large responses don't have to be held in memory. It can also upload
+//! with Titan, for syncing bookmarks.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use gmi::{protocol::StatusCode, url::Url};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, ClientConfig, ClientConnection, PrivateKey, ServerName, StreamOwned,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{config, dns, history, input};
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    sync::{Arc, Mutex, OnceLock, RwLock},
+    time::{Duration, Instant, SystemTime},
+};
+
+const READ_TIMEOUT: Duration = Duration::from_secs(15);
+const KNOWN_HOSTS_FILE: &str = "known_hosts.ron";
+/// A two digit status, a space, 1024 bytes of meta and CRLF.
+const MAX_HEADER: u64 = 1029;
+
+/// The header of a response, with the body left to be read.
+pub struct Connection {
+    pub status: StatusCode,
+    pub meta: String,
+    pub body: Box<dyn Read + Send>,
+    pub timing: Timing,
+    /// The server's certificate, or `None` for mercury.
+    pub certificate: Option<ServerCertificate>,
+    /// The name of the client certificate we connected with, if any.
+    pub identity: Option<String>,
+}
+
+/// How long each step of a request took.
+#[derive(Clone, Debug)]
+pub struct Timing {
+    started: Instant,
+    /// Looking up the host, which is instant once it's been looked up.
+    pub dns: Duration,
+    pub connect: Duration,
+    /// The TLS handshake, or `None` for mercury.
+    pub tls: Option<Duration>,
+    /// From the start until the header came back.
+    pub first_byte: Duration,
+    /// From the start until the whole body was read, once it has been.
+    pub total: Option<Duration>,
+}
+
+impl Timing {
+    /// Call once the body has been read.
+    pub fn finish(&mut self) {
+        self.total = Some(self.started.elapsed());
+    }
+}
+
+/// The server's certificate, and whether it's the one we saw there before.
+#[derive(Clone, Debug)]
+pub struct ServerCertificate {
+    /// Its SHA-256 hash, as hex pairs split by colons.
+    pub fingerprint: String,
+    pub trust: Trust,
+}
+
+/// Trust on first use: the first certificate a host shows is pinned, and
+/// it should show the same one from then on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trust {
+    /// The first visit to the host, so the certificate was just pinned.
+    New,
+    /// The same certificate as the pinned one.
+    Known,
+    /// A different certificate than the pinned one. It might have been
+    /// renewed, or someone might be listening in.
+    Changed,
+    /// A capsule on this computer, which isn't pinned since `gbrowse
+    /// serve` makes a new certificate every time.
+    Local,
+}
+
+/// A pinned certificate.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct KnownHost {
+    fingerprint: String,
+    /// When it was first seen, in seconds since the epoch.
+    since: u64,
+}
+
+/// The pinned certificates by host, read from `known_hosts.ron` the first
+/// time they're needed.
+static KNOWN_HOSTS: Mutex<Option<HashMap<String, KnownHost>>> = Mutex::new(None);
+
+/// Do something with the pinned certificates, reading them first if they
+/// haven't been yet.
+fn known_hosts<T>(f: impl FnOnce(&mut HashMap<String, KnownHost>) -> T) -> Option<T> {
+    let mut known = KNOWN_HOSTS.lock().ok()?;
+
+    Some(f(
+        known.get_or_insert_with(|| config::read_file(KNOWN_HOSTS_FILE))
+    ))
+}
+
+/// Check a host's certificate against the pinned one, pinning it if there
+/// isn't one yet.
+fn check_certificate(host: &str, fingerprint: String) -> ServerCertificate {
+    if matches!(host, "localhost" | "127.0.0.1" | "::1" | "[::1]") {
+        return ServerCertificate {
+            fingerprint,
+            trust: Trust::Local,
+        };
+    }
+
+    let trust = known_hosts(|known| match known.get(host) {
+        Some(pinned) if pinned.fingerprint == fingerprint => Trust::Known,
+        Some(_) => Trust::Changed,
+        None => {
+            pin(known, host, &fingerprint);
+            Trust::New
+        }
+    });
+
+    ServerCertificate {
+        fingerprint,
+        trust: trust.unwrap_or(Trust::New),
+    }
+}
+
+/// Pin a host's new certificate in place of the old one.
+pub fn trust(host: &str, fingerprint: &str) {
+    known_hosts(|known| pin(known, host, fingerprint));
+}
+
+fn pin(known: &mut HashMap<String, KnownHost>, host: &str, fingerprint: &str) {
+    known.insert(
+        host.to_string(),
+        KnownHost {
+            fingerprint: fingerprint.to_string(),
+            since: history::now(),
+        },
+    );
+    config::write_file(KNOWN_HOSTS_FILE, &*known);
+}
+
+/// How many hosts have a pinned certificate.
+pub fn pinned_count() -> usize {
+    known_hosts(|known| known.len()).unwrap_or_default()
+}
+
+/// Let rustls accept any certificate, since gemini capsules sign their own.
+/// They're checked against the pinned ones after the handshake instead.
+struct AcceptAll;
+
+impl ServerCertVerifier for AcceptAll {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Shared by every connection without an identity, so the TLS session with
+/// a host we've talked to before is resumed, saving a round trip on each
+/// click. Gemini closes the connection after every response, so that's all
+/// that can be reused.
+fn tls_config() -> Arc<ClientConfig> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+
+    CONFIG
+        .get_or_init(|| {
+            Arc::new(
+                ClientConfig::builder()
+                    .with_safe_defaults()
+                    .with_custom_certificate_verifier(Arc::new(AcceptAll))
+                    .with_no_client_auth(),
+            )
+        })
+        .clone()
+}
+
+/// The identity each host is connected to with, by host, and the name it
+/// was loaded from.
+static IDENTITIES: RwLock<Vec<(String, String, Arc<ClientConfig>)>> = RwLock::new(Vec::new());
+
+/// Connect to each host with the identity of that name from the
+/// `identities` folder of the config directory.
+pub fn set_identities(identities: &HashMap<String, String>) {
+    let loaded = identities
+        .iter()
+        .filter_map(|(host, name)| match load_identity(name) {
+            Ok(config) => Some((host.clone(), name.clone(), config)),
+            Err(err) => {
+                eprintln!("Error loading identity {name} for {host}: {err}");
+                None
+            }
+        })
+        .collect();
+
+    if let Ok(mut current) = IDENTITIES.write() {
+        *current = loaded;
+    }
+}
+
+/// Read a client certificate and its private key from one PEM file.
+fn load_identity(name: &str) -> Result<Arc<ClientConfig>, String> {
+    let path = config::config_dir()
+        .ok_or("no config directory")?
+        .join("identities")
+        .join(format!("{name}.pem"));
+
+    let pem = fs::read_to_string(&path)
+        .map_err(|err| format!("Error reading {}: {err}", path.display()))?;
+    let blocks = pem_blocks(&pem);
+
+    let certs: Vec<Certificate> = blocks
+        .iter()
+        .filter(|(label, _)| label == "CERTIFICATE")
+        .map(|(_, der)| Certificate(der.clone()))
+        .collect();
+
+    // PKCS #8, RSA and EC keys are all fine with rustls
+    let key = blocks
+        .into_iter()
+        .find(|(label, _)| label.ends_with("PRIVATE KEY"))
+        .map(|(_, der)| PrivateKey(der))
+        .ok_or_else(|| format!("{} has no private key", path.display()))?;
+
+    if certs.is_empty() {
+        return Err(format!("{} has no certificate", path.display()));
+    }
+
+    ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(AcceptAll))
+        .with_single_cert(certs, key)
+        .map(Arc::new)
+        .map_err(|err| format!("TLS Error: {err}"))
+}
+
+/// The label and contents of each `-----BEGIN ...-----` block in a PEM file.
+fn pem_blocks(pem: &str) -> Vec<(String, Vec<u8>)> {
+    pem.split("-----BEGIN ")
+        .skip(1)
+        .filter_map(|block| {
+            let (label, rest) = block.split_once("-----")?;
+            let (base64, _) = rest.split_once("-----END")?;
+            let base64: String = base64.split_whitespace().collect();
+
+            Some((label.to_string(), STANDARD.decode(base64).ok()?))
+        })
+        .collect()
+}
+
+/// The TLS config for a host, with its identity if it has one.
+fn host_config(host: &str) -> (Arc<ClientConfig>, Option<String>) {
+    let identity = IDENTITIES.read().ok().and_then(|identities| {
+        identities
+            .iter()
+            .find(|(identity_host, ..)| identity_host == host)
+            .map(|(_, name, config)| (config.clone(), Some(name.clone())))
+    });
+
+    identity.unwrap_or_else(|| (tls_config(), None))
+}
+
+/// Send a request and read the response header.
+pub fn connect(url: &Url) -> Result<Connection, String> {
+    let scheme = url.scheme.as_deref().unwrap_or("gemini");
+
+    let port = match scheme {
+        "gemini" => 1965,
+        "mercury" => 1963,
+        scheme => return Err(format!("Unknown scheme {scheme}")),
+    };
+
+    request(
+        &url.authority.host,
+        url.authority.port.unwrap_or(port),
+        scheme == "gemini",
+        &url.to_string(),
+        &[],
+    )
+}
+
+/// Upload `body` to a `titan://` url. Titan servers answer like gemini
+/// ones, usually with a redirect to where the upload can be read.
+pub fn upload(
+    url: &url::Url,
+    mime: &str,
+    token: Option<&str>,
+    body: &[u8],
+) -> Result<Connection, String> {
+    if url.scheme() != "titan" {
+        return Err(format!("{url} isn't a titan:// url"));
+    }
+
+    let host = url.host_str().ok_or_else(|| format!("{url} has no host"))?;
+    let mut line = format!("{url};mime={mime};size={}", body.len());
+
+    if let Some(token) = token {
+        line += &format!(";token={}", input::encode(token));
+    }
+
+    request(host, url.port().unwrap_or(1965), true, &line, body)
+}
+
+/// Send a request line and then `body`, and read the response header.
+fn request(
+    host: &str,
+    port: u16,
+    use_tls: bool,
+    line: &str,
+    body: &[u8],
+) -> Result<Connection, String> {
+    let started = Instant::now();
+
+    dns::lookup(host, port).map_err(|err| format!("DNS error: {err}"))?;
+    let dns = started.elapsed();
+
+    let mut tcp = dns::connect(host, port).map_err(|err| format!("IO error: {err}"))?;
+    let connect = started.elapsed() - dns;
+
+    tcp.set_read_timeout(Some(READ_TIMEOUT))
+        .map_err(|err| format!("IO error: {err}"))?;
+
+    let mut tls = None;
+    let mut certificate = None;
+    let mut identity = None;
+
+    let mut stream: Box<dyn ReadWrite> = if use_tls {
+        let name = ServerName::try_from(host).map_err(|_| "DNS Error".to_string())?;
+        let (config, name_used) = host_config(host);
+        let mut client =
+            ClientConnection::new(config, name).map_err(|err| format!("TLS Error: {err}"))?;
+
+        identity = name_used;
+
+        while client.is_handshaking() {
+            client
+                .complete_io(&mut tcp)
+                .map_err(|err| format!("TLS Error: {err}"))?;
+        }
+
+        tls = Some(started.elapsed() - dns - connect);
+        certificate = client
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .map(|cert| check_certificate(host, fingerprint(cert)));
+
+        Box::new(StreamOwned::new(client, tcp))
+    } else {
+        Box::new(tcp)
+    };
+
+    stream
+        .write_all(format!("{line}\r\n").as_bytes())
+        .and_then(|_| stream.write_all(body))
+        .map_err(|err| format!("IO error: {err}"))?;
+
+    let mut reader = BufReader::new(Lenient(stream));
+    let mut header = Vec::new();
+
+    (&mut reader)
+        .take(MAX_HEADER)
+        .read_until(b'\n', &mut header)
+        .map_err(|err| format!("IO error: {err}"))?;
+
+    let first_byte = started.elapsed();
+    let header = String::from_utf8_lossy(&header);
+    let header = header.trim_end_matches(['\r', '\n']);
+
+    let (status, meta) = header.split_once(' ').unwrap_or((header, ""));
+    let status: u8 = status
+        .parse()
+        .map_err(|_| format!("Response parse error: invalid status {status:?}"))?;
+
+    Ok(Connection {
+        status: StatusCode::from(status),
+        meta: meta.trim().to_string(),
+        body: Box::new(reader),
+        timing: Timing {
+            started,
+            dns,
+            connect,
+            tls,
+            first_byte,
+            total: None,
+        },
+        certificate,
+        identity,
+    })
+}
+
+/// A certificate's SHA-256 hash, as hex pairs split by colons.
+fn fingerprint(cert: &Certificate) -> String {
+    ring::digest::digest(&ring::digest::SHA256, &cert.0)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+trait ReadWrite: Read + Write + Send {}
+
+impl<T: Read + Write + Send> ReadWrite for T {}
+
+/// Lots of servers close the connection without a TLS close_notify, which
+/// is fine for gemini since the end of the body is the end of the
+/// connection.
+struct Lenient(Box<dyn ReadWrite>);
+
+impl Read for Lenient {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(0),
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pins_first_certificate() {
+        let check = |fingerprint: &str| check_certificate("tofu.test", fingerprint.to_string());
+
+        assert_eq!(check("AA").trust, Trust::New);
+        assert_eq!(check("AA").trust, Trust::Known);
+        assert_eq!(check("BB").trust, Trust::Changed);
+        // still changed until it's trusted
+        assert_eq!(check("BB").trust, Trust::Changed);
+
+        trust("tofu.test", "BB");
+
+        assert_eq!(check("BB").trust, Trust::Known);
+        assert_eq!(check("AA").trust, Trust::Changed);
+    }
+
+    #[test]
+    fn doesnt_pin_localhost() {
+        let check = |fingerprint: &str| check_certificate("localhost", fingerprint.to_string());
+
+        assert_eq!(check("AA").trust, Trust::Local);
+        assert_eq!(check("BB").trust, Trust::Local);
+    }
+}
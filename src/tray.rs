@@ -0,0 +1,775 @@
+//! An icon in the system tray, for keeping an eye on subscriptions with the
+//! window out of the way. Clicking it brings the window up, and right
+//! clicking it opens a menu with a new tab, the unread posts and quit.
+//!
+//! Only built with the `tray` feature, and only on Linux, where the icon is
+//! docked into X11 trays with the freedesktop system tray protocol.
+
+#[cfg(all(feature = "tray", target_os = "linux"))]
+pub use x11::Tray;
+
+#[cfg(not(all(feature = "tray", target_os = "linux")))]
+pub use stub::Tray;
+
+/// What was picked from the tray.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(not(all(feature = "tray", target_os = "linux")), allow(dead_code))]
+pub enum Action {
+    /// Bring the window up.
+    Open,
+    NewTab,
+    /// Read a post, by its url.
+    Read(String),
+    Quit,
+}
+
+/// An unread post for the menu, as its title and url.
+pub type Post = (String, String);
+
+#[cfg(all(feature = "tray", target_os = "linux"))]
+mod x11 {
+    use eframe::egui;
+    use x11rb::{
+        connection::Connection,
+        errors::ReplyOrIdError,
+        protocol::{
+            xproto::{
+                Arc as Circle, AtomEnum, BackPixmap, ButtonPressEvent, ChangeGCAux,
+                ChangeWindowAttributesAux, Char2b, ClientMessageEvent, ConnectionExt as _,
+                CreateGCAux, CreateWindowAux, EventMask, Font, Gcontext, GrabMode, PropMode,
+                Rectangle, Screen, Window, WindowClass,
+            },
+            Event,
+        },
+        rust_connection::RustConnection,
+        wrapper::ConnectionExt as _,
+        COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT, CURRENT_TIME, NONE,
+    };
+
+    use std::{
+        sync::{
+            mpsc::{self, Receiver, Sender},
+            Arc, Mutex,
+        },
+        thread,
+    };
+
+    use super::{Action, Post};
+    use crate::i18n::{tr, trf};
+
+    /// How many unread posts the menu lists.
+    const MAX_POSTS: usize = 10;
+    /// Long titles are cut to this many characters.
+    const MAX_TITLE: usize = 60;
+    /// Space around the text in the menu.
+    const PADDING: i16 = 8;
+    /// The height of a line between parts of the menu.
+    const SEPARATOR: i16 = 9;
+    /// From the system tray spec.
+    const SYSTEM_TRAY_REQUEST_DOCK: u32 = 0;
+    /// From the XEmbed spec, so the tray shows the icon once it's docked.
+    const XEMBED_MAPPED: u32 = 1;
+    /// The font for the menu, falling back to "fixed" without unicode.
+    const FONT: &[u8] = b"-misc-fixed-medium-r-normal--13-*-*-*-*-*-iso10646-1";
+
+    const ACCENT: (u32, u32, u32) = (0x3f, 0x7f, 0xbf);
+    const GRAY: (u32, u32, u32) = (0x80, 0x80, 0x80);
+
+    pub struct Tray {
+        actions: Receiver<Action>,
+        posts: Arc<Mutex<Vec<Post>>>,
+    }
+
+    impl Tray {
+        /// Put the icon in the tray, waking up `ctx` when something is picked.
+        pub fn start(ctx: egui::Context) -> Result<Self, String> {
+            let (conn, screen) = RustConnection::connect(None)
+                .map_err(|err| format!("Error connecting to X for the tray icon: {err}"))?;
+
+            let (tx, actions) = mpsc::channel();
+            let posts = Arc::new(Mutex::new(vec![]));
+
+            let icon = Icon::new(conn, screen, tx, ctx, posts.clone())
+                .map_err(|err| format!("Error making the tray icon: {err}"))?;
+
+            thread::spawn(move || {
+                if let Err(err) = icon.run() {
+                    eprintln!("Error in the tray icon: {err}");
+                }
+            });
+
+            Ok(Self { actions, posts })
+        }
+
+        pub fn actions(&self) -> Vec<Action> {
+            self.actions.try_iter().collect()
+        }
+
+        /// The unread posts to list in the menu, oldest first.
+        pub fn set_posts(&self, posts: Vec<Post>) {
+            if let Ok(mut shown) = self.posts.lock() {
+                *shown = posts;
+            }
+        }
+    }
+
+    struct Atoms {
+        /// The selection owned by the tray on our screen.
+        selection: u32,
+        opcode: u32,
+        manager: u32,
+        xembed_info: u32,
+    }
+
+    /// A line in the menu.
+    enum Item {
+        Pick(String, Action),
+        Note(String),
+        Separator,
+    }
+
+    impl Item {
+        fn label(&self) -> &str {
+            match self {
+                Self::Pick(label, _) | Self::Note(label) => label,
+                Self::Separator => "",
+            }
+        }
+
+        fn height(&self, row: i16) -> i16 {
+            match self {
+                Self::Separator => SEPARATOR,
+                _ => row,
+            }
+        }
+    }
+
+    struct Menu {
+        window: Window,
+        items: Vec<Item>,
+        width: u16,
+        hovered: Option<usize>,
+    }
+
+    /// The icon's side of the X connection, run on its own thread.
+    struct Icon {
+        conn: RustConnection,
+        screen: Screen,
+        atoms: Atoms,
+        window: Window,
+        gc: Gcontext,
+        /// The font's ascent, descent and character width.
+        font: (i16, i16, i16),
+        size: (u16, u16),
+        /// The tray the icon is docked in.
+        tray: Window,
+        menu: Option<Menu>,
+        tx: Sender<Action>,
+        ctx: egui::Context,
+        posts: Arc<Mutex<Vec<Post>>>,
+    }
+
+    impl Icon {
+        fn new(
+            conn: RustConnection,
+            screen: usize,
+            tx: Sender<Action>,
+            ctx: egui::Context,
+            posts: Arc<Mutex<Vec<Post>>>,
+        ) -> Result<Self, ReplyOrIdError> {
+            let number = screen;
+            let screen = conn.setup().roots[number].clone();
+
+            let atom = |name: &str| -> Result<u32, ReplyOrIdError> {
+                Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+            };
+
+            let atoms = Atoms {
+                selection: atom(&format!("_NET_SYSTEM_TRAY_S{number}"))?,
+                opcode: atom("_NET_SYSTEM_TRAY_OPCODE")?,
+                manager: atom("MANAGER")?,
+                xembed_info: atom("_XEMBED_INFO")?,
+            };
+
+            let window = conn.generate_id()?;
+            conn.create_window(
+                COPY_DEPTH_FROM_PARENT,
+                window,
+                screen.root,
+                0,
+                0,
+                24,
+                24,
+                0,
+                WindowClass::INPUT_OUTPUT,
+                COPY_FROM_PARENT,
+                &CreateWindowAux::new()
+                    .background_pixmap(BackPixmap::PARENT_RELATIVE)
+                    .event_mask(
+                        EventMask::EXPOSURE | EventMask::BUTTON_PRESS | EventMask::STRUCTURE_NOTIFY,
+                    ),
+            )?;
+
+            conn.change_property8(
+                PropMode::REPLACE,
+                window,
+                AtomEnum::WM_NAME,
+                AtomEnum::STRING,
+                b"gbrowse",
+            )?;
+            conn.change_property8(
+                PropMode::REPLACE,
+                window,
+                AtomEnum::WM_CLASS,
+                AtomEnum::STRING,
+                b"gbrowse\0gbrowse\0",
+            )?;
+            conn.change_property32(
+                PropMode::REPLACE,
+                window,
+                atoms.xembed_info,
+                atoms.xembed_info,
+                &[0, XEMBED_MAPPED],
+            )?;
+
+            // trays announce themselves on the root window when they start
+            conn.change_window_attributes(
+                screen.root,
+                &ChangeWindowAttributesAux::new().event_mask(EventMask::STRUCTURE_NOTIFY),
+            )?;
+
+            let font = open_font(&conn)?;
+            let metrics = conn.query_font(font)?.reply()?;
+
+            let gc = conn.generate_id()?;
+            conn.create_gc(
+                gc,
+                screen.root,
+                &CreateGCAux::new()
+                    .font(font)
+                    .foreground(screen.black_pixel)
+                    .background(screen.white_pixel),
+            )?;
+
+            Ok(Self {
+                conn,
+                screen,
+                atoms,
+                window,
+                gc,
+                font: (
+                    metrics.font_ascent,
+                    metrics.font_descent,
+                    metrics.max_bounds.character_width,
+                ),
+                size: (24, 24),
+                tray: NONE,
+                menu: None,
+                tx,
+                ctx,
+                posts,
+            })
+        }
+
+        fn run(mut self) -> Result<(), ReplyOrIdError> {
+            self.dock()?;
+
+            loop {
+                match self.conn.wait_for_event()? {
+                    Event::Expose(event) if event.count == 0 && event.window == self.window => {
+                        self.draw_icon()?
+                    }
+                    Event::Expose(event) if event.count == 0 => self.draw_menu()?,
+                    Event::ConfigureNotify(event) if event.window == self.window => {
+                        self.size = (event.width, event.height);
+                        self.draw_icon()?
+                    }
+                    Event::ButtonPress(event) if event.event == self.window => match event.detail {
+                        1 => self.send(Action::Open),
+                        2 | 3 => self.open_menu(&event)?,
+                        _ => {}
+                    },
+                    Event::ButtonPress(event) if (1..=3).contains(&event.detail) => {
+                        let picked = self.menu.as_ref().and_then(|menu| {
+                            let inside = event.event_x >= 0 && event.event_x < menu.width as i16;
+
+                            match item_at(&menu.items, event.event_y, self.row()) {
+                                Some(Item::Pick(_, action)) if inside => Some(action.clone()),
+                                _ => None,
+                            }
+                        });
+
+                        self.close_menu()?;
+
+                        if let Some(action) = picked {
+                            self.send(action);
+                        }
+                    }
+                    Event::MotionNotify(event) => {
+                        let row = self.row();
+
+                        if let Some(menu) = &mut self.menu {
+                            let inside = event.event_x >= 0 && event.event_x < menu.width as i16;
+                            let hovered =
+                                index_at(&menu.items, event.event_y, row).filter(|&index| {
+                                    inside && matches!(menu.items[index], Item::Pick(..))
+                                });
+
+                            if hovered != menu.hovered {
+                                menu.hovered = hovered;
+                                self.draw_menu()?;
+                            }
+                        }
+                    }
+                    Event::KeyPress(_) => self.close_menu()?,
+                    // a new tray started
+                    Event::ClientMessage(event)
+                        if event.type_ == self.atoms.manager
+                            && event.data.as_data32()[1] == self.atoms.selection =>
+                    {
+                        self.dock()?
+                    }
+                    // the tray went away, which leaves the icon on the desktop
+                    Event::DestroyNotify(event)
+                        if self.tray != NONE && event.window == self.tray =>
+                    {
+                        self.tray = NONE;
+                        self.conn.unmap_window(self.window)?;
+                    }
+                    _ => {}
+                }
+
+                self.conn.flush()?;
+            }
+        }
+
+        /// Ask the tray to take the icon, if there's a tray.
+        fn dock(&mut self) -> Result<(), ReplyOrIdError> {
+            let owner = self
+                .conn
+                .get_selection_owner(self.atoms.selection)?
+                .reply()?
+                .owner;
+
+            if owner == NONE {
+                return Ok(());
+            }
+
+            self.tray = owner;
+            self.conn.change_window_attributes(
+                owner,
+                &ChangeWindowAttributesAux::new().event_mask(EventMask::STRUCTURE_NOTIFY),
+            )?;
+
+            let request = ClientMessageEvent::new(
+                32,
+                owner,
+                self.atoms.opcode,
+                [CURRENT_TIME, SYSTEM_TRAY_REQUEST_DOCK, self.window, 0, 0],
+            );
+            self.conn
+                .send_event(false, owner, EventMask::NO_EVENT, request)?;
+            self.conn.flush()?;
+
+            Ok(())
+        }
+
+        fn send(&self, action: Action) {
+            self.tx.send(action).ok();
+            self.ctx.request_repaint();
+        }
+
+        /// A color, or black or white on screens with few colors.
+        fn color(&self, (red, green, blue): (u32, u32, u32)) -> u32 {
+            match self.screen.root_depth >= 24 {
+                true => red << 16 | green << 8 | blue,
+                false if red + green + blue > 0x180 => self.screen.white_pixel,
+                false => self.screen.black_pixel,
+            }
+        }
+
+        fn row(&self) -> i16 {
+            let (ascent, descent, _) = self.font;
+            ascent + descent + PADDING
+        }
+
+        /// A "g" in a circle, filling the space the tray gave.
+        fn draw_icon(&self) -> Result<(), ReplyOrIdError> {
+            let (width, height) = self.size;
+            let side = width.min(height).saturating_sub(2);
+            let accent = self.color(ACCENT);
+
+            self.conn.clear_area(false, self.window, 0, 0, 0, 0)?;
+            self.conn.change_gc(
+                self.gc,
+                &ChangeGCAux::new().foreground(accent).background(accent),
+            )?;
+            self.conn.poly_fill_arc(
+                self.window,
+                self.gc,
+                &[Circle {
+                    x: ((width - side) / 2) as i16,
+                    y: ((height - side) / 2) as i16,
+                    width: side,
+                    height: side,
+                    angle1: 0,
+                    angle2: 360 * 64,
+                }],
+            )?;
+
+            let (ascent, descent, char_width) = self.font;
+            self.conn.change_gc(
+                self.gc,
+                &ChangeGCAux::new().foreground(self.screen.white_pixel),
+            )?;
+            self.conn.image_text16(
+                self.window,
+                self.gc,
+                (width as i16 - char_width) / 2,
+                (height as i16 + ascent - descent) / 2,
+                &chars("g"),
+            )?;
+
+            Ok(())
+        }
+
+        fn open_menu(&mut self, press: &ButtonPressEvent) -> Result<(), ReplyOrIdError> {
+            self.close_menu()?;
+
+            let posts = self
+                .posts
+                .lock()
+                .map(|posts| posts.clone())
+                .unwrap_or_default();
+            let items = items(&posts);
+
+            let (_, _, char_width) = self.font;
+            let longest = items.iter().map(|item| item.label().chars().count()).max();
+            let width = longest.unwrap_or(0) as i16 * char_width + PADDING * 2;
+            let height = items
+                .iter()
+                .map(|item| item.height(self.row()))
+                .sum::<i16>();
+
+            // keep it on the screen, above the icon if the tray is at the bottom
+            let screen_width = self.screen.width_in_pixels as i16;
+            let screen_height = self.screen.height_in_pixels as i16;
+            let x = press.root_x.min(screen_width - width).max(0);
+            let y = match press.root_y + height > screen_height {
+                true => press.root_y - height,
+                false => press.root_y,
+            }
+            .max(0);
+
+            let window = self.conn.generate_id()?;
+            self.conn.create_window(
+                COPY_DEPTH_FROM_PARENT,
+                window,
+                self.screen.root,
+                x,
+                y,
+                width as u16,
+                height as u16,
+                1,
+                WindowClass::INPUT_OUTPUT,
+                COPY_FROM_PARENT,
+                &CreateWindowAux::new()
+                    .override_redirect(1)
+                    .background_pixel(self.screen.white_pixel)
+                    .border_pixel(self.color(GRAY))
+                    .event_mask(
+                        EventMask::EXPOSURE
+                            | EventMask::BUTTON_PRESS
+                            | EventMask::POINTER_MOTION
+                            | EventMask::KEY_PRESS,
+                    ),
+            )?;
+            self.conn.map_window(window)?;
+
+            // take every click and key, so the menu closes when clicking elsewhere
+            let mask = u32::from(EventMask::BUTTON_PRESS | EventMask::POINTER_MOTION) as u16;
+            self.conn.grab_pointer(
+                false,
+                window,
+                mask,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+                NONE,
+                NONE,
+                CURRENT_TIME,
+            )?;
+            self.conn.grab_keyboard(
+                false,
+                window,
+                CURRENT_TIME,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+            )?;
+
+            self.menu = Some(Menu {
+                window,
+                items,
+                width: width as u16,
+                hovered: None,
+            });
+
+            Ok(())
+        }
+
+        fn close_menu(&mut self) -> Result<(), ReplyOrIdError> {
+            if let Some(menu) = self.menu.take() {
+                self.conn.ungrab_pointer(CURRENT_TIME)?;
+                self.conn.ungrab_keyboard(CURRENT_TIME)?;
+                self.conn.destroy_window(menu.window)?;
+            }
+
+            Ok(())
+        }
+
+        fn draw_menu(&self) -> Result<(), ReplyOrIdError> {
+            let menu = match &self.menu {
+                Some(menu) => menu,
+                None => return Ok(()),
+            };
+
+            let (ascent, descent, _) = self.font;
+            let white = self.screen.white_pixel;
+            let mut top = 0;
+
+            self.conn.clear_area(false, menu.window, 0, 0, 0, 0)?;
+
+            for (index, item) in menu.items.iter().enumerate() {
+                let height = item.height(self.row());
+
+                let (foreground, background) = match item {
+                    _ if menu.hovered == Some(index) => (white, self.color(ACCENT)),
+                    Item::Pick(..) => (self.screen.black_pixel, white),
+                    Item::Note(_) | Item::Separator => (self.color(GRAY), white),
+                };
+
+                self.conn.change_gc(
+                    self.gc,
+                    &ChangeGCAux::new()
+                        .foreground(background)
+                        .background(background),
+                )?;
+                self.conn.poly_fill_rectangle(
+                    menu.window,
+                    self.gc,
+                    &[Rectangle {
+                        x: 0,
+                        y: top,
+                        width: menu.width,
+                        height: height as u16,
+                    }],
+                )?;
+                self.conn
+                    .change_gc(self.gc, &ChangeGCAux::new().foreground(foreground))?;
+
+                match item {
+                    Item::Separator => self.conn.poly_fill_rectangle(
+                        menu.window,
+                        self.gc,
+                        &[Rectangle {
+                            x: PADDING,
+                            y: top + height / 2,
+                            width: menu.width.saturating_sub(PADDING as u16 * 2),
+                            height: 1,
+                        }],
+                    )?,
+                    _ => self.conn.image_text16(
+                        menu.window,
+                        self.gc,
+                        PADDING,
+                        top + (height + ascent - descent) / 2,
+                        &chars(item.label()),
+                    )?,
+                };
+
+                top += height;
+            }
+
+            Ok(())
+        }
+    }
+
+    fn open_font(conn: &RustConnection) -> Result<Font, ReplyOrIdError> {
+        let font = conn.generate_id()?;
+
+        if conn.open_font(font, FONT)?.check().is_err() {
+            conn.open_font(font, b"fixed")?.check()?;
+        }
+
+        Ok(font)
+    }
+
+    /// What the menu shows for these posts.
+    fn items(posts: &[Post]) -> Vec<Item> {
+        let mut items = vec![
+            Item::Pick(tr("Open gbrowse"), Action::Open),
+            Item::Pick(tr("New tab"), Action::NewTab),
+            Item::Separator,
+        ];
+
+        if posts.is_empty() {
+            items.push(Item::Note(tr("No unread posts")));
+        }
+
+        items.extend(posts.iter().take(MAX_POSTS).map(|(title, url)| {
+            let title = if title.trim().is_empty() { url } else { title };
+            Item::Pick(shorten(title), Action::Read(url.clone()))
+        }));
+
+        if posts.len() > MAX_POSTS {
+            let count = posts.len() - MAX_POSTS;
+            items.push(Item::Note(trf("{count} more unread", &[("count", &count)])));
+        }
+
+        items.push(Item::Separator);
+        items.push(Item::Pick(tr("Quit"), Action::Quit));
+        items
+    }
+
+    fn shorten(title: &str) -> String {
+        match title.chars().count() > MAX_TITLE {
+            true => title.chars().take(MAX_TITLE - 1).chain(['…']).collect(),
+            false => title.to_string(),
+        }
+    }
+
+    /// The index of the item `y` pixels down the menu.
+    fn index_at(items: &[Item], y: i16, row: i16) -> Option<usize> {
+        let mut top = 0;
+
+        for (index, item) in items.iter().enumerate() {
+            let bottom = top + item.height(row);
+
+            if y >= top && y < bottom {
+                return Some(index);
+            }
+
+            top = bottom;
+        }
+
+        None
+    }
+
+    fn item_at(items: &[Item], y: i16, row: i16) -> Option<&Item> {
+        index_at(items, y, row).map(|index| &items[index])
+    }
+
+    /// Text as X wants it for 16 bit fonts. Core fonts only reach the basic
+    /// multilingual plane, and draw up to 255 characters at once.
+    fn chars(text: &str) -> Vec<Char2b> {
+        text.encode_utf16()
+            .take(255)
+            .map(|unit| Char2b {
+                byte1: (unit >> 8) as u8,
+                byte2: unit as u8,
+            })
+            .collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn post(title: &str, url: &str) -> Post {
+            (title.to_string(), url.to_string())
+        }
+
+        #[test]
+        fn lists_unread_posts_between_the_actions() {
+            let items = items(&[post("First", "gemini://a/1"), post("", "gemini://a/2")]);
+            let labels: Vec<&str> = items.iter().map(Item::label).collect();
+
+            assert_eq!(
+                labels,
+                [
+                    "Open gbrowse",
+                    "New tab",
+                    "",
+                    "First",
+                    "gemini://a/2",
+                    "",
+                    "Quit"
+                ]
+            );
+            assert!(matches!(&items[4], Item::Pick(_, Action::Read(url)) if url == "gemini://a/2"));
+        }
+
+        #[test]
+        fn says_when_nothing_is_unread() {
+            let items = items(&[]);
+
+            assert!(matches!(&items[3], Item::Note(note) if note == "No unread posts"));
+        }
+
+        #[test]
+        fn cuts_long_lists_and_titles() {
+            let posts: Vec<Post> = (0..15)
+                .map(|n| post(&"x".repeat(100), &format!("gemini://a/{n}")))
+                .collect();
+            let items = items(&posts);
+
+            assert_eq!(items.len(), 3 + MAX_POSTS + 3);
+            assert_eq!(items[3].label().chars().count(), MAX_TITLE);
+            assert_eq!(items[3 + MAX_POSTS].label(), "5 more unread");
+        }
+
+        #[test]
+        fn finds_the_item_under_the_pointer() {
+            let items = items(&[post("First", "gemini://a/1")]);
+
+            // rows are 20 pixels, separators are shorter
+            assert_eq!(index_at(&items, 0, 20), Some(0));
+            assert_eq!(index_at(&items, 39, 20), Some(1));
+            assert_eq!(index_at(&items, 40, 20), Some(2));
+            assert_eq!(index_at(&items, 40 + SEPARATOR, 20), Some(3));
+            assert_eq!(index_at(&items, -1, 20), None);
+            assert_eq!(index_at(&items, 1000, 20), None);
+        }
+
+        #[test]
+        fn encodes_text_for_x() {
+            assert_eq!(
+                chars("gü"),
+                [
+                    Char2b {
+                        byte1: 0,
+                        byte2: b'g'
+                    },
+                    Char2b {
+                        byte1: 0,
+                        byte2: 0xfc
+                    }
+                ]
+            );
+        }
+    }
+}
+
+#[cfg(not(all(feature = "tray", target_os = "linux")))]
+mod stub {
+    use eframe::egui;
+
+    use super::{Action, Post};
+
+    /// Stands in for the tray icon when gbrowse is built without it.
+    pub struct Tray;
+
+    impl Tray {
+        pub fn start(_ctx: egui::Context) -> Result<Self, String> {
+            match cfg!(target_os = "linux") {
+                true => Err("gbrowse was built without the tray feature".to_string()),
+                false => Err("the tray icon is only supported on Linux".to_string()),
+            }
+        }
+
+        pub fn actions(&self) -> Vec<Action> {
+            vec![]
+        }
+
+        pub fn set_posts(&self, _posts: Vec<Post>) {}
+    }
+}
@@ -0,0 +1,462 @@
+use eframe::{
+    egui::{
+        containers::Frame, style::Margin, widget_text::WidgetTextGalley, FontId, Label, Pos2,
+        Response, RichText, Sense, TextStyle, Ui, Vec2, Widget, WidgetInfo, WidgetText, WidgetType,
+    },
+    epaint::Color32,
+};
+use gmi::gemtext::GemtextNode;
+
+use std::sync::Arc;
+
+use crate::{fonts, justify, nav, plugin::Plugins, rtl};
+
+/// Sizes and colors used when drawing gemtext.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GemtextTheme {
+    pub heading_size: f32,
+    pub sub_heading_size: f32,
+    pub sub_sub_heading_size: f32,
+    /// `None` uses the size from the egui style.
+    pub text_size: Option<f32>,
+    /// `None` uses the color from the egui style.
+    pub text_color: Option<Color32>,
+    pub link_color: Option<Color32>,
+    pub quote_indent: f32,
+    pub empty_line_height: f32,
+    /// Extra space between the lines of a paragraph.
+    pub line_spacing: f32,
+    /// Space between paragraphs, links and other lines of gemtext. `None`
+    /// uses the spacing from the egui style.
+    pub paragraph_spacing: Option<f32>,
+    /// Line paragraphs up with both edges, hyphenating long words.
+    pub justify: bool,
+    /// Limit the width of the content column.
+    pub content_width: Option<f32>,
+    /// Scale factor for everything on the page.
+    pub zoom: f32,
+}
+
+impl Default for GemtextTheme {
+    fn default() -> Self {
+        Self {
+            heading_size: 30.0,
+            sub_heading_size: 25.0,
+            sub_sub_heading_size: 20.0,
+            text_size: None,
+            text_color: None,
+            link_color: None,
+            quote_indent: 15.0,
+            empty_line_height: 10.0,
+            line_spacing: 0.0,
+            paragraph_spacing: None,
+            justify: false,
+            content_width: None,
+            zoom: 1.0,
+        }
+    }
+}
+
+type LinkCallback<'a> = Box<dyn FnMut(&str) + 'a>;
+type ContextMenuCallback<'a> = Box<dyn FnMut(&mut Ui, &GemtextNode) + 'a>;
+type LinkTooltipCallback<'a> = Box<dyn FnMut(&mut Ui, &str) + 'a>;
+
+/// A widget that draws a list of gemtext nodes.
+///
+/// Clicking a link calls the link callback with the url exactly as it
+/// appears in the document, it's up to the caller to resolve it.
+pub struct GemtextView<'a> {
+    nodes: &'a [GemtextNode],
+    theme: GemtextTheme,
+    plugins: Option<&'a Plugins>,
+    on_link: Option<LinkCallback<'a>>,
+    context_menu: Option<ContextMenuCallback<'a>>,
+    link_tooltip: Option<LinkTooltipCallback<'a>>,
+    base_url: Option<&'a str>,
+    highlight: Option<usize>,
+    headings: Option<&'a mut Vec<(usize, f32)>>,
+}
+
+impl<'a> GemtextView<'a> {
+    pub fn new(nodes: &'a [GemtextNode]) -> Self {
+        Self {
+            nodes,
+            theme: GemtextTheme::default(),
+            plugins: None,
+            on_link: None,
+            context_menu: None,
+            link_tooltip: None,
+            base_url: None,
+            highlight: None,
+            headings: None,
+        }
+    }
+
+    pub fn theme(mut self, theme: GemtextTheme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Let plugins draw nodes before the default renderer.
+    pub fn plugins(mut self, plugins: &'a Plugins) -> Self {
+        self.plugins = Some(plugins);
+        self
+    }
+
+    pub fn on_link(mut self, on_link: impl FnMut(&str) + 'a) -> Self {
+        self.on_link = Some(Box::new(on_link));
+        self
+    }
+
+    /// The url of the page, so links can show where they go when hovered.
+    pub fn base_url(mut self, base_url: &'a str) -> Self {
+        self.base_url = Some(base_url);
+        self
+    }
+
+    /// Fill in the tooltip of a hovered link, given where it goes. The
+    /// tooltip is just the url otherwise.
+    pub fn link_tooltip(mut self, link_tooltip: impl FnMut(&mut Ui, &str) + 'a) -> Self {
+        self.link_tooltip = Some(Box::new(link_tooltip));
+        self
+    }
+
+    /// Ring the node at this index and scroll to it, like a found link.
+    pub fn highlight(mut self, highlight: Option<usize>) -> Self {
+        self.highlight = highlight;
+        self
+    }
+
+    /// Write down where each heading was drawn, as its node's index and how
+    /// far it is from the top of the page.
+    pub fn headings(mut self, headings: &'a mut Vec<(usize, f32)>) -> Self {
+        self.headings = Some(headings);
+        self
+    }
+
+    /// Fill in the menu shown when a node is right clicked.
+    pub fn context_menu(mut self, context_menu: impl FnMut(&mut Ui, &GemtextNode) + 'a) -> Self {
+        self.context_menu = Some(Box::new(context_menu));
+        self
+    }
+
+    /// Text only needs to be clickable if it has a context menu. Tab only
+    /// stops at links.
+    fn sense(&self) -> Sense {
+        match self.context_menu {
+            Some(_) => Sense::click(),
+            None => Sense::hover(),
+        }
+    }
+
+    /// What egui's text to speech says when a link is focused: its label
+    /// and where it goes.
+    fn describe_link(&self, url: &str, label: Option<&str>) -> WidgetInfo {
+        let target = self
+            .base_url
+            .and_then(|base| nav::resolve(base, url))
+            .unwrap_or_else(|| url.to_string());
+
+        let label = match label {
+            Some(label) => format!("{label}, {target}"),
+            None => target,
+        };
+
+        WidgetInfo::labeled(WidgetType::Link, label)
+    }
+
+    fn label(&self, ui: &mut Ui, text: RichText) -> Response {
+        let sense = self.sense();
+
+        if self.theme.line_spacing > 0.0 {
+            return self.spaced_label(ui, text, sense);
+        }
+
+        ui.add(Label::new(text).sense(sense))
+    }
+
+    /// A label with extra space between its lines, which egui can't do by
+    /// itself. The text is laid out as usual and then each line is moved down.
+    fn spaced_label(&self, ui: &mut Ui, text: RichText, sense: Sense) -> Response {
+        let extra = self.theme.line_spacing * self.theme.zoom;
+        let text =
+            WidgetText::from(text).into_galley(ui, None, ui.available_width(), TextStyle::Body);
+
+        let mut galley = (*text.galley).clone();
+
+        for (i, row) in galley.rows.iter_mut().enumerate() {
+            let offset = Vec2::new(0.0, extra * i as f32);
+
+            row.rect = row.rect.translate(offset);
+            row.visuals.mesh.translate(offset);
+            row.visuals.mesh_bounds = row.visuals.mesh_bounds.translate(offset);
+
+            for glyph in &mut row.glyphs {
+                glyph.pos += offset;
+            }
+        }
+
+        let grow = extra * galley.rows.len().saturating_sub(1) as f32;
+        galley.rect.max.y += grow;
+        galley.mesh_bounds.max.y += grow;
+
+        let (rect, response) = ui.allocate_exact_size(galley.size(), sense);
+        let color = ui.style().interact(&response).text_color();
+
+        WidgetTextGalley {
+            galley: Arc::new(galley),
+            galley_has_color: text.galley_has_color,
+        }
+        .paint_with_fallback_color(ui.painter(), rect.min, color);
+
+        response
+    }
+
+    fn body_font(&self, ui: &Ui) -> FontId {
+        let size = match self.theme.text_size {
+            Some(size) => size * self.theme.zoom,
+            None => ui.style().text_styles[&TextStyle::Body].size,
+        };
+
+        FontId::new(size, fonts::body())
+    }
+
+    fn heading_font(&self, size: f32) -> FontId {
+        FontId::new(size * self.theme.zoom, fonts::heading())
+    }
+
+    /// Text laid out word by word, so lines can be stretched to fill the
+    /// width or flipped for right-to-left text.
+    fn word_label(&self, ui: &mut Ui, text: &str, font_id: FontId, justify: bool) -> Response {
+        let rtl = rtl::is_rtl(text);
+        let color = self
+            .theme
+            .text_color
+            .unwrap_or_else(|| ui.visuals().text_color());
+
+        let width = ui.available_width();
+        let line_spacing = self.theme.line_spacing * self.theme.zoom;
+        let space = ui.fonts().glyph_width(&font_id, ' ');
+        let row_height = ui.fonts().row_height(&font_id) + line_spacing;
+
+        let lines = justify::lines(text, width, space, |word| {
+            ui.fonts()
+                .layout_no_wrap(word.to_string(), font_id.clone(), color)
+                .size()
+                .x
+        });
+
+        let height = row_height * lines.len() as f32 - line_spacing;
+        let (rect, response) = ui.allocate_exact_size(Vec2::new(width, height), self.sense());
+
+        for (i, line) in lines.iter().enumerate() {
+            let words: f32 = line.iter().map(|word| word.width).sum();
+
+            // the last line of a paragraph isn't stretched
+            let gap = if justify && i + 1 < lines.len() && line.len() > 1 {
+                (width - words) / (line.len() - 1) as f32
+            } else {
+                space
+            };
+
+            let y = rect.top() + row_height * i as f32;
+            let mut x = if rtl {
+                rect.right() - words - gap * line.len().saturating_sub(1) as f32
+            } else {
+                rect.left()
+            };
+
+            // right-to-left lines are drawn from their last word
+            let words: Vec<_> = if rtl {
+                line.iter().rev().collect()
+            } else {
+                line.iter().collect()
+            };
+
+            for word in words {
+                let text = if rtl {
+                    rtl::visual(&word.text)
+                } else {
+                    word.text.clone()
+                };
+
+                let galley = ui.fonts().layout_no_wrap(text, font_id.clone(), color);
+
+                ui.painter().galley(Pos2::new(x, y), galley);
+                x += word.width + gap;
+            }
+        }
+
+        response
+    }
+
+    fn text(&self, text: impl Into<String>) -> RichText {
+        match self.theme.text_color {
+            Some(color) => self.sized(text).color(color),
+            None => self.sized(text),
+        }
+    }
+
+    fn sized(&self, text: impl Into<String>) -> RichText {
+        let text = RichText::new(text).family(fonts::body());
+
+        match self.theme.text_size {
+            Some(size) => text.size(size * self.theme.zoom),
+            None => text,
+        }
+    }
+
+    fn heading(&self, text: &str, size: f32) -> RichText {
+        self.text(text)
+            .family(fonts::heading())
+            .size(size * self.theme.zoom)
+    }
+}
+
+impl Widget for GemtextView<'_> {
+    fn ui(mut self, ui: &mut Ui) -> Response {
+        ui.vertical(|ui| {
+            let top = ui.min_rect().top();
+
+            if let Some(content_width) = self.theme.content_width {
+                ui.set_max_width(content_width);
+            }
+
+            if let Some(link_color) = self.theme.link_color {
+                ui.visuals_mut().hyperlink_color = link_color;
+            }
+
+            let zoom = self.theme.zoom;
+
+            for font in ui.style_mut().text_styles.values_mut() {
+                font.size *= zoom;
+            }
+
+            if let Some(spacing) = self.theme.paragraph_spacing {
+                ui.spacing_mut().item_spacing.y = spacing * zoom;
+            }
+
+            for (index, block) in self.nodes.iter().enumerate() {
+                if let Some(plugins) = self.plugins {
+                    if plugins.render_node(ui, block) {
+                        continue;
+                    }
+                }
+
+                let response = match block {
+                    GemtextNode::Text(text) if self.theme.justify || rtl::is_rtl(text) => {
+                        self.word_label(ui, text, self.body_font(ui), self.theme.justify)
+                    }
+                    GemtextNode::Text(text) => self.label(ui, self.text(text)),
+                    GemtextNode::Link(url, label) => {
+                        let target = self
+                            .base_url
+                            .and_then(|base| nav::resolve(base, url))
+                            .unwrap_or_else(|| url.clone());
+
+                        let link = ui.link(self.sized(label.as_ref().unwrap_or(url)));
+                        let link = match &mut self.link_tooltip {
+                            Some(tooltip) => link.on_hover_ui_at_pointer(|ui| tooltip(ui, &target)),
+                            None => link.on_hover_text_at_pointer(target),
+                        };
+
+                        if link.clicked() {
+                            if let Some(on_link) = &mut self.on_link {
+                                on_link(url);
+                            }
+                        }
+
+                        link
+                    }
+                    GemtextNode::Heading(text) if rtl::is_rtl(text) => {
+                        let font_id = self.heading_font(self.theme.heading_size);
+                        self.word_label(ui, text, font_id, false)
+                    }
+                    GemtextNode::SubHeading(text) if rtl::is_rtl(text) => {
+                        let font_id = self.heading_font(self.theme.sub_heading_size);
+                        self.word_label(ui, text, font_id, false)
+                    }
+                    GemtextNode::SubSubHeading(text) if rtl::is_rtl(text) => {
+                        let font_id = self.heading_font(self.theme.sub_sub_heading_size);
+                        self.word_label(ui, text, font_id, false)
+                    }
+                    GemtextNode::ListItem(text) if rtl::is_rtl(text) => {
+                        self.word_label(ui, &format!("• {text}"), self.body_font(ui), false)
+                    }
+                    GemtextNode::Heading(text) => {
+                        self.label(ui, self.heading(text, self.theme.heading_size))
+                    }
+                    GemtextNode::SubHeading(text) => {
+                        self.label(ui, self.heading(text, self.theme.sub_heading_size))
+                    }
+                    GemtextNode::SubSubHeading(text) => {
+                        self.label(ui, self.heading(text, self.theme.sub_sub_heading_size))
+                    }
+                    GemtextNode::ListItem(text) => self.label(ui, self.text(format!("  • {text}"))),
+                    GemtextNode::Blockquote(text) => {
+                        let frame = Frame {
+                            outer_margin: Margin {
+                                left: self.theme.quote_indent * zoom,
+                                ..Margin::default()
+                            },
+                            ..Frame::default()
+                        };
+
+                        frame
+                            .show(ui, |ui| {
+                                if rtl::is_rtl(text) {
+                                    self.word_label(ui, text, self.body_font(ui), false)
+                                } else {
+                                    self.label(ui, self.text(text))
+                                }
+                            })
+                            .inner
+                    }
+                    GemtextNode::Preformatted(text, _) => {
+                        self.label(ui, RichText::new(text).code())
+                    }
+                    GemtextNode::EmptyLine => {
+                        ui.add_space(self.theme.empty_line_height * zoom);
+                        continue;
+                    }
+                };
+
+                // after the widget's own description, so this one is what's said
+                if let GemtextNode::Link(url, label) = block {
+                    response.widget_info(|| self.describe_link(url, label.as_deref()));
+                }
+
+                if let Some(headings) = &mut self.headings {
+                    if matches!(
+                        block,
+                        GemtextNode::Heading(_)
+                            | GemtextNode::SubHeading(_)
+                            | GemtextNode::SubSubHeading(_)
+                    ) {
+                        headings.push((index, response.rect.top() - top));
+                    }
+                }
+
+                let highlighted = self.highlight == Some(index);
+
+                // a ring around whatever has keyboard focus, kept in view
+                if response.has_focus() || highlighted {
+                    ui.painter().rect_stroke(
+                        response.rect.expand(2.0),
+                        2.0,
+                        ui.visuals().selection.stroke,
+                    );
+                }
+
+                if response.gained_focus() || highlighted {
+                    response.scroll_to_me(None);
+                }
+
+                if let Some(context_menu) = &mut self.context_menu {
+                    response.context_menu(|ui| context_menu(ui, block));
+                }
+            }
+        })
+        .response
+    }
+}
@@ -0,0 +1,148 @@
+//! Watching a capsule's directory while it's being written, so the page
+//! being looked at reloads when it changes. Files are checked every so
+//! often instead of using the system's file notifications, which would
+//! need a different crate on each platform.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crate::input;
+
+pub const INTERVAL: Duration = Duration::from_millis(500);
+/// Stop looking past this many files, in case a huge directory is opened.
+const MAX_FILES: usize = 5000;
+
+pub struct Watcher {
+    dir: PathBuf,
+    /// The url `dir` is at, ending with `/`.
+    url: String,
+    rx: Receiver<Vec<PathBuf>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Watcher {
+    pub fn new(dir: &Path, url: &str) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let root = dir.to_path_buf();
+        let stopped = stop.clone();
+
+        thread::spawn(move || {
+            let mut last = scan(&root);
+
+            while !stopped.load(Ordering::Relaxed) {
+                thread::sleep(INTERVAL);
+
+                let now = scan(&root);
+                let mut changed: Vec<PathBuf> = now
+                    .iter()
+                    .filter(|(path, modified)| last.get(*path) != Some(modified))
+                    .map(|(path, _)| path.clone())
+                    .collect();
+
+                changed.extend(last.keys().filter(|path| !now.contains_key(*path)).cloned());
+
+                if !changed.is_empty() && tx.send(changed).is_err() {
+                    return;
+                }
+
+                last = now;
+            }
+        });
+
+        Self {
+            dir: dir.to_path_buf(),
+            url: format!("{}/", url.trim_end_matches('/')),
+            rx,
+            stop,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The urls of the files that changed since last time.
+    pub fn changed(&self) -> Vec<String> {
+        self.rx
+            .try_iter()
+            .flatten()
+            .filter_map(|path| self.url_of(&path))
+            .collect()
+    }
+
+    fn url_of(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.dir).ok()?;
+        let segments: Vec<String> = relative
+            .iter()
+            .map(|segment| input::encode(&segment.to_string_lossy()))
+            .collect();
+
+        Some(format!("{}{}", self.url, segments.join("/")))
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Whether a change to the file at `changed` shows on the page at `url`.
+/// A directory's page is its `index.gmi`, or else the list of what's in it.
+pub fn affects(url: &str, changed: &str) -> bool {
+    let url = input::without_query(url);
+
+    if !url.ends_with('/') {
+        return url == changed;
+    }
+
+    changed
+        .strip_prefix(url)
+        .is_some_and(|rest| !rest.contains('/'))
+}
+
+/// When every file under `dir` was last changed. Hidden files are skipped.
+fn scan(dir: &Path) -> HashMap<PathBuf, SystemTime> {
+    let mut files = HashMap::new();
+    let mut dirs = vec![dir.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            if files.len() >= MAX_FILES {
+                return files;
+            }
+
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            } else if let Ok(modified) = metadata.modified() {
+                files.insert(entry.path(), modified);
+            }
+        }
+    }
+
+    files
+}
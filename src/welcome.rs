@@ -0,0 +1,51 @@
+//! The page opened the first time gbrowse starts, at `about:welcome`. It's
+//! plain gemtext shown like any other page, so it's also a quick check
+//! that every kind of line renders.
+//!
+//! Its links go to `about:welcome/...` actions, which change the settings
+//! instead of loading a page.
+
+use gmi::gemtext;
+
+use crate::{config::Theme, fetch::Page, input};
+
+pub const URL: &str = "about:welcome";
+
+const WELCOME: &str = include_str!("welcome.gmi");
+
+pub fn page() -> Page {
+    Page {
+        url: URL.to_string(),
+        redirects: vec![],
+        nodes: gemtext::parse_gemtext(WELCOME),
+        ..Default::default()
+    }
+}
+
+/// Something a link on the welcome page asks for.
+pub enum Action {
+    Theme(Theme),
+    /// Ask for a home page, or set it once it's been typed.
+    Home(Option<String>),
+    /// Ask for a file of links, or import it once it's been typed.
+    Import(Option<String>),
+}
+
+impl Action {
+    pub fn parse(url: &str) -> Option<Self> {
+        let action = url.strip_prefix(URL)?.strip_prefix('/')?;
+        let (action, query) = match action.split_once('?') {
+            Some((action, query)) => (action, Some(input::decode(query))),
+            None => (action, None),
+        };
+
+        match action {
+            "theme/system" => Some(Self::Theme(Theme::System)),
+            "theme/dark" => Some(Self::Theme(Theme::Dark)),
+            "theme/light" => Some(Self::Theme(Theme::Light)),
+            "home" => Some(Self::Home(query)),
+            "import" => Some(Self::Import(query)),
+            _ => None,
+        }
+    }
+}
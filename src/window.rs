@@ -0,0 +1,93 @@
+//! Where the window was and how big it was, so it opens the same way next
+//! time. eframe 0.19 can't tell whether the window is maximized, so that's
+//! only restored if `maximized` is set in `window.ron` by hand.
+
+use eframe::{egui, NativeOptions, WindowInfo};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+const FILE: &str = "window.ron";
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Geometry {
+    /// The top left corner, in physical pixels.
+    pub position: Option<(f32, f32)>,
+    /// The inner size, in logical pixels.
+    pub size: Option<(f32, f32)>,
+    pub fullscreen: bool,
+    /// Never written by gbrowse, only kept, since there's no way to tell
+    /// whether the window is maximized.
+    pub maximized: bool,
+}
+
+impl Geometry {
+    pub fn load() -> Self {
+        config::read_file(FILE)
+    }
+
+    pub fn save(&self) {
+        config::write_file(FILE, self);
+    }
+
+    /// Read the window's geometry, keeping `maximized` as it was. eframe
+    /// gives it in egui points, which `pixels_per_point` turns back into
+    /// pixels, and `native_pixels_per_point` from pixels into the logical
+    /// pixels the window's size is set in.
+    pub fn update(
+        &mut self,
+        info: &WindowInfo,
+        pixels_per_point: f32,
+        native_pixels_per_point: f32,
+    ) {
+        *self = Self {
+            position: info
+                .position
+                .map(|pos| (pos.x * pixels_per_point, pos.y * pixels_per_point)),
+            size: Some((
+                info.size.x * pixels_per_point / native_pixels_per_point,
+                info.size.y * pixels_per_point / native_pixels_per_point,
+            )),
+            fullscreen: info.fullscreen,
+            maximized: self.maximized,
+        };
+    }
+
+    /// Open the window where it was.
+    pub fn apply(&self, options: &mut NativeOptions) {
+        options.initial_window_pos = self.position.map(|(x, y)| egui::pos2(x, y));
+        options.initial_window_size = self.size.map(|(x, y)| egui::vec2(x, y));
+        options.fullscreen = self.fullscreen;
+        options.maximized = self.maximized;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_maximized_and_converts_to_pixels() {
+        let mut geometry = Geometry {
+            maximized: true,
+            ..Geometry::default()
+        };
+        let info = WindowInfo {
+            position: Some(egui::pos2(10.0, 20.0)),
+            fullscreen: false,
+            size: egui::vec2(400.0, 300.0),
+        };
+
+        geometry.update(&info, 2.0, 1.0);
+
+        assert_eq!(geometry.position, Some((20.0, 40.0)));
+        assert_eq!(geometry.size, Some((800.0, 600.0)));
+        assert!(geometry.maximized);
+
+        let mut options = NativeOptions::default();
+        geometry.apply(&mut options);
+
+        assert!(options.maximized);
+    }
+}